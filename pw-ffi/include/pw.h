@@ -0,0 +1,121 @@
+#ifndef PW_H
+#define PW_H
+
+/* Generated by cbindgen from pw-ffi/src/lib.rs - do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of every fallible call.
+ */
+typedef enum PwStatus {
+  PW_STATUS_OK = 0,
+  /**
+   * A required pointer argument was NULL.
+   */
+  PW_STATUS_NULL_ARGUMENT,
+  /**
+   * A string argument was not valid UTF-8.
+   */
+  PW_STATUS_INVALID_UTF8,
+  /**
+   * A stored value contains a NUL byte and cannot be returned as a C string.
+   */
+  PW_STATUS_INTERIOR_NUL,
+  PW_STATUS_FILE_NOT_FOUND,
+  PW_STATUS_WRONG_PASSPHRASE,
+  PW_STATUS_NOT_FOUND,
+  PW_STATUS_INVALID_INPUT,
+  PW_STATUS_IO,
+  PW_STATUS_CORRUPT_VAULT,
+} PwStatus;
+
+/**
+ * An opened vault: its path and the passphrase that unlocked it.
+ */
+typedef struct PwVault PwVault;
+
+/**
+ * A list of strings, such as entry names.
+ */
+typedef struct PwStrings {
+  char **items;
+  size_t len;
+} PwStrings;
+
+/**
+ * A password entry. `url` is NULL when the entry has none.
+ */
+typedef struct PwEntry {
+  char *name;
+  char *username;
+  char *password;
+  char *url;
+} PwEntry;
+
+/**
+ * A static, human-readable description of `status`.
+ */
+const char *pw_status_message(PwStatus status);
+
+/**
+ * Open the vault at `path`, checking `passphrase` by decrypting it.
+ *
+ * # Safety
+ *
+ * `path` and `passphrase` must be NULL or valid NUL-terminated strings, and
+ * `out` NULL or valid for writes.
+ */
+PwStatus pw_vault_open(const char *path, const char *passphrase, PwVault **out);
+
+/**
+ * Close a vault returned by [`pw_vault_open`], zeroing the passphrase.
+ *
+ * # Safety
+ *
+ * `vault` must be NULL or a pointer from [`pw_vault_open`] not yet freed.
+ */
+void pw_vault_free(PwVault *vault);
+
+/**
+ * The names of all entries, in vault order.
+ *
+ * # Safety
+ *
+ * `vault` must be NULL or an open vault, and `out` NULL or valid for writes.
+ */
+PwStatus pw_vault_list_names(const PwVault *vault, PwStrings *out);
+
+/**
+ * Release a list returned by [`pw_vault_list_names`].
+ *
+ * # Safety
+ *
+ * `strings` must be NULL or point to a list from this library not yet freed.
+ */
+void pw_strings_free(PwStrings *strings);
+
+/**
+ * The entry named `name`.
+ *
+ * # Safety
+ *
+ * `vault` must be NULL or an open vault, `name` NULL or a valid
+ * NUL-terminated string, and `out` NULL or valid for writes.
+ */
+PwStatus pw_vault_get(const PwVault *vault, const char *name, PwEntry *out);
+
+/**
+ * Release an entry returned by [`pw_vault_get`], zeroing its password.
+ *
+ * # Safety
+ *
+ * `entry` must be NULL or point to an entry from this library not yet freed.
+ */
+void pw_entry_free(PwEntry *entry);
+
+#endif  /* PW_H */
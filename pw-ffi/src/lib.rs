@@ -0,0 +1,379 @@
+//! C bindings for the pw library, for wrappers in other languages (Python,
+//! Swift, ...). The C declarations are in `include/pw.h`.
+//!
+//! Conventions:
+//!
+//! - Every fallible function returns a [`PwStatus`] and writes its result
+//!   through an out pointer, which is left untouched on failure.
+//! - Strings are NUL-terminated UTF-8, borrowed for the duration of the call.
+//! - Everything returned is owned by the caller and released with the
+//!   matching `pw_*_free` function, never with `free()`. Passwords are
+//!   zeroed when freed.
+//! - A [`PwVault`] is read-only and holds no decrypted entries: each call
+//!   reads and decrypts the vault file again.
+
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use pw::{EntryName, Passphrase, PwError};
+use zeroize::Zeroize;
+
+/// Result of every fallible call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwStatus {
+    Ok = 0,
+    /// A required pointer argument was NULL.
+    NullArgument,
+    /// A string argument was not valid UTF-8.
+    InvalidUtf8,
+    /// A stored value contains a NUL byte and cannot be returned as a C string.
+    InteriorNul,
+    FileNotFound,
+    WrongPassphrase,
+    NotFound,
+    InvalidInput,
+    Io,
+    CorruptVault,
+}
+
+impl From<PwError> for PwStatus {
+    fn from(err: PwError) -> Self {
+        match err {
+            PwError::FileNotFound(_) => PwStatus::FileNotFound,
+            PwError::WrongPassphrase => PwStatus::WrongPassphrase,
+            PwError::NotFound { .. } => PwStatus::NotFound,
+            PwError::FileAlreadyExists(_)
+            | PwError::AlreadyExists { .. }
+            | PwError::InvalidInput { .. } => PwStatus::InvalidInput,
+            PwError::Io(_) => PwStatus::Io,
+            PwError::CorruptVault { .. } => PwStatus::CorruptVault,
+        }
+    }
+}
+
+/// An opened vault: its path and the passphrase that unlocked it.
+pub struct PwVault {
+    file: PathBuf,
+    passphrase: Passphrase,
+}
+
+/// A list of strings, such as entry names.
+#[repr(C)]
+pub struct PwStrings {
+    pub items: *mut *mut c_char,
+    pub len: usize,
+}
+
+/// A password entry. `url` is NULL when the entry has none.
+#[repr(C)]
+pub struct PwEntry {
+    pub name: *mut c_char,
+    pub username: *mut c_char,
+    pub password: *mut c_char,
+    pub url: *mut c_char,
+}
+
+/// A static, human-readable description of `status`.
+#[no_mangle]
+pub extern "C" fn pw_status_message(status: PwStatus) -> *const c_char {
+    let message: &'static CStr = match status {
+        PwStatus::Ok => c"ok",
+        PwStatus::NullArgument => c"a required argument was NULL",
+        PwStatus::InvalidUtf8 => c"a string argument was not valid UTF-8",
+        PwStatus::InteriorNul => c"a stored value contains a NUL byte",
+        PwStatus::FileNotFound => c"no vault at this path",
+        PwStatus::WrongPassphrase => c"incorrect passphrase",
+        PwStatus::NotFound => c"no such entry",
+        PwStatus::InvalidInput => c"invalid input",
+        PwStatus::Io => c"cannot read the vault",
+        PwStatus::CorruptVault => c"cannot use the vault",
+    };
+    message.as_ptr()
+}
+
+/// Open the vault at `path`, checking `passphrase` by decrypting it.
+///
+/// # Safety
+///
+/// `path` and `passphrase` must be NULL or valid NUL-terminated strings, and
+/// `out` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pw_vault_open(
+    path: *const c_char,
+    passphrase: *const c_char,
+    out: *mut *mut PwVault,
+) -> PwStatus {
+    if out.is_null() {
+        return PwStatus::NullArgument;
+    }
+    let (file, passphrase) = match (str_arg(path), str_arg(passphrase)) {
+        (Ok(file), Ok(passphrase)) => (PathBuf::from(file), passphrase.to_string()),
+        (Err(status), _) | (_, Err(status)) => return status,
+    };
+    let vault = PwVault {
+        file,
+        passphrase: Passphrase::new(passphrase),
+    };
+    if let Err(err) = pw::list(&vault.file, &vault.passphrase) {
+        return err.into();
+    }
+    *out = Box::into_raw(Box::new(vault));
+    PwStatus::Ok
+}
+
+/// Close a vault returned by [`pw_vault_open`], zeroing the passphrase.
+///
+/// # Safety
+///
+/// `vault` must be NULL or a pointer from [`pw_vault_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn pw_vault_free(vault: *mut PwVault) {
+    if !vault.is_null() {
+        drop(Box::from_raw(vault));
+    }
+}
+
+/// The names of all entries, in vault order.
+///
+/// # Safety
+///
+/// `vault` must be NULL or an open vault, and `out` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pw_vault_list_names(
+    vault: *const PwVault,
+    out: *mut PwStrings,
+) -> PwStatus {
+    let (Some(vault), false) = (vault.as_ref(), out.is_null()) else {
+        return PwStatus::NullArgument;
+    };
+    let entries = match pw::list(&vault.file, &vault.passphrase) {
+        Ok(entries) => entries,
+        Err(err) => return err.into(),
+    };
+    let names: Result<Vec<_>, _> = entries.iter().map(|e| c_string(&e.name)).collect();
+    let mut names = match names {
+        Ok(names) => names
+            .into_iter()
+            .map(CString::into_raw)
+            .collect::<Box<[_]>>(),
+        Err(status) => return status,
+    };
+    let len = names.len();
+    let items = names.as_mut_ptr();
+    std::mem::forget(names);
+    *out = PwStrings { items, len };
+    PwStatus::Ok
+}
+
+/// Release a list returned by [`pw_vault_list_names`].
+///
+/// # Safety
+///
+/// `strings` must be NULL or point to a list from this library not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn pw_strings_free(strings: *mut PwStrings) {
+    let Some(strings) = strings.as_mut() else {
+        return;
+    };
+    if !strings.items.is_null() {
+        let items = Box::from_raw(ptr::slice_from_raw_parts_mut(strings.items, strings.len));
+        for &item in items.iter() {
+            drop(CString::from_raw(item));
+        }
+    }
+    strings.items = ptr::null_mut();
+    strings.len = 0;
+}
+
+/// The entry named `name`.
+///
+/// # Safety
+///
+/// `vault` must be NULL or an open vault, `name` NULL or a valid
+/// NUL-terminated string, and `out` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pw_vault_get(
+    vault: *const PwVault,
+    name: *const c_char,
+    out: *mut PwEntry,
+) -> PwStatus {
+    let (Some(vault), false) = (vault.as_ref(), out.is_null()) else {
+        return PwStatus::NullArgument;
+    };
+    let name = match str_arg(name) {
+        Ok(name) => name,
+        Err(status) => return status,
+    };
+    let entry = match EntryName::new(name)
+        .and_then(|name| pw::get(&vault.file, &vault.passphrase, &name))
+    {
+        Ok(entry) => entry,
+        Err(err) => return err.into(),
+    };
+    let strings = (
+        c_string(&entry.name),
+        c_string(&entry.username),
+        c_string(entry.password.expose()),
+        entry.url.as_deref().map(c_string).transpose(),
+    );
+    match strings {
+        (Ok(name), Ok(username), Ok(password), Ok(url)) => {
+            *out = PwEntry {
+                name: name.into_raw(),
+                username: username.into_raw(),
+                password: password.into_raw(),
+                url: url.map_or(ptr::null_mut(), CString::into_raw),
+            };
+            PwStatus::Ok
+        }
+        (name, username, password, url) => {
+            if let Ok(password) = password {
+                password.into_bytes().zeroize();
+            }
+            let statuses = [name.err(), username.err(), url.err()];
+            statuses
+                .into_iter()
+                .flatten()
+                .next()
+                .unwrap_or(PwStatus::InteriorNul)
+        }
+    }
+}
+
+/// Release an entry returned by [`pw_vault_get`], zeroing its password.
+///
+/// # Safety
+///
+/// `entry` must be NULL or point to an entry from this library not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn pw_entry_free(entry: *mut PwEntry) {
+    let Some(entry) = entry.as_mut() else {
+        return;
+    };
+    for field in [&mut entry.name, &mut entry.username, &mut entry.url] {
+        if !field.is_null() {
+            drop(CString::from_raw(*field));
+        }
+        *field = ptr::null_mut();
+    }
+    if !entry.password.is_null() {
+        CString::from_raw(entry.password).into_bytes().zeroize();
+        entry.password = ptr::null_mut();
+    }
+}
+
+/// Borrow a C string argument as UTF-8.
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, PwStatus> {
+    if s.is_null() {
+        return Err(PwStatus::NullArgument);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| PwStatus::InvalidUtf8)
+}
+
+fn c_string(s: &str) -> Result<CString, PwStatus> {
+    CString::new(s).map_err(|_| PwStatus::InteriorNul)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_with_entry() -> (tempfile::TempDir, CString) {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pw.scrypt");
+        let passphrase = Passphrase::new("secret".to_string());
+        let params = pw::Params {
+            log_n: 12,
+            ..Default::default()
+        };
+        pw::init(&file, &passphrase, &params).unwrap();
+        let entry = pw::PasswordEntry {
+            name: "github.com".to_string(),
+            username: "alice".to_string(),
+            password: "hunter2".into(),
+            url: Some("https://github.com".to_string()),
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        };
+        pw::add(&file, &passphrase, entry, &params).unwrap();
+        let path = CString::new(file.to_str().unwrap()).unwrap();
+        (dir, path)
+    }
+
+    unsafe fn string(s: *const c_char) -> &'static str {
+        CStr::from_ptr(s).to_str().unwrap()
+    }
+
+    #[test]
+    fn opens_lists_and_gets() {
+        let (_dir, path) = vault_with_entry();
+        unsafe {
+            let mut vault = ptr::null_mut();
+            assert_eq!(
+                pw_vault_open(path.as_ptr(), c"wrong".as_ptr(), &mut vault),
+                PwStatus::WrongPassphrase
+            );
+            assert!(vault.is_null());
+            assert_eq!(
+                pw_vault_open(path.as_ptr(), c"secret".as_ptr(), &mut vault),
+                PwStatus::Ok
+            );
+
+            let mut names = PwStrings {
+                items: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(pw_vault_list_names(vault, &mut names), PwStatus::Ok);
+            assert_eq!(names.len, 1);
+            assert_eq!(string(*names.items), "github.com");
+            pw_strings_free(&mut names);
+            assert!(names.items.is_null());
+
+            let mut entry = PwEntry {
+                name: ptr::null_mut(),
+                username: ptr::null_mut(),
+                password: ptr::null_mut(),
+                url: ptr::null_mut(),
+            };
+            assert_eq!(
+                pw_vault_get(vault, c"github.com".as_ptr(), &mut entry),
+                PwStatus::Ok
+            );
+            assert_eq!(string(entry.username), "alice");
+            assert_eq!(string(entry.password), "hunter2");
+            assert_eq!(string(entry.url), "https://github.com");
+            pw_entry_free(&mut entry);
+            assert!(entry.password.is_null());
+
+            assert_eq!(
+                pw_vault_get(vault, c"gitlab.com".as_ptr(), &mut entry),
+                PwStatus::NotFound
+            );
+            assert_eq!(
+                pw_vault_get(vault, ptr::null(), &mut entry),
+                PwStatus::NullArgument
+            );
+            pw_vault_free(vault);
+        }
+    }
+
+    #[test]
+    fn status_messages() {
+        let message = unsafe { string(pw_status_message(PwStatus::WrongPassphrase)) };
+        assert_eq!(message, "incorrect passphrase");
+    }
+}
@@ -0,0 +1,838 @@
+//! The agent of `pw agent`: a process that holds unlocked vaults, so that
+//! commands need neither the passphrase nor the scrypt key derivation.
+//!
+//! It listens on a Unix socket, [`socket_path`], in a directory private to
+//! the user, and answers only connections from processes running as the
+//! same user. Each connection carries one [`Request`] and one [`Response`],
+//! each a line of JSON. For every vault unlocked with `pw unlock` the agent
+//! holds the passphrase (combined with any key file, as the vault needs it)
+//! and the keys derived from it for the current header of the vault
+//! ([`DerivedKey`]). It never hands out the passphrase, only keys: those for
+//! the current header, to read the vault, and those for the new salt of a
+//! write, which it then holds as the current ones. For a vault written
+//! elsewhere it derives the keys afresh once. The response of a YubiKey
+//! changes with the salt, so for such a vault it holds the passphrase alone,
+//! and a write hands it the response to the new salt. Both are kept in
+//! memory locked against swapping, as far as `RLIMIT_MEMLOCK` allows, and
+//! zeroed when forgotten. The agent exits, forgetting everything, when no
+//! request has come for its idle timeout, or when `pw lock` leaves it
+//! holding no vault.
+//!
+//! Every process running as the user can ask the agent, so while a vault is
+//! unlocked any of them can read and write it without a prompt, as it could
+//! read an unlocked keychain; but none can take the passphrase away, and the
+//! keys it hands out open only the vault as it is, not one written later
+//! with another salt. Keys for weaker scrypt parameters than the vault's are
+//! refused, so that they cannot serve to guess the passphrase faster. Lock
+//! the vault, or do without the agent, where even that is not acceptable.
+//!
+//! Compare [`crate::keyring`], which caches only the passphrase, so that
+//! every command still runs scrypt.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::paths::Paths;
+use crate::scrypt_format::{self, DerivedKey, Params, HEADER_LEN, SALT_LEN};
+use crate::{decode_hex, Passphrase};
+
+/// The name of the socket in pw's runtime directory.
+pub const SOCKET_NAME: &str = "agent.sock";
+/// The longest request or response line read.
+const MAX_LINE_LEN: u64 = 64 * 1024;
+/// How long the agent waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client waits for the response, which may need scrypt.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where the agent listens: in the runtime directory, or the cache directory
+/// where there is none.
+pub fn socket_path(paths: &Paths) -> PathBuf {
+    paths
+        .runtime
+        .as_ref()
+        .unwrap_or(&paths.cache)
+        .join(SOCKET_NAME)
+}
+
+/// What a client asks the agent. Vaults are named by their absolute path;
+/// headers and keys are in hex.
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    /// Hold `passphrase` for the vault `file`, and `key`, the keys derived
+    /// from it for `header`.
+    Unlock {
+        #[zeroize(skip)]
+        file: PathBuf,
+        passphrase: String,
+        header: String,
+        key: String,
+    },
+    /// The keys of the vault `file` for `header`.
+    Get {
+        #[zeroize(skip)]
+        file: PathBuf,
+        header: String,
+    },
+    /// Keys of the vault `file` for a new header with the scrypt parameters
+    /// `log_n`, `r` and `p` and `salt`, which the agent holds from then on;
+    /// `response` is a YubiKey's to the salt, for a vault that needs one.
+    Derive {
+        #[zeroize(skip)]
+        file: PathBuf,
+        log_n: u8,
+        r: u32,
+        p: u32,
+        salt: String,
+        response: Option<String>,
+    },
+    /// Forget the vault `file`.
+    Lock {
+        #[zeroize(skip)]
+        file: PathBuf,
+    },
+}
+
+/// What the agent answers.
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    /// Unlocked, or forgotten.
+    Done,
+    /// The keys asked for.
+    Unlocked { key: String },
+    /// The keys asked for a new header, and the header.
+    Derived { header: String, key: String },
+    /// The agent does not hold the vault, or its passphrase no longer opens
+    /// it.
+    Locked,
+    /// The request could not be answered.
+    Failed { message: String },
+}
+
+/// Secret bytes, locked in memory against swapping as far as the system
+/// allows, and zeroed and unlocked when dropped.
+struct Locked(Box<[u8]>);
+
+impl Locked {
+    fn new(secret: &[u8]) -> Self {
+        let locked = Locked(secret.into());
+        #[cfg(unix)]
+        // SAFETY: the range is the buffer just allocated. A failure, such as
+        // over RLIMIT_MEMLOCK, leaves it swappable like any other memory.
+        unsafe {
+            libc::mlock(locked.0.as_ptr().cast(), locked.0.len());
+        }
+        locked
+    }
+}
+
+impl Drop for Locked {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        #[cfg(unix)]
+        // SAFETY: the range is the buffer locked in `new`, still allocated.
+        // Locks do not nest, so a page shared with another `Locked` becomes
+        // swappable with it too: the lesser harm than leaving freed pages
+        // locked for as long as the agent runs.
+        unsafe {
+            libc::munlock(self.0.as_ptr().cast(), self.0.len());
+        }
+    }
+}
+
+/// A vault the agent holds.
+struct Held {
+    passphrase: Locked,
+    /// The header the keys are for; not secret.
+    header: Vec<u8>,
+    key: Locked,
+}
+
+/// The vaults an agent holds, and its answers to requests.
+#[derive(Default)]
+pub struct Agent {
+    vaults: HashMap<PathBuf, Held>,
+}
+
+impl Agent {
+    /// Whether the agent holds no vault.
+    pub fn is_empty(&self) -> bool {
+        self.vaults.is_empty()
+    }
+
+    /// Answer one request.
+    pub fn handle(&mut self, request: &Request) -> Response {
+        let failed = |message: &str| Response::Failed {
+            message: message.to_string(),
+        };
+        match request {
+            Request::Unlock {
+                file,
+                passphrase,
+                header,
+                key,
+            } => {
+                let (Some(header), Some(key)) = (decode_hex(header), decode_hex(key)) else {
+                    return failed("header and key must be hex");
+                };
+                let key = Zeroizing::new(key);
+                if DerivedKey::from_parts(&header, &key).is_err() {
+                    return failed("the key is not for this header");
+                }
+                self.vaults.insert(
+                    file.clone(),
+                    Held {
+                        passphrase: Locked::new(passphrase.as_bytes()),
+                        header,
+                        key: Locked::new(&key),
+                    },
+                );
+                Response::Done
+            }
+            Request::Get { file, header } => {
+                let Some(header) = decode_hex(header) else {
+                    return failed("the header must be hex");
+                };
+                let Some(held) = self.vaults.get_mut(file) else {
+                    return Response::Locked;
+                };
+                if held.header != header {
+                    // Written since: derive the keys for the new salt.
+                    match DerivedKey::derive(&header, &held.passphrase.0) {
+                        Ok(key) => {
+                            held.header = header;
+                            held.key = Locked::new(key.key());
+                        }
+                        Err(scrypt_format::Error::WrongPassphrase) => {
+                            self.vaults.remove(file);
+                            return Response::Locked;
+                        }
+                        Err(e) => return failed(&e.to_string()),
+                    }
+                }
+                Response::Unlocked {
+                    key: hex(&held.key.0),
+                }
+            }
+            Request::Derive {
+                file,
+                log_n,
+                r,
+                p,
+                salt,
+                response,
+            } => {
+                let params = Params {
+                    log_n: *log_n,
+                    r: *r,
+                    p: *p,
+                };
+                let Some(salt) =
+                    decode_hex(salt).and_then(|salt| <[u8; SALT_LEN]>::try_from(salt).ok())
+                else {
+                    return failed("the salt must be 32 bytes of hex");
+                };
+                let response = match response {
+                    Some(response) => match decode_hex(response) {
+                        Some(response) => Some(Zeroizing::new(response)),
+                        None => return failed("the response must be hex"),
+                    },
+                    None => None,
+                };
+                let Some(held) = self.vaults.get_mut(file) else {
+                    return Response::Locked;
+                };
+                // Keys for cheaper parameters would let a client guess the
+                // passphrase at that cost.
+                match DerivedKey::from_parts(&held.header, &held.key.0) {
+                    Ok(current) if params.cost() >= current.params().cost() => {}
+                    Ok(_) => return failed("weaker scrypt parameters than the vault's"),
+                    Err(e) => return failed(&e.to_string()),
+                }
+                let passphrase =
+                    Passphrase::new(String::from_utf8_lossy(&held.passphrase.0).into_owned());
+                let passphrase = match &response {
+                    Some(response) => passphrase.with_key_file(response),
+                    None => passphrase,
+                };
+                match DerivedKey::new(passphrase.as_bytes(), &params, &salt) {
+                    Ok(key) => {
+                        held.header = key.header().to_vec();
+                        held.key = Locked::new(key.key());
+                        Response::Derived {
+                            header: hex(&held.header),
+                            key: hex(&held.key.0),
+                        }
+                    }
+                    Err(e) => failed(&e.to_string()),
+                }
+            }
+            Request::Lock { file } => match self.vaults.remove(file) {
+                Some(_) => Response::Done,
+                None => Response::Locked,
+            },
+        }
+    }
+}
+
+/// Listen at `socket` and answer requests until none has come for `idle`, or
+/// a [`Request::Lock`] leaves the agent holding no vault; then remove the
+/// socket. The directory of the socket is created private to the user, and
+/// must be so if it already exists. Connections from other users are closed
+/// unanswered. A socket left behind by an agent that has exited is replaced;
+/// one in use is an error.
+#[cfg(unix)]
+pub fn serve(socket: &Path, idle: Duration) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+
+    if let Some(dir) = socket.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        check_private(dir)?;
+    }
+    if running(socket) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("an agent is already listening at {}", socket.display()),
+        ));
+    }
+    match fs::remove_file(socket) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+
+    let mut agent = Agent::default();
+    let mut deadline = Instant::now() + idle;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        let mut fd = libc::pollfd {
+            fd: listener.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = libc::c_int::try_from(left.as_millis()).unwrap_or(libc::c_int::MAX);
+        // SAFETY: one valid pollfd, alive for the duration of the call.
+        if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 {
+            // Timed out, or interrupted: check the deadline again.
+            continue;
+        }
+        let Ok((mut stream, _)) = listener.accept() else {
+            continue;
+        };
+        // SAFETY: geteuid cannot fail.
+        if peer_uid(&stream).ok() != Some(unsafe { libc::geteuid() }) {
+            continue;
+        }
+        deadline = Instant::now() + idle;
+        let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+        let Ok(line) = read_line(&mut stream) else {
+            continue;
+        };
+        let (response, lock) = match serde_json::from_slice::<Request>(&line) {
+            Ok(request) => (
+                agent.handle(&request),
+                matches!(request, Request::Lock { .. }),
+            ),
+            Err(e) => (
+                Response::Failed {
+                    message: format!("invalid request: {e}"),
+                },
+                false,
+            ),
+        };
+        let _ = write_line(&mut stream, &response);
+        if lock && agent.is_empty() {
+            break;
+        }
+    }
+    // Remove the socket, unless another agent has replaced it since.
+    drop(listener);
+    if fs::metadata(socket).is_ok() && !running(socket) {
+        let _ = fs::remove_file(socket);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_socket: &Path, _idle: Duration) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// Fail unless `dir` is a directory owned by the user and closed to everyone
+/// else, so that no one else can reach or replace the socket in it.
+#[cfg(unix)]
+fn check_private(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::symlink_metadata(dir)?;
+    // SAFETY: geteuid cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} must be a directory private to the user (mode 0700)",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The user id of the process at the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> io::Result<libc::uid_t> {
+    use std::os::fd::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len are valid for writes of the size given.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> io::Result<libc::uid_t> {
+    use std::os::fd::AsRawFd;
+
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: uid and gid are valid for writes.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Whether an agent is listening at `socket`.
+#[cfg(unix)]
+pub fn running(socket: &Path) -> bool {
+    std::os::unix::net::UnixStream::connect(socket).is_ok()
+}
+
+#[cfg(not(unix))]
+pub fn running(_socket: &Path) -> bool {
+    false
+}
+
+/// Hand the agent at `socket` the vault `file`, with its passphrase and the
+/// keys derived from it, as [`crate::derive_key`] returns them.
+pub fn unlock(
+    socket: &Path,
+    file: &Path,
+    passphrase: &Passphrase,
+    key: &DerivedKey,
+) -> io::Result<()> {
+    let header = read_header(file)?;
+    let response = request(
+        socket,
+        &Request::Unlock {
+            file: absolute(file),
+            passphrase: String::from_utf8_lossy(passphrase.as_bytes()).into_owned(),
+            header: hex(&header),
+            key: hex(key.key()),
+        },
+    )?;
+    match &response {
+        Response::Done => Ok(()),
+        Response::Failed { message } => Err(io::Error::other(message.clone())),
+        _ => Err(unexpected()),
+    }
+}
+
+/// The keys of the vault `file`, from the agent at `socket`, as a
+/// [`Passphrase::delegated`] that asks the agent for the keys of every write;
+/// `None` if no agent is running or it does not hold the vault.
+pub fn get(socket: &Path, file: &Path) -> io::Result<Option<Passphrase>> {
+    let header = match read_header(file) {
+        Ok(header) => header,
+        // No vault, or not one pw can open: the command will say so.
+        Err(_) => return Ok(None),
+    };
+    let response = match request(
+        socket,
+        &Request::Get {
+            file: absolute(file),
+            header: hex(&header),
+        },
+    ) {
+        Err(e) if not_running(&e) => return Ok(None),
+        response => response?,
+    };
+    match &response {
+        Response::Unlocked { key } => {
+            let key = Zeroizing::new(decode_hex(key).ok_or_else(unexpected)?);
+            let key = DerivedKey::from_parts(&header, &key).map_err(|_| unexpected())?;
+            let (socket, file) = (socket.to_path_buf(), file.to_path_buf());
+            Ok(Some(Passphrase::delegated(
+                key,
+                move |params, salt, response| {
+                    derive(&socket, &file, params, salt, response)
+                        .map_err(|e| format!("cannot use the agent: {e}"))
+                },
+            )))
+        }
+        Response::Locked => Ok(None),
+        Response::Failed { message } => Err(io::Error::other(message.clone())),
+        _ => Err(unexpected()),
+    }
+}
+
+/// Keys of the vault `file` for a new header with `params` and `salt`, from
+/// the agent at `socket`, which holds them from then on; `response` is the
+/// YubiKey's to the salt, for a vault that needs one.
+pub fn derive(
+    socket: &Path,
+    file: &Path,
+    params: &Params,
+    salt: &[u8; SALT_LEN],
+    response: Option<&[u8]>,
+) -> io::Result<DerivedKey> {
+    let response = request(
+        socket,
+        &Request::Derive {
+            file: absolute(file),
+            log_n: params.log_n,
+            r: params.r,
+            p: params.p,
+            salt: hex(salt),
+            response: response.map(hex),
+        },
+    )?;
+    match &response {
+        Response::Derived { header, key } => {
+            let header = decode_hex(header).ok_or_else(unexpected)?;
+            let key = Zeroizing::new(decode_hex(key).ok_or_else(unexpected)?);
+            DerivedKey::from_parts(&header, &key).map_err(|_| unexpected())
+        }
+        Response::Locked => Err(io::Error::other(format!(
+            "the agent no longer holds {} - run `pw unlock` again",
+            file.display()
+        ))),
+        Response::Failed { message } => Err(io::Error::other(message.clone())),
+        _ => Err(unexpected()),
+    }
+}
+
+/// Have the agent at `socket` forget the vault `file`. Returns false if no
+/// agent is running or it did not hold the vault.
+pub fn lock(socket: &Path, file: &Path) -> io::Result<bool> {
+    let response = match request(
+        socket,
+        &Request::Lock {
+            file: absolute(file),
+        },
+    ) {
+        Err(e) if not_running(&e) => return Ok(false),
+        response => response?,
+    };
+    match &response {
+        Response::Done => Ok(true),
+        Response::Locked => Ok(false),
+        Response::Failed { message } => Err(io::Error::other(message.clone())),
+        _ => Err(unexpected()),
+    }
+}
+
+#[cfg(unix)]
+fn request(socket: &Path, request: &Request) -> io::Result<Response> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    write_line(&mut stream, request)?;
+    let line = read_line(&mut stream)?;
+    serde_json::from_slice(&line).map_err(|_| unexpected())
+}
+
+#[cfg(not(unix))]
+fn request(_socket: &Path, _request: &Request) -> io::Result<Response> {
+    Err(unsupported())
+}
+
+/// Whether `err`, from connecting, means that no agent is listening.
+fn not_running(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused | io::ErrorKind::Unsupported
+    )
+}
+
+fn read_line(stream: impl Read) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut line = Zeroizing::new(Vec::new());
+    BufReader::new(stream.take(MAX_LINE_LEN)).read_until(b'\n', &mut line)?;
+    Ok(line)
+}
+
+fn write_line(mut stream: impl Write, message: &impl Serialize) -> io::Result<()> {
+    let mut line = Zeroizing::new(serde_json::to_vec(message).map_err(io::Error::other)?);
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// The first [`HEADER_LEN`] bytes of the vault, which the keys are for.
+fn read_header(file: &Path) -> io::Result<Vec<u8>> {
+    let mut header = vec![0; HEADER_LEN];
+    fs::File::open(file)?.read_exact(&mut header)?;
+    Ok(header)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn absolute(file: &Path) -> PathBuf {
+    std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected response from the agent",
+    )
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the agent is only available on Unix",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scrypt_format::Params;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+    const TEST_PARAMS: Params = Params {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+
+    fn vault() -> Vec<u8> {
+        scrypt_format::encrypt(b"[]", PASSPHRASE.as_bytes(), &TEST_PARAMS).unwrap()
+    }
+
+    fn get_keys(agent: &mut Agent, data: &[u8]) -> Response {
+        agent.handle(&Request::Get {
+            file: PathBuf::from("/v"),
+            header: hex(&data[..HEADER_LEN]),
+        })
+    }
+
+    #[test]
+    fn holds_a_vault_until_locked() {
+        let data = vault();
+        let key = DerivedKey::derive(&data, PASSPHRASE.as_bytes()).unwrap();
+        let mut agent = Agent::default();
+        assert!(matches!(get_keys(&mut agent, &data), Response::Locked));
+
+        let unlock = Request::Unlock {
+            file: PathBuf::from("/v"),
+            passphrase: PASSPHRASE.to_string(),
+            header: hex(&data[..HEADER_LEN]),
+            key: hex(&[0; 64]),
+        };
+        assert!(matches!(agent.handle(&unlock), Response::Failed { .. }));
+        assert!(agent.is_empty());
+        let unlock = Request::Unlock {
+            file: PathBuf::from("/v"),
+            passphrase: PASSPHRASE.to_string(),
+            header: hex(&data[..HEADER_LEN]),
+            key: hex(key.key()),
+        };
+        assert!(matches!(agent.handle(&unlock), Response::Done));
+
+        let Response::Unlocked { key: held } = &get_keys(&mut agent, &data) else {
+            panic!("not unlocked");
+        };
+        assert_eq!(held, &hex(key.key()));
+
+        // Written since, with a new salt: the keys are derived again.
+        let newer = vault();
+        let Response::Unlocked { key: held, .. } = &get_keys(&mut agent, &newer) else {
+            panic!("not unlocked");
+        };
+        let key = DerivedKey::from_parts(&newer, &decode_hex(held).unwrap()).unwrap();
+        assert!(key.matches(&newer));
+
+        let lock = Request::Lock {
+            file: PathBuf::from("/v"),
+        };
+        assert!(matches!(agent.handle(&lock), Response::Done));
+        assert!(matches!(agent.handle(&lock), Response::Locked));
+        assert!(agent.is_empty());
+    }
+
+    #[test]
+    fn forgets_a_vault_with_another_passphrase() {
+        let data = vault();
+        let key = DerivedKey::derive(&data, PASSPHRASE.as_bytes()).unwrap();
+        let mut agent = Agent::default();
+        agent.handle(&Request::Unlock {
+            file: PathBuf::from("/v"),
+            passphrase: PASSPHRASE.to_string(),
+            header: hex(&data[..HEADER_LEN]),
+            key: hex(key.key()),
+        });
+        let changed = scrypt_format::encrypt(b"[]", b"another", &TEST_PARAMS).unwrap();
+        assert!(matches!(get_keys(&mut agent, &changed), Response::Locked));
+        assert!(agent.is_empty());
+    }
+
+    #[test]
+    fn derives_keys_for_a_write_without_handing_out_the_passphrase() {
+        let data = vault();
+        let key = DerivedKey::derive(&data, PASSPHRASE.as_bytes()).unwrap();
+        let mut agent = Agent::default();
+        agent.handle(&Request::Unlock {
+            file: PathBuf::from("/v"),
+            passphrase: PASSPHRASE.to_string(),
+            header: hex(&data[..HEADER_LEN]),
+            key: hex(key.key()),
+        });
+        let derive = |params: Params, salt: &[u8], response: Option<&[u8]>| Request::Derive {
+            file: PathBuf::from("/v"),
+            log_n: params.log_n,
+            r: params.r,
+            p: params.p,
+            salt: hex(salt),
+            response: response.map(hex),
+        };
+
+        let salt = scrypt_format::new_salt();
+        let Response::Derived { header, key } = &agent.handle(&derive(TEST_PARAMS, &salt, None))
+        else {
+            panic!("not derived");
+        };
+        let key = DerivedKey::from_parts(&decode_hex(header).unwrap(), &decode_hex(key).unwrap())
+            .unwrap();
+        let written = scrypt_format::encrypt_with_key(b"[]", &key);
+        assert_eq!(
+            written,
+            scrypt_format::encrypt_with_salt(b"[]", PASSPHRASE.as_bytes(), &TEST_PARAMS, &salt)
+                .unwrap()
+        );
+        // The agent holds the keys of the write, and needs no scrypt to read it.
+        let Response::Unlocked { key: held } = &get_keys(&mut agent, &written) else {
+            panic!("not unlocked");
+        };
+        assert_eq!(held, &hex(key.key()));
+
+        // A YubiKey's response is combined with the passphrase, as a key file.
+        let salt = scrypt_format::new_salt();
+        let Response::Derived { key: held, .. } =
+            &agent.handle(&derive(TEST_PARAMS, &salt, Some(b"response")))
+        else {
+            panic!("not derived");
+        };
+        let combined = Passphrase::new(PASSPHRASE.to_string()).with_key_file(b"response");
+        let key = DerivedKey::new(combined.as_bytes(), &TEST_PARAMS, &salt).unwrap();
+        assert_eq!(held, &hex(key.key()));
+
+        let weaker = Params {
+            log_n: TEST_PARAMS.log_n - 1,
+            ..TEST_PARAMS
+        };
+        assert!(matches!(
+            agent.handle(&derive(weaker, &salt, None)),
+            Response::Failed { .. }
+        ));
+        agent.handle(&Request::Lock {
+            file: PathBuf::from("/v"),
+        });
+        assert!(matches!(
+            agent.handle(&derive(TEST_PARAMS, &salt, None)),
+            Response::Locked
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_clients_on_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("run").join(SOCKET_NAME);
+        let file = dir.path().join("pw.scrypt");
+        fs::write(&file, vault()).unwrap();
+        let passphrase = Passphrase::new(PASSPHRASE.to_string());
+        let key = crate::derive_key(&file, &passphrase).unwrap();
+
+        assert!(!running(&socket));
+        assert!(get(&socket, &file).unwrap().is_none());
+        let agent = {
+            let socket = socket.clone();
+            std::thread::spawn(move || serve(&socket, Duration::from_secs(30)))
+        };
+        let start = Instant::now();
+        while !running(&socket) {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "agent did not start"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(serve(&socket, Duration::from_secs(30)).is_err());
+
+        unlock(&socket, &file, &passphrase, &key).unwrap();
+        let held = get(&socket, &file).unwrap().unwrap();
+        assert!(held.as_bytes().is_empty());
+        assert!(crate::vault::load(&file, &held).unwrap().is_empty());
+        // A write gets the keys for its new salt from the agent.
+        crate::vault::store(&file, &held, &[], &TEST_PARAMS).unwrap();
+        assert!(crate::vault::load(&file, &passphrase).unwrap().is_empty());
+        let held = get(&socket, &file).unwrap().unwrap();
+        assert!(crate::vault::load(&file, &held).unwrap().is_empty());
+        assert!(lock(&socket, &file).unwrap());
+        // Holding no vault any more, the agent exits.
+        agent.join().unwrap().unwrap();
+        assert!(!socket.exists());
+        assert!(!lock(&socket, &file).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_a_directory_others_can_reach() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let run = dir.path().join("run");
+        fs::create_dir(&run).unwrap();
+        fs::set_permissions(&run, fs::Permissions::from_mode(0o755)).unwrap();
+        let socket = run.join(SOCKET_NAME);
+        let err = serve(&socket, Duration::from_secs(30)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!socket.exists());
+    }
+}
@@ -4,6 +4,7 @@
 
 use std::path::PathBuf;
 
+use pw::paths::Paths;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +21,10 @@ pub struct Config {
     /// A leading `~/` is expanded. Absent by default, so logging is off.
     #[serde(default)]
     pub log_file: Option<String>,
+    /// The key file of a vault created with `pw init --key-file`, needed
+    /// together with the passphrase. A leading `~/` is expanded.
+    #[serde(default)]
+    pub key_file: Option<String>,
 }
 
 fn default_file() -> String {
@@ -52,7 +57,15 @@ impl Config {
 
     /// The vault path with `~/` expanded.
     pub fn vault_file(&self) -> PathBuf {
-        expand_tilde(&self.file)
+        Paths::resolve().expand_tilde(&self.file)
+    }
+
+    /// The key file with `~/` expanded, if the vault needs one.
+    pub fn key_file(&self) -> Option<PathBuf> {
+        let paths = Paths::resolve();
+        self.key_file
+            .as_deref()
+            .map(|file| paths.expand_tilde(file))
     }
 
     /// The debug-log destination, if any: `$PW_BROWSER_LOG` overrides the
@@ -60,32 +73,19 @@ impl Config {
     pub fn log_file(&self) -> Option<PathBuf> {
         if let Some(env) = std::env::var_os("PW_BROWSER_LOG") {
             if !env.is_empty() {
-                return Some(expand_tilde(&env.to_string_lossy()));
+                return Some(Paths::resolve().expand_tilde(&env.to_string_lossy()));
             }
         }
-        self.log_file.as_deref().map(expand_tilde)
+        let paths = Paths::resolve();
+        self.log_file
+            .as_deref()
+            .map(|file| paths.expand_tilde(file))
     }
 }
 
-/// `~/.config/pw`, where the host config lives.
-pub fn config_dir() -> PathBuf {
-    dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("pw")
-}
-
 fn config_path() -> PathBuf {
     if let Some(path) = std::env::var_os("PW_BROWSER_CONFIG") {
         return PathBuf::from(path);
     }
-    config_dir().join("browser.json")
-}
-
-fn expand_tilde(path: &str) -> PathBuf {
-    if let Some(rest) = path.strip_prefix("~/") {
-        if let Some(home) = dirs::home_dir() {
-            return home.join(rest);
-        }
-    }
-    PathBuf::from(path)
+    Paths::resolve().config.join("browser.json")
 }
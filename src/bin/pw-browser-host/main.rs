@@ -17,7 +17,9 @@ use std::path::PathBuf;
 use std::process::ExitCode;
 use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use pw::{Passphrase, PasswordEntry, PwError};
+use zeroize::Zeroizing;
 
 use config::Config;
 use protocol::{read_message, write_message, Login, Request, Response};
@@ -64,6 +66,10 @@ struct Cache {
 
 struct Host {
     file: PathBuf,
+    /// The contents of the configured key file, if any.
+    key: Option<Zeroizing<Vec<u8>>>,
+    /// The vault's YubiKey, when it has one.
+    yubikey: Option<pw::yubikey::YubiKey>,
     cache_minutes: u64,
     cache: Option<Cache>,
 }
@@ -82,8 +88,22 @@ impl Host {
         let config = Config::load()?;
         debug_log::init(config.log_file());
         log_startup(&config);
+        let key = config
+            .key_file()
+            .map(|path| {
+                std::fs::read(&path)
+                    .map(Zeroizing::new)
+                    .with_context(|| format!("cannot read key file {}", path.display()))
+            })
+            .transpose()?;
+        let yubikey = pw::yubikey::YubiKey::load(&config.vault_file())?;
+        if key.is_some() && yubikey.is_some() {
+            anyhow::bail!("the vault needs its YubiKey, which key_file does not replace");
+        }
         Ok(Host {
             file: config.vault_file(),
+            key,
+            yubikey,
             cache_minutes: config.cache_minutes,
             cache: None,
         })
@@ -198,6 +218,13 @@ impl Host {
         if !self.file.exists() {
             return Err(UnlockError::DbMissing);
         }
+        // Asked once for all attempts: the host never writes, so the salt,
+        // the challenge, stays the same.
+        let response = match &self.yubikey {
+            Some(yubikey) => Some(yubikey_response(yubikey.slot, &self.salt()?)?),
+            None => None,
+        };
+        let key = self.key.as_ref().or(response.as_ref());
         let desc = format!("Unlock {}", self.file.display());
         let mut error_hint: Option<&str> = None;
         for attempt in 1..=MAX_UNLOCK_ATTEMPTS {
@@ -215,7 +242,10 @@ impl Host {
             };
             debug_log::log("pinentry: got passphrase, decrypting");
             let mut pin = pin;
-            let passphrase = Passphrase::new(std::mem::take(&mut *pin));
+            let mut passphrase = Passphrase::new(std::mem::take(&mut *pin));
+            if let Some(key) = key {
+                passphrase = passphrase.with_key_file(key);
+            }
             match pw::list(&self.file, &passphrase) {
                 Ok(entries) => {
                     debug_log::log("decrypt: success");
@@ -232,6 +262,12 @@ impl Host {
         Err(UnlockError::ScryptFailed)
     }
 
+    /// The salt of the vault's header, the YubiKey's challenge.
+    fn salt(&self) -> Result<[u8; pw::scrypt_format::SALT_LEN], UnlockError> {
+        let data = std::fs::read(&self.file).map_err(|e| UnlockError::Internal(e.to_string()))?;
+        pw::scrypt_format::salt(&data).map_err(|e| UnlockError::Internal(e.to_string()))
+    }
+
     /// Drop and zeroize any cached entries.
     fn relock(&mut self) {
         self.cache = None;
@@ -249,9 +285,12 @@ fn log_startup(config: &Config) {
     }
     debug_log::log(&format!("=== pw-browser-host {VERSION} starting ==="));
     debug_log::log(&format!(
-        "config: vault={} cache_minutes={}",
+        "config: vault={} cache_minutes={} key_file={}",
         config.vault_file().display(),
         config.cache_minutes,
+        config
+            .key_file()
+            .map_or("(none)".to_string(), |path| path.display().to_string()),
     ));
     for var in [
         "DISPLAY",
@@ -270,6 +309,30 @@ fn log_startup(config: &Config) {
     }
 }
 
+/// The response of the YubiKey in `slot` to the challenge of a vault header
+/// with `salt`, from `ykchalresp`, as the `pw` binary asks for it; the user
+/// may have to touch the key.
+fn yubikey_response(slot: u8, salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, UnlockError> {
+    debug_log::log(&format!("ykchalresp: asking slot {slot}"));
+    let output = std::process::Command::new("ykchalresp")
+        .arg(format!("-{slot}"))
+        .arg("-x")
+        .arg(pw::yubikey::challenge(salt))
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .map_err(|e| UnlockError::Internal(format!("cannot run ykchalresp: {e}")))?;
+    let stdout = Zeroizing::new(output.stdout);
+    let response = std::str::from_utf8(&stdout)
+        .ok()
+        .filter(|_| output.status.success())
+        .and_then(|hex| pw::yubikey::parse_response(hex).ok());
+    response.ok_or_else(|| {
+        debug_log::log("ykchalresp: no response");
+        UnlockError::Internal(format!("no response from the YubiKey in slot {slot}"))
+    })
+}
+
 fn error(id: u64, code: &'static str, message: impl Into<String>) -> Vec<u8> {
     Response::Error {
         id,
@@ -0,0 +1,260 @@
+//! Unencrypted Bitwarden JSON exports, for `pw import --format bitwarden`.
+//!
+//! Logins become entries with their username, password, first uri, TOTP
+//! secret and notes; secure notes become entries with just their notes. A
+//! favorite is pinned, and the item's folder and collections become tags,
+//! with whitespace and commas replaced by `-`. Custom fields that have a
+//! value become custom fields, as do further uris (`uri2`, `uri3`, …) and a
+//! TOTP secret pw cannot use (`totp`). Cards, identities and other kinds of
+//! item have no place in pw and are only counted.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::{folder_tag, totp, PasswordEntry, PwError, Secret};
+
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+/// A field that refers to another attribute of the item and has no value.
+const LINKED_FIELD: u8 = 3;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Group>,
+    #[serde(default)]
+    collections: Vec<Group>,
+    items: Vec<Item>,
+}
+
+/// A folder, or a collection of an organization export.
+#[derive(Deserialize)]
+struct Group {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(rename = "type")]
+    kind: u8,
+    name: String,
+    folder_id: Option<String>,
+    collection_ids: Option<Vec<String>>,
+    notes: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    fields: Option<Vec<Field>>,
+    login: Option<Login>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(rename = "type")]
+    kind: u8,
+    name: Option<String>,
+    value: Option<Secret>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: Option<String>,
+    password: Option<Secret>,
+    totp: Option<Secret>,
+    uris: Option<Vec<Uri>>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    uri: Option<String>,
+}
+
+/// The entries of a Bitwarden export, in export order.
+pub struct Converted {
+    pub entries: Vec<PasswordEntry>,
+    /// Items of a kind pw cannot store, such as cards
+    pub unsupported: usize,
+}
+
+/// Convert the Bitwarden export `json`. Fails on an encrypted export, which
+/// only Bitwarden can read.
+pub fn convert(json: &[u8]) -> Result<Converted, PwError> {
+    let invalid = |reason: String| PwError::InvalidInput {
+        what: "Bitwarden export",
+        reason,
+    };
+    // serde_json errors carry positions, not data.
+    let export: Export = serde_json::from_slice(json).map_err(|e| invalid(e.to_string()))?;
+    if export.encrypted {
+        return Err(invalid(
+            "it is encrypted - export from Bitwarden as unencrypted JSON".to_string(),
+        ));
+    }
+    let groups: HashMap<&str, &str> = export
+        .folders
+        .iter()
+        .chain(&export.collections)
+        .map(|group| (group.id.as_str(), group.name.as_str()))
+        .collect();
+
+    let mut converted = Converted {
+        entries: Vec::new(),
+        unsupported: 0,
+    };
+    for item in export.items {
+        if item.kind != LOGIN && item.kind != SECURE_NOTE {
+            converted.unsupported += 1;
+            continue;
+        }
+        let mut entry = PasswordEntry {
+            name: item.name,
+            username: String::new(),
+            password: Secret::new(String::new()),
+            url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: item.notes.filter(|notes| !notes.is_empty()),
+            totp: None,
+            pinned: item.favorite,
+            tags: Vec::new(),
+            fields: BTreeMap::new(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        };
+        let group_ids = item
+            .folder_id
+            .into_iter()
+            .chain(item.collection_ids.into_iter().flatten());
+        for id in group_ids {
+            if let Some(name) = groups.get(id.as_str()) {
+                entry.tags.push(folder_tag(name));
+            }
+        }
+        entry.tags.retain(|tag| !tag.is_empty());
+        entry.tags.sort();
+        entry.tags.dedup();
+        for field in item.fields.into_iter().flatten() {
+            if let (Some(name), Some(value)) = (field.name, field.value) {
+                if field.kind != LINKED_FIELD && !name.is_empty() {
+                    entry.fields.insert(name, value);
+                }
+            }
+        }
+        if let Some(login) = item.login {
+            entry.username = login.username.unwrap_or_default();
+            if let Some(password) = login.password {
+                entry.password = password;
+            }
+            if let Some(secret) = login.totp.filter(|s| !s.expose().is_empty()) {
+                if totp::Totp::parse(secret.expose()).is_ok() {
+                    entry.totp = Some(secret);
+                } else {
+                    entry.fields.insert("totp".to_string(), secret);
+                }
+            }
+            let uris = login.uris.into_iter().flatten().filter_map(|u| u.uri);
+            for (i, uri) in uris.filter(|uri| !uri.is_empty()).enumerate() {
+                if i == 0 {
+                    entry.url = Some(uri);
+                } else {
+                    entry
+                        .fields
+                        .insert(format!("uri{}", i + 1), Secret::new(uri));
+                }
+            }
+        }
+        converted.entries.push(entry);
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+      "encrypted": false,
+      "folders": [{"id": "f1", "name": "Work Stuff"}],
+      "items": [
+        {"id": "1", "organizationId": null, "folderId": "f1", "type": 1,
+         "reprompt": 0, "name": "GitHub", "notes": "recovery codes",
+         "favorite": true,
+         "fields": [{"name": "PIN", "value": "1234", "type": 1, "linkedId": null},
+                    {"name": "Username", "value": null, "type": 3, "linkedId": 100}],
+         "login": {"uris": [{"match": null, "uri": "https://github.com"},
+                            {"match": null, "uri": "https://gist.github.com"}],
+                   "username": "alice", "password": "s3cret",
+                   "totp": "JBSWY3DPEHPK3PXP"},
+         "collectionIds": null},
+        {"id": "2", "folderId": null, "type": 2, "name": "Wi-Fi",
+         "notes": "network: home", "favorite": false, "secureNote": {"type": 0}},
+        {"id": "3", "folderId": null, "type": 3, "name": "Visa",
+         "card": {"number": "4111111111111111"}},
+        {"id": "4", "folderId": null, "type": 1, "name": "Steam",
+         "login": {"username": "bob", "password": null, "totp": "steam://ABC"}}
+      ]
+    }"#;
+
+    #[test]
+    fn converts_logins_and_secure_notes() {
+        let converted = convert(EXPORT.as_bytes()).unwrap();
+        assert_eq!(converted.unsupported, 1);
+        let [github, wifi, steam] = &converted.entries[..] else {
+            panic!("expected three entries");
+        };
+
+        assert_eq!(
+            (github.name.as_str(), github.username.as_str()),
+            ("GitHub", "alice")
+        );
+        assert_eq!(github.password, "s3cret".into());
+        assert_eq!(github.url.as_deref(), Some("https://github.com"));
+        assert_eq!(github.notes.as_deref(), Some("recovery codes"));
+        assert!(github.pinned);
+        assert_eq!(github.tags, ["Work-Stuff"]);
+        assert_eq!(github.totp, Some("JBSWY3DPEHPK3PXP".into()));
+        assert_eq!(
+            github.fields,
+            BTreeMap::from([
+                ("PIN".to_string(), "1234".into()),
+                ("uri2".to_string(), "https://gist.github.com".into()),
+            ])
+        );
+
+        assert_eq!(wifi.notes.as_deref(), Some("network: home"));
+        assert_eq!(wifi.password, "".into());
+        assert!(wifi.url.is_none() && wifi.tags.is_empty());
+
+        // A TOTP secret pw cannot generate codes for is kept as a field.
+        assert!(steam.totp.is_none());
+        assert_eq!(steam.fields["totp"], "steam://ABC".into());
+    }
+
+    #[test]
+    fn rejects_encrypted_and_malformed_exports() {
+        for bad in [
+            r#"{"encrypted": true, "items": []}"#,
+            r#"{"folders": []}"#,
+            "not json",
+        ] {
+            assert!(
+                matches!(
+                    convert(bad.as_bytes()),
+                    Err(PwError::InvalidInput {
+                        what: "Bitwarden export",
+                        ..
+                    })
+                ),
+                "{bad}"
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Checking passwords against a local copy of the Have I Been Pwned "Pwned
+//! Passwords" corpus, for machines that must not query the online API.
+//!
+//! The corpus is the SHA-1 download *ordered by hash*: one `HASH:COUNT` line
+//! per breached password, upper-case hex, sorted. It is tens of gigabytes, so
+//! it is never read whole: [`PwnedPasswords::count`] binary-searches the file
+//! by byte offset and reads only the few blocks it probes, which is what a
+//! memory map would page in, without needing one. Passwords never leave the
+//! process; only their SHA-1 is compared.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+use zeroize::Zeroizing;
+
+/// An open Pwned Passwords corpus (SHA-1, ordered by hash).
+pub struct PwnedPasswords {
+    reader: BufReader<File>,
+    len: u64,
+}
+
+impl PwnedPasswords {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(PwnedPasswords {
+            reader: BufReader::new(file),
+            len,
+        })
+    }
+
+    /// How many times `password` occurs in the corpus; 0 if it does not.
+    pub fn count(&mut self, password: &str) -> io::Result<u64> {
+        let target = hex_upper(&sha1(password.as_bytes()));
+        // Every line starting before `lo` has a smaller hash, and every line
+        // starting at or after `hi` has an equal or greater one.
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let start = self.line_start(mid)?;
+            if start >= hi {
+                hi = mid;
+                continue;
+            }
+            let (hash, _, len) = self.read_line(start)?;
+            if hash.as_str() < target.as_str() {
+                lo = start + len;
+            } else {
+                hi = mid;
+            }
+        }
+        let start = self.line_start(lo)?;
+        if start >= self.len {
+            return Ok(0);
+        }
+        let (hash, count, _) = self.read_line(start)?;
+        Ok(if hash == target { count } else { 0 })
+    }
+
+    /// The offset of the first line that starts at or after `pos`.
+    fn line_start(&mut self, pos: u64) -> io::Result<u64> {
+        if pos == 0 {
+            return Ok(0);
+        }
+        self.reader.seek(SeekFrom::Start(pos - 1))?;
+        let mut skipped = Vec::new();
+        let n = self.reader.read_until(b'\n', &mut skipped)?;
+        Ok(pos - 1 + n as u64)
+    }
+
+    /// The hash and count of the line at `start`, and its length in bytes.
+    fn read_line(&mut self, start: u64) -> io::Result<(String, u64, u64)> {
+        self.reader.seek(SeekFrom::Start(start))?;
+        let mut line = String::new();
+        let len = self.reader.read_line(&mut line)? as u64;
+        let parsed = line.trim_end().split_once(':').and_then(|(hash, count)| {
+            let valid = hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+            Some((hash.to_ascii_uppercase(), count.parse().ok()?)).filter(|_| valid)
+        });
+        let (hash, count) = parsed.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a Pwned Passwords SHA-1 file: bad line at byte {start}"),
+            )
+        })?;
+        Ok((hash, count, len))
+    }
+}
+
+fn hex_upper(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+/// SHA-1 (FIPS 180-4), to look passwords up in the corpus, which is keyed by
+/// it, and for HMAC-SHA1 in [`crate::totp`], which its collision weakness
+/// does not affect. The padded copy of `data` is zeroized.
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = Zeroizing::new(data.to_vec());
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn sha1_test_vectors() {
+        assert_eq!(
+            hex_upper(&sha1(b"")),
+            "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709"
+        );
+        assert_eq!(
+            hex_upper(&sha1(b"abc")),
+            "A9993E364706816ABA3E25717850C26C9CD0D89D"
+        );
+        assert_eq!(
+            hex_upper(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983E441C3BD26EBAAE4AA1F95129E5E54670F1"
+        );
+    }
+
+    #[test]
+    fn finds_counts_in_a_sorted_corpus() {
+        let mut hashes: Vec<(String, u64)> = (0..200)
+            .map(|i| (hex_upper(&sha1(format!("pw{i}").as_bytes())), i + 1))
+            .collect();
+        hashes.sort();
+        let mut corpus = tempfile::NamedTempFile::new().unwrap();
+        for (hash, count) in &hashes {
+            write!(corpus, "{hash}:{count}\r\n").unwrap();
+        }
+        corpus.flush().unwrap();
+
+        let mut pwned = PwnedPasswords::open(corpus.path()).unwrap();
+        for i in 0..200 {
+            assert_eq!(pwned.count(&format!("pw{i}")).unwrap(), i + 1);
+        }
+        assert_eq!(pwned.count("not in the corpus").unwrap(), 0);
+        assert_eq!(pwned.count("").unwrap(), 0);
+    }
+
+    #[test]
+    fn empty_and_malformed_corpora() {
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(
+            PwnedPasswords::open(empty.path())
+                .unwrap()
+                .count("x")
+                .unwrap(),
+            0
+        );
+
+        let mut bad = tempfile::NamedTempFile::new().unwrap();
+        writeln!(bad, "this is not a corpus").unwrap();
+        let err = PwnedPasswords::open(bad.path())
+            .unwrap()
+            .count("x")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
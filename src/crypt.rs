@@ -0,0 +1,297 @@
+//! SHA-crypt password hashes, the `$5$…` (SHA-256) and `$6$…` (SHA-512)
+//! strings of crypt(3) and `/etc/shadow`, for `pw hash`.
+//!
+//! This follows Ulrich Drepper's specification, so a hash made here for a
+//! given salt and number of rounds is the same string `openssl passwd -6`,
+//! `mkpasswd` or the C library produce, and a stored hash from any of them can
+//! be checked with [`verify`].
+
+use rand::RngExt;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::PwError;
+
+/// Rounds when a hash does not give any, which are then left out of it.
+pub const DEFAULT_ROUNDS: u32 = 5000;
+/// The fewest rounds allowed.
+pub const MIN_ROUNDS: u32 = 1000;
+/// The most rounds allowed.
+pub const MAX_ROUNDS: u32 = 999_999_999;
+/// The longest salt; the specification ignores any further characters.
+pub const MAX_SALT_LEN: usize = 16;
+
+/// The characters of the encoding, and of generated salts.
+const ALPHABET: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The order in which the digest bytes are encoded, three at a time.
+const SHA256_ORDER: [[usize; 3]; 10] = [
+    [0, 10, 20],
+    [21, 1, 11],
+    [12, 22, 2],
+    [3, 13, 23],
+    [24, 4, 14],
+    [15, 25, 5],
+    [6, 16, 26],
+    [27, 7, 17],
+    [18, 28, 8],
+    [9, 19, 29],
+];
+const SHA512_ORDER: [[usize; 3]; 21] = [
+    [0, 21, 42],
+    [22, 43, 1],
+    [44, 2, 23],
+    [3, 24, 45],
+    [25, 46, 4],
+    [47, 5, 26],
+    [6, 27, 48],
+    [28, 49, 7],
+    [50, 8, 29],
+    [9, 30, 51],
+    [31, 52, 10],
+    [53, 11, 32],
+    [12, 33, 54],
+    [34, 55, 13],
+    [56, 14, 35],
+    [15, 36, 57],
+    [37, 58, 16],
+    [59, 17, 38],
+    [18, 39, 60],
+    [40, 61, 19],
+    [62, 20, 41],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// `$5$`
+    Sha256,
+    /// `$6$`
+    Sha512,
+}
+
+impl Algorithm {
+    fn id(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "5",
+            Algorithm::Sha512 => "6",
+        }
+    }
+}
+
+/// Hash `password` with `salt` (at most [`MAX_SALT_LEN`] characters, none of
+/// them `$`, `:` or a control character) and `rounds`, which are written into
+/// the hash when given, even if they are [`DEFAULT_ROUNDS`], as crypt(3) does.
+pub fn hash(
+    password: &[u8],
+    algorithm: Algorithm,
+    salt: &str,
+    rounds: Option<u32>,
+) -> Result<String, PwError> {
+    let invalid = |what, reason: String| PwError::InvalidInput { what, reason };
+    if salt.len() > MAX_SALT_LEN {
+        return Err(invalid(
+            "salt",
+            format!("is longer than {MAX_SALT_LEN} characters"),
+        ));
+    }
+    if salt.chars().any(|c| c == '$' || c == ':' || c.is_control()) {
+        return Err(invalid(
+            "salt",
+            "must not contain '$', ':' or control characters".to_string(),
+        ));
+    }
+    if let Some(rounds) = rounds {
+        if !(MIN_ROUNDS..=MAX_ROUNDS).contains(&rounds) {
+            return Err(invalid(
+                "rounds",
+                format!("must be between {MIN_ROUNDS} and {MAX_ROUNDS}"),
+            ));
+        }
+    }
+
+    let digest = match algorithm {
+        Algorithm::Sha256 => sha_crypt::<Sha256>(password, salt, rounds.unwrap_or(DEFAULT_ROUNDS)),
+        Algorithm::Sha512 => sha_crypt::<Sha512>(password, salt, rounds.unwrap_or(DEFAULT_ROUNDS)),
+    };
+    let mut out = format!("${}$", algorithm.id());
+    if let Some(rounds) = rounds {
+        out.push_str(&format!("rounds={rounds}$"));
+    }
+    out.push_str(salt);
+    out.push('$');
+    match algorithm {
+        Algorithm::Sha256 => {
+            for [a, b, c] in SHA256_ORDER {
+                encode(&mut out, digest[a], digest[b], digest[c], 4);
+            }
+            encode(&mut out, 0, digest[31], digest[30], 3);
+        }
+        Algorithm::Sha512 => {
+            for [a, b, c] in SHA512_ORDER {
+                encode(&mut out, digest[a], digest[b], digest[c], 4);
+            }
+            encode(&mut out, 0, 0, digest[63], 2);
+        }
+    }
+    Ok(out)
+}
+
+/// Whether `password` matches `stored`, a `$5$…` or `$6$…` hash as in
+/// `/etc/shadow`.
+pub fn verify(password: &[u8], stored: &str) -> Result<bool, PwError> {
+    let invalid = || PwError::InvalidInput {
+        what: "hash",
+        reason: "is not a SHA-crypt hash ($5$… or $6$…)".to_string(),
+    };
+    let (algorithm, rest) = if let Some(rest) = stored.strip_prefix("$5$") {
+        (Algorithm::Sha256, rest)
+    } else if let Some(rest) = stored.strip_prefix("$6$") {
+        (Algorithm::Sha512, rest)
+    } else {
+        return Err(invalid());
+    };
+    let (rounds, rest) = match rest.strip_prefix("rounds=") {
+        Some(rest) => {
+            let (rounds, rest) = rest.split_once('$').ok_or_else(invalid)?;
+            let rounds: u32 = rounds.parse().map_err(|_| invalid())?;
+            // crypt(3) clamps the rounds of a stored hash to the allowed range.
+            (Some(rounds.clamp(MIN_ROUNDS, MAX_ROUNDS)), rest)
+        }
+        None => (None, rest),
+    };
+    let (salt, _) = rest.split_once('$').ok_or_else(invalid)?;
+    let expected = hash(password, algorithm, salt, rounds)?;
+    // Compare in constant time: the hash is of a secret.
+    Ok(expected.len() == stored.len()
+        && expected
+            .bytes()
+            .zip(stored.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0)
+}
+
+/// A random salt of [`MAX_SALT_LEN`] characters.
+pub fn generate_salt() -> String {
+    let mut rng = crate::rng();
+    (0..MAX_SALT_LEN)
+        .map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())] as char)
+        .collect()
+}
+
+/// The digest of steps 1 to 21 of the specification.
+fn sha_crypt<D: Digest>(password: &[u8], salt: &str, rounds: u32) -> Zeroizing<Vec<u8>> {
+    let salt = salt.as_bytes();
+    // Repeat `digest` to `len` bytes.
+    let repeat = |digest: &[u8], len: usize| -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(digest.iter().copied().cycle().take(len).collect())
+    };
+
+    let b = Zeroizing::new(
+        D::new()
+            .chain_update(password)
+            .chain_update(salt)
+            .chain_update(password)
+            .finalize()
+            .to_vec(),
+    );
+    let mut a = D::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(&repeat(&b, password.len())[..]);
+    let mut n = password.len();
+    while n > 0 {
+        if n & 1 == 1 {
+            a.update(&b[..]);
+        } else {
+            a.update(password);
+        }
+        n >>= 1;
+    }
+    let mut c = Zeroizing::new(a.finalize().to_vec());
+
+    let mut dp = D::new();
+    for _ in 0..password.len() {
+        dp.update(password);
+    }
+    let p = repeat(&Zeroizing::new(dp.finalize().to_vec()), password.len());
+    let mut ds = D::new();
+    for _ in 0..16 + usize::from(c[0]) {
+        ds.update(salt);
+    }
+    let s = repeat(&ds.finalize(), salt.len());
+
+    for i in 0..rounds {
+        let mut round = D::new();
+        if i % 2 == 1 {
+            round.update(&p[..]);
+        } else {
+            round.update(&c[..]);
+        }
+        if i % 3 != 0 {
+            round.update(&s[..]);
+        }
+        if i % 7 != 0 {
+            round.update(&p[..]);
+        }
+        if i % 2 == 1 {
+            round.update(&c[..]);
+        } else {
+            round.update(&p[..]);
+        }
+        c.copy_from_slice(&round.finalize());
+    }
+    c
+}
+
+/// Append the `n` low characters of the 24 bits `b2 b1 b0`, lowest first.
+fn encode(out: &mut String, b2: u8, b1: u8, b0: u8, n: usize) {
+    let mut w = u32::from(b2) << 16 | u32::from(b1) << 8 | u32::from(b0);
+    for _ in 0..n {
+        out.push(ALPHABET[(w & 0x3f) as usize] as char);
+        w >>= 6;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the specification, and `openssl passwd -5` / `-6`.
+    #[test]
+    fn matches_the_reference_hashes() {
+        assert_eq!(
+            hash(b"Hello world!", Algorithm::Sha256, "saltstring", None).unwrap(),
+            "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"
+        );
+        assert_eq!(
+            hash(b"Hello world!", Algorithm::Sha512, "saltstring", None).unwrap(),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(
+            hash(b"Hello world!", Algorithm::Sha512, "saltstringsaltst", Some(10000)).unwrap(),
+            "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."
+        );
+    }
+
+    #[test]
+    fn verifies_stored_hashes() {
+        let stored = "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA";
+        assert!(verify(b"Hello world!", stored).unwrap());
+        assert!(!verify(b"Hello world?", stored).unwrap());
+        let fresh = hash(b"secret", Algorithm::Sha512, &generate_salt(), None).unwrap();
+        assert!(verify(b"secret", &fresh).unwrap());
+        assert!(verify(b"secret", "$1$salt$abc").is_err());
+        assert!(verify(b"secret", "$6$nodollar").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_salts_and_rounds() {
+        for salt in ["a$b", "a:b", "a\nb", "seventeen-chars-x"] {
+            assert!(hash(b"secret", Algorithm::Sha256, salt, None).is_err());
+        }
+        assert!(hash(b"secret", Algorithm::Sha256, "salt", Some(999)).is_err());
+        let salt = generate_salt();
+        assert_eq!(salt.len(), MAX_SALT_LEN);
+        assert!(salt.bytes().all(|b| ALPHABET.contains(&b)));
+    }
+}
@@ -0,0 +1,304 @@
+//! CSV exports of other password managers, for `pw import --format
+//! 1password-csv` and `--format lastpass-csv`.
+//!
+//! Columns are found by their header, case-insensitively, so column order and
+//! extra columns do not matter. Every row must make a valid entry: rows that
+//! do not are all reported by line number, and nothing is imported.
+
+use std::collections::BTreeMap;
+
+use crate::{folder_tag, totp, validate_entry, PasswordEntry, PwError, Secret};
+
+/// Rows reported in one error before the rest are only counted.
+const MAX_REPORTED_ROWS: usize = 10;
+
+/// The password manager that wrote an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    /// 1Password 7 or 8: `Title`, `Url`, `Username`, `Password`, `OTPAuth`,
+    /// `Favorite`, `Tags` and `Notes`
+    OnePassword,
+    /// LastPass: `url`, `username`, `password`, `totp`, `extra`, `name`,
+    /// `grouping` and `fav`. Secure notes have the url `http://sn`.
+    LastPass,
+}
+
+/// The attributes pw reads, with the headers each flavor uses for them.
+struct Columns {
+    name: &'static [&'static str],
+    url: &'static [&'static str],
+    username: &'static [&'static str],
+    password: &'static [&'static str],
+    totp: &'static [&'static str],
+    notes: &'static [&'static str],
+    favorite: &'static [&'static str],
+    tags: &'static [&'static str],
+}
+
+impl Flavor {
+    fn columns(self) -> Columns {
+        match self {
+            Flavor::OnePassword => Columns {
+                name: &["title"],
+                url: &["url", "website", "login url"],
+                username: &["username", "login username"],
+                password: &["password", "login password"],
+                totp: &["otpauth", "one-time password"],
+                notes: &["notes", "notesplain"],
+                favorite: &["favorite"],
+                tags: &["tags"],
+            },
+            Flavor::LastPass => Columns {
+                name: &["name"],
+                url: &["url"],
+                username: &["username"],
+                password: &["password"],
+                totp: &["totp"],
+                notes: &["extra"],
+                favorite: &["fav"],
+                tags: &["grouping"],
+            },
+        }
+    }
+}
+
+/// The entries of the CSV export `text`, in file order.
+pub fn convert(text: &[u8], flavor: Flavor) -> Result<Vec<PasswordEntry>, PwError> {
+    let invalid = |reason: String| PwError::InvalidInput {
+        what: "CSV export",
+        reason,
+    };
+    let text = std::str::from_utf8(text).map_err(|_| invalid("not UTF-8".to_string()))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = records(text).map_err(invalid)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Err(invalid("empty".to_string()));
+    };
+    let header: Vec<String> = header
+        .iter()
+        .map(|h| h.expose().trim().to_lowercase())
+        .collect();
+    let find = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let columns = flavor.columns();
+    let (Some(name), Some(password)) = (find(columns.name), find(columns.password)) else {
+        return Err(invalid(format!(
+            "the header has no {} or no {} column",
+            columns.name[0], columns.password[0]
+        )));
+    };
+    let url = find(columns.url);
+    let username = find(columns.username);
+    let totp = find(columns.totp);
+    let notes = find(columns.notes);
+    let favorite = find(columns.favorite);
+    let tags = find(columns.tags);
+
+    let mut entries = Vec::new();
+    let mut problems = Vec::new();
+    for (line, row) in records {
+        if row.len() != header.len() {
+            problems.push(format!(
+                "line {line}: {} fields where the header has {}",
+                row.len(),
+                header.len()
+            ));
+            continue;
+        }
+        let cell = |column: Option<usize>| {
+            column
+                .map(|i| row[i].expose().trim())
+                .filter(|value| !value.is_empty())
+        };
+        let mut entry = PasswordEntry {
+            name: cell(Some(name)).unwrap_or_default().to_string(),
+            username: cell(username).unwrap_or_default().to_string(),
+            password: row[password].clone(),
+            url: cell(url).map(str::to_string),
+            url_match: Default::default(),
+            launch: None,
+            notes: cell(notes).map(str::to_string),
+            totp: None,
+            pinned: matches!(cell(favorite), Some("1" | "true" | "TRUE" | "True")),
+            tags: Vec::new(),
+            fields: BTreeMap::new(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        };
+        if flavor == Flavor::LastPass && entry.url.as_deref() == Some("http://sn") {
+            entry.url = None;
+        }
+        if let Some(secret) = cell(totp) {
+            let secret = Secret::new(secret.to_string());
+            if totp::Totp::parse(secret.expose()).is_ok() {
+                entry.totp = Some(secret);
+            } else {
+                entry.fields.insert("totp".to_string(), secret);
+            }
+        }
+        if let Some(cell) = cell(tags) {
+            // 1Password separates tags with commas, LastPass nests folders
+            // with backslashes; each becomes a tag of its own.
+            entry.tags = cell
+                .split([',', ';', '\\'])
+                .map(folder_tag)
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            entry.tags.sort();
+            entry.tags.dedup();
+        }
+        match validate_entry(&entry) {
+            Ok(()) => entries.push(entry),
+            Err(err) => problems.push(format!("line {line}: {err}")),
+        }
+    }
+    if !problems.is_empty() {
+        let more = problems.len().saturating_sub(MAX_REPORTED_ROWS);
+        problems.truncate(MAX_REPORTED_ROWS);
+        let mut reason = problems.join("; ");
+        if more > 0 {
+            reason.push_str(&format!(" (and {more} more rows)"));
+        }
+        return Err(invalid(reason));
+    }
+    Ok(entries)
+}
+
+/// The records of `text` with the line each starts on, as RFC 4180 has
+/// them: fields in double quotes may hold commas, line breaks and doubled
+/// quotes. Blank lines are skipped.
+fn records(text: &str) -> Result<Vec<(usize, Vec<Secret>)>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    let mut in_record = false;
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => {
+                quoted = true;
+                in_record = true;
+            }
+            ',' => {
+                record.push(Secret::new(std::mem::take(&mut field)));
+                in_record = true;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if in_record || !field.is_empty() {
+                    record.push(Secret::new(std::mem::take(&mut field)));
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                in_record = false;
+                line += 1;
+                start = line;
+            }
+            c => {
+                field.push(c);
+                in_record = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(format!("line {start}: unterminated quoted field"));
+    }
+    if in_record || !field.is_empty() {
+        record.push(Secret::new(field));
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_fields() {
+        let text = "a,b\r\n\"x, \"\"y\"\"\",\"two\nlines\"\n\nlast,\n";
+        let records: Vec<_> = records(text)
+            .unwrap()
+            .into_iter()
+            .map(|(line, row)| {
+                let row: Vec<_> = row.iter().map(|f| f.expose().to_string()).collect();
+                (line, row)
+            })
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (1, vec!["a".to_string(), "b".to_string()]),
+                (2, vec!["x, \"y\"".to_string(), "two\nlines".to_string()]),
+                (5, vec!["last".to_string(), String::new()]),
+            ]
+        );
+        assert!(super::records("\"open")
+            .unwrap_err()
+            .contains("unterminated"));
+    }
+
+    #[test]
+    fn converts_lastpass_exports() {
+        let text = "url,username,password,totp,extra,name,grouping,fav\n\
+            https://github.com,alice,s3cret,JBSWY3DPEHPK3PXP,,GitHub,Work\\Code,1\n\
+            http://sn,,,,\"network: home\",Wi-Fi,,0\n";
+        let [github, wifi] = &convert(text.as_bytes(), Flavor::LastPass).unwrap()[..] else {
+            panic!("expected two entries");
+        };
+        assert_eq!(
+            (github.name.as_str(), github.username.as_str()),
+            ("GitHub", "alice")
+        );
+        assert_eq!(github.password, "s3cret".into());
+        assert_eq!(github.url.as_deref(), Some("https://github.com"));
+        assert_eq!(github.tags, ["Code", "Work"]);
+        assert!(github.pinned && github.totp.is_some());
+        assert_eq!(wifi.url, None);
+        assert_eq!(wifi.notes.as_deref(), Some("network: home"));
+    }
+
+    #[test]
+    fn converts_1password_exports() {
+        let text = "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n\
+            GitHub,https://github.com,alice,s3cret,,true,false,\"work,code\",recovery codes\n";
+        let entries = convert(text.as_bytes(), Flavor::OnePassword).unwrap();
+        assert_eq!(entries[0].name, "GitHub");
+        assert_eq!(entries[0].tags, ["code", "work"]);
+        assert_eq!(entries[0].notes.as_deref(), Some("recovery codes"));
+        assert!(entries[0].pinned);
+    }
+
+    #[test]
+    fn reports_every_bad_row() {
+        let text = "name,password\nok,pw\n,pw\nshort\nfine,pw\n";
+        let err = convert(text.as_bytes(), Flavor::LastPass).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid CSV export: line 3: invalid entry name: must not be empty; \
+             line 4: 1 fields where the header has 2"
+        );
+        let err = convert(b"title,url\n", Flavor::OnePassword).unwrap_err();
+        assert!(err.to_string().contains("no title or no password column"));
+    }
+}
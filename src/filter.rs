@@ -0,0 +1,355 @@
+//! Entry filters and the small query language of `pw search`.
+//!
+//! A query is a list of terms separated by whitespace, all of which must
+//! match, such as `tag:work user:alice github`:
+//!
+//! - `name:`, `user:` and `url:` match a substring of that attribute,
+//!   ignoring case;
+//! - `tag:` matches entries with exactly that tag;
+//! - `is:pinned` matches pinned entries;
+//! - a plain word matches as [`crate::search`] ranks: a substring of the
+//!   name, username or url, ignoring case and allowing a typo per four
+//!   characters, or a whole tag. [`Filter::exact`] drops the typos;
+//! - a leading `-` negates a term, as in `-tag:old`.
+//!
+//! Double quotes group words into one term or value: `name:"home router"`.
+//! [`Filter::including`] lets plain words match the notes and the names of
+//! custom fields as well, as `pw search --in notes,fields` does.
+//! `list` builds the same [`Filter`] from its pattern, `--tag` and
+//! `--expired`.
+
+use crate::{search, PasswordEntry, PwError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// A substring of the name, username or url, or a whole tag, ignoring
+    /// case
+    Text(String),
+    /// What [`search::score_entry`] matches: as `Text`, but allowing a typo
+    /// per four characters
+    Fuzzy(String),
+    Name(String),
+    User(String),
+    Url(String),
+    Tag(String),
+    Pinned,
+    /// Entries whose password expires at or before this time, in seconds
+    /// since the Unix epoch
+    ExpiresBy(u64),
+    /// A substring of the notes, ignoring case and accents
+    Notes(String),
+    /// A substring of the name of a custom field, ignoring case and accents.
+    /// The values are secrets like the password, and are never searched.
+    Field(String),
+    Not(Box<Filter>),
+    /// Entries matching every filter; matches everything when empty
+    All(Vec<Filter>),
+    /// Entries matching at least one filter
+    Any(Vec<Filter>),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::All(Vec::new())
+    }
+}
+
+impl Filter {
+    /// Parse a query, as described in the module documentation.
+    pub fn parse(query: &str) -> Result<Self, PwError> {
+        let invalid = |reason: String| PwError::InvalidInput {
+            what: "search query",
+            reason,
+        };
+        let mut filters = Vec::new();
+        for term in split_terms(query).map_err(|reason| invalid(reason.to_string()))? {
+            let (negated, term) = match term.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest.to_string()),
+                _ => (false, term),
+            };
+            let filter = match term.split_once(':') {
+                None => Filter::Fuzzy(term),
+                Some((key, value)) => {
+                    let value = value.to_string();
+                    if value.is_empty() {
+                        return Err(invalid(format!("'{key}:' needs a value")));
+                    }
+                    match key {
+                        "name" => Filter::Name(value),
+                        "user" => Filter::User(value),
+                        "url" => Filter::Url(value),
+                        "tag" => Filter::Tag(value),
+                        "is" if value == "pinned" => Filter::Pinned,
+                        "is" => return Err(invalid("'is:' only takes 'pinned'".to_string())),
+                        _ => {
+                            return Err(invalid(format!(
+                                "unknown key '{key}:' - use name:, user:, url:, tag: or \
+                                 is:pinned"
+                            )))
+                        }
+                    }
+                }
+            };
+            filters.push(if negated {
+                Filter::Not(Box::new(filter))
+            } else {
+                filter
+            });
+        }
+        Ok(Filter::All(filters))
+    }
+
+    /// This filter and `other` both.
+    pub fn and(self, other: Filter) -> Filter {
+        match (self, other) {
+            (Filter::All(mut a), Filter::All(b)) => {
+                a.extend(b);
+                Filter::All(a)
+            }
+            (Filter::All(mut a), other) | (other, Filter::All(mut a)) => {
+                a.push(other);
+                Filter::All(a)
+            }
+            (a, b) => Filter::All(vec![a, b]),
+        }
+    }
+
+    /// This filter with typos no longer allowed in plain words.
+    pub fn exact(self) -> Filter {
+        match self {
+            Filter::Fuzzy(text) => Filter::Text(text),
+            Filter::Not(filter) => Filter::Not(Box::new(filter.exact())),
+            Filter::All(filters) => Filter::All(filters.into_iter().map(Filter::exact).collect()),
+            Filter::Any(filters) => Filter::Any(filters.into_iter().map(Filter::exact).collect()),
+            filter => filter,
+        }
+    }
+
+    /// This filter with plain words also matching the notes, if `notes`, and
+    /// the names of custom fields, if `fields`. Typos are still allowed only
+    /// in the name, username and url.
+    pub fn including(self, notes: bool, fields: bool) -> Filter {
+        match self {
+            Filter::Text(ref text) | Filter::Fuzzy(ref text) if notes || fields => {
+                let mut any = Vec::new();
+                if notes {
+                    any.push(Filter::Notes(text.clone()));
+                }
+                if fields {
+                    any.push(Filter::Field(text.clone()));
+                }
+                any.insert(0, self);
+                Filter::Any(any)
+            }
+            Filter::Not(filter) => Filter::Not(Box::new(filter.including(notes, fields))),
+            Filter::All(filters) => Filter::All(
+                filters
+                    .into_iter()
+                    .map(|filter| filter.including(notes, fields))
+                    .collect(),
+            ),
+            filter => filter,
+        }
+    }
+
+    /// The plain words that an entry must match, for ranking the entries
+    /// that do with [`search::score_entry`].
+    pub fn words(&self) -> Vec<&str> {
+        match self {
+            Filter::Text(text) | Filter::Fuzzy(text) => vec![text],
+            Filter::All(filters) | Filter::Any(filters) => {
+                filters.iter().flat_map(Filter::words).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn matches(&self, entry: &PasswordEntry) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+        match self {
+            Filter::Fuzzy(text) => search::score_entry(entry, text).is_some(),
+            Filter::Text(text) => {
+                contains(&entry.name, text)
+                    || contains(&entry.username, text)
+                    || entry.url.as_deref().is_some_and(|url| contains(url, text))
+                    || entry.tags.iter().any(|tag| tag.eq_ignore_ascii_case(text))
+            }
+            Filter::Name(name) => contains(&entry.name, name),
+            Filter::User(user) => contains(&entry.username, user),
+            Filter::Url(url) => entry.url.as_deref().is_some_and(|u| contains(u, url)),
+            Filter::Tag(tag) => entry.tags.contains(tag),
+            Filter::Pinned => entry.pinned,
+            Filter::ExpiresBy(time) => entry.expires_at.is_some_and(|at| at <= *time),
+            Filter::Notes(text) => entry
+                .notes
+                .as_deref()
+                .is_some_and(|notes| search::find(notes, text).is_some()),
+            Filter::Field(text) => entry
+                .fields
+                .keys()
+                .any(|field| search::find(field, text).is_some()),
+            Filter::Not(filter) => !filter.matches(entry),
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(entry)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(entry)),
+        }
+    }
+}
+
+/// Split a query at whitespace outside double quotes, dropping the quotes.
+fn split_terms(query: &str) -> Result<Vec<String>, &'static str> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let (mut quoted, mut in_term) = (false, false);
+    for c in query.chars() {
+        match c {
+            '"' => (quoted, in_term) = (!quoted, true),
+            c if c.is_whitespace() && !quoted => {
+                if in_term {
+                    terms.push(std::mem::take(&mut term));
+                    in_term = false;
+                }
+            }
+            c => {
+                term.push(c);
+                in_term = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("unterminated quote");
+    }
+    if in_term {
+        terms.push(term);
+    }
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, username: &str, url: Option<&str>, tags: &[&str]) -> PasswordEntry {
+        PasswordEntry {
+            name: name.to_string(),
+            username: username.to_string(),
+            password: "p".into(),
+            url: url.map(str::to_string),
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: name.starts_with("work"),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn names(query: &str, entries: &[PasswordEntry]) -> Vec<String> {
+        let filter = Filter::parse(query).unwrap();
+        entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn terms_combine_and_negate() {
+        let entries = [
+            entry("work-github", "Alice", Some("github.com"), &["work"]),
+            entry("home-github", "alice", Some("github.com"), &[]),
+            entry(
+                "work-mail",
+                "bob",
+                Some("mail.example.com"),
+                &["work", "old"],
+            ),
+            entry("home router", "admin", None, &["Net"]),
+        ];
+        assert_eq!(names("", &entries).len(), 4);
+        assert_eq!(
+            names("tag:work user:alice github", &entries),
+            ["work-github"]
+        );
+        assert_eq!(names("GITHUB -tag:work", &entries), ["home-github"]);
+        assert_eq!(names("url:example", &entries), ["work-mail"]);
+        assert_eq!(names("name:\"home router\"", &entries), ["home router"]);
+        assert_eq!(names("net", &entries), ["home router"]);
+        assert_eq!(names("is:pinned -old", &entries), ["work-github"]);
+        assert_eq!(names("tag:Work", &entries), Vec::<String>::new());
+        assert_eq!(names("githb -tag:work", &entries), ["home-github"]);
+        let exact = Filter::parse("githb").unwrap().exact();
+        assert_eq!(exact, Filter::All(vec![Filter::Text("githb".to_string())]));
+        assert!(!entries.iter().any(|e| exact.matches(e)));
+        assert_eq!(
+            Filter::parse("tag:x a -b \"c d\"").unwrap().words(),
+            ["a", "c d"]
+        );
+    }
+
+    #[test]
+    fn and_flattens() {
+        let filter = Filter::default()
+            .and(Filter::Name("a".to_string()))
+            .and(Filter::parse("tag:x -b").unwrap());
+        assert_eq!(
+            filter,
+            Filter::All(vec![
+                Filter::Name("a".to_string()),
+                Filter::Tag("x".to_string()),
+                Filter::Not(Box::new(Filter::Fuzzy("b".to_string()))),
+            ])
+        );
+    }
+
+    #[test]
+    fn plain_words_can_include_notes_and_field_names() {
+        let mut safe = entry("bank", "alice", None, &[]);
+        safe.notes = Some("Recovery codes are in the Safe".to_string());
+        let mut pin = entry("phone", "bob", None, &[]);
+        pin.fields.insert("SIM PIN".to_string(), "1234".into());
+        pin.fields.insert("puk".to_string(), "safe".into());
+        let entries = [safe, pin];
+
+        let names = |query: &str, notes, fields| -> Vec<String> {
+            let filter = Filter::parse(query).unwrap().including(notes, fields);
+            entries
+                .iter()
+                .filter(|e| filter.matches(e))
+                .map(|e| e.name.clone())
+                .collect()
+        };
+        assert!(names("safe", false, false).is_empty());
+        assert_eq!(names("safe", true, false), ["bank"]);
+        assert_eq!(names("pin", false, true), ["phone"]);
+        // Field values are secrets, and never searched.
+        assert!(names("safe", false, true).is_empty());
+        assert_eq!(names("-safe", true, true), ["phone"]);
+        assert_eq!(names("bank safe", true, true), ["bank"]);
+        assert_eq!(
+            Filter::parse("safe").unwrap().including(true, true).words(),
+            ["safe"]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for bad in ["usr:alice", "tag:", "is:old", "name:\"open"] {
+            assert!(
+                matches!(
+                    Filter::parse(bad),
+                    Err(PwError::InvalidInput {
+                        what: "search query",
+                        ..
+                    })
+                ),
+                "{bad}"
+            );
+        }
+    }
+}
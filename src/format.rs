@@ -0,0 +1,347 @@
+//! The decrypted vault payload — the JSON document inside the scrypt format —
+//! as a published JSON Schema, with a validator for it, so external tools
+//! that write or read vaults can check that they are compatible.
+//!
+//! [`validate`] is written by hand to match [`SCHEMA`] rule for rule (pw does
+//! not depend on a JSON Schema engine), and also checks that entry names are
+//! unique, which the schema cannot express. Entries may carry properties the
+//! schema does not list; pw preserves them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::PwError;
+
+/// JSON Schema (draft 2020-12) of the decrypted vault payload.
+pub const SCHEMA: &str = include_str!("../docs/vault-schema.json");
+
+/// One way in which a payload does not match [`SCHEMA`]. Never contains
+/// passwords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// JSON Pointer to the offending value, e.g. `/entries/3/url`; empty for
+    /// the document itself.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Validate a decrypted vault payload, returning every problem found. An
+/// empty result means the payload is valid.
+pub fn validate(bytes: &[u8]) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    // serde_json errors carry positions, not data, so the message is safe to
+    // show.
+    let document: Value = match serde_json::from_slice(bytes) {
+        Ok(document) => document,
+        Err(e) => {
+            errors.push(error("", format!("not valid JSON: {e}")));
+            return errors;
+        }
+    };
+    let root = match document {
+        Value::Object(root) => root,
+        Value::Array(_) => {
+            errors.push(error(
+                "",
+                "a bare array of entries (the pw 0.1 format) - \
+                 wrap it as {\"version\": 1, \"entries\": [...]}",
+            ));
+            return errors;
+        }
+        _ => {
+            errors.push(error("", "must be an object"));
+            return errors;
+        }
+    };
+
+    check_required("", &root, &["version", "entries"], &mut errors);
+    for key in root.keys() {
+        if !["version", "entries"].contains(&key.as_str()) {
+            errors.push(error(&format!("/{}", escape(key)), "unknown property"));
+        }
+    }
+    if let Some(version) = root.get("version") {
+        if version.as_u64() != Some(1) {
+            errors.push(error("/version", "must be 1"));
+        }
+    }
+    match root.get("entries") {
+        Some(Value::Array(entries)) => {
+            let mut names: HashMap<&str, usize> = HashMap::new();
+            for (i, entry) in entries.iter().enumerate() {
+                let path = format!("/entries/{i}");
+                check_entry(&path, entry, &mut errors);
+                if let Some(name) = entry.get("name").and_then(Value::as_str) {
+                    if let Some(first) = names.insert(name, i) {
+                        errors.push(error(
+                            &format!("{path}/name"),
+                            format!("same name as /entries/{first}"),
+                        ));
+                    }
+                }
+            }
+        }
+        Some(_) => errors.push(error("/entries", "must be an array")),
+        None => {}
+    }
+    errors
+}
+
+fn check_entry(path: &str, entry: &Value, errors: &mut Vec<ValidationError>) {
+    let Value::Object(entry) = entry else {
+        errors.push(error(path, "must be an object"));
+        return;
+    };
+    // Other properties are allowed: pw keeps them as they are (see
+    // `PasswordEntry::extra`).
+    check_required(path, entry, &["name", "username", "password"], errors);
+    let text = |key: &str, check: fn(&str) -> Result<(), PwError>, errors: &mut Vec<_>| {
+        let field = format!("{path}/{key}");
+        match entry.get(key) {
+            Some(Value::String(value)) => {
+                if let Err(e) = check(value) {
+                    errors.push(error(&field, reason(e)));
+                }
+            }
+            Some(_) => errors.push(error(&field, "must be a string")),
+            None => {}
+        }
+    };
+    text("name", crate::validate_name, errors);
+    text("username", crate::validate_username, errors);
+    text("password", |_| Ok(()), errors);
+    text("url", crate::validate_url, errors);
+    text("launch", crate::validate_launch, errors);
+    text("notes", crate::validate_notes, errors);
+    text(
+        "totp",
+        |secret| crate::totp::Totp::parse(secret).map(drop),
+        errors,
+    );
+    if let Some(rule) = entry.get("match") {
+        if !["domain", "host", "prefix"]
+            .iter()
+            .any(|r| rule.as_str() == Some(r))
+        {
+            errors.push(error(
+                &format!("{path}/match"),
+                "must be one of domain, host, prefix",
+            ));
+        }
+    }
+    for key in ["created_at", "updated_at", "expires_at"] {
+        if entry.get(key).is_some_and(|time| !time.is_u64()) {
+            errors.push(error(
+                &format!("{path}/{key}"),
+                "must be a non-negative integer",
+            ));
+        }
+    }
+    if entry
+        .get("pinned")
+        .is_some_and(|pinned| !pinned.is_boolean())
+    {
+        errors.push(error(&format!("{path}/pinned"), "must be a boolean"));
+    }
+
+    match entry.get("fields") {
+        Some(Value::Object(fields)) => {
+            for (field, value) in fields {
+                let pointer = format!("{path}/fields/{}", escape(field));
+                if let Err(e) = crate::validate_field_name(field) {
+                    errors.push(error(&pointer, reason(e)));
+                }
+                if !value.is_string() {
+                    errors.push(error(&pointer, "must be a string"));
+                }
+            }
+        }
+        Some(_) => errors.push(error(&format!("{path}/fields"), "must be an object")),
+        None => {}
+    }
+
+    match entry.get("history") {
+        Some(Value::Array(history)) => {
+            for (i, old) in history.iter().enumerate() {
+                let field = format!("{path}/history/{i}");
+                let Value::Object(old) = old else {
+                    errors.push(error(&field, "must be an object"));
+                    continue;
+                };
+                check_required(&field, old, &["password", "replaced_at"], errors);
+                if old.get("password").is_some_and(|p| !p.is_string()) {
+                    errors.push(error(&format!("{field}/password"), "must be a string"));
+                }
+                if old.get("replaced_at").is_some_and(|t| !t.is_u64()) {
+                    errors.push(error(
+                        &format!("{field}/replaced_at"),
+                        "must be a non-negative integer",
+                    ));
+                }
+            }
+        }
+        Some(_) => errors.push(error(&format!("{path}/history"), "must be an array")),
+        None => {}
+    }
+
+    match entry.get("tags") {
+        Some(Value::Array(tags)) => {
+            for (i, tag) in tags.iter().enumerate() {
+                let field = format!("{path}/tags/{i}");
+                match tag {
+                    Value::String(tag) => {
+                        if let Err(e) = crate::validate_tag(tag) {
+                            errors.push(error(&field, reason(e)));
+                        }
+                    }
+                    _ => errors.push(error(&field, "must be a string")),
+                }
+                if tags[..i].contains(tag) {
+                    errors.push(error(&field, "duplicate tag"));
+                }
+            }
+        }
+        Some(_) => errors.push(error(&format!("{path}/tags"), "must be an array")),
+        None => {}
+    }
+}
+
+fn check_required(
+    path: &str,
+    object: &Map<String, Value>,
+    required: &[&str],
+    errors: &mut Vec<ValidationError>,
+) {
+    for key in required {
+        if !object.contains_key(*key) {
+            errors.push(error(path, format!("missing property '{key}'")));
+        }
+    }
+}
+
+/// Escape a property name for use in a JSON Pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn reason(err: PwError) -> String {
+    match err {
+        PwError::InvalidInput { reason, .. } => reason,
+        other => other.to_string(),
+    }
+}
+
+fn error(path: &str, message: impl Into<String>) -> ValidationError {
+    ValidationError {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(payload: &str) -> Vec<String> {
+        validate(payload.as_bytes())
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn schema_is_valid_json() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], 1);
+    }
+
+    #[test]
+    fn accepts_what_pw_writes() {
+        let entries = [crate::PasswordEntry {
+            name: "github.com".to_string(),
+            username: "alice".to_string(),
+            password: "secret".into(),
+            url: Some("https://github.com/login".to_string()),
+            url_match: crate::UrlMatch::Prefix,
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: true,
+            tags: vec!["work".to_string()],
+            fields: [("pin".to_string(), "1234".into())].into(),
+            history: vec![crate::OldPassword {
+                password: "old".into(),
+                replaced_at: 1_700_000_000,
+            }],
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }];
+        let json = crate::vault::to_json(&entries).unwrap();
+        assert_eq!(validate(json.as_bytes()), []);
+        assert_eq!(validate(br#"{"version":1,"entries":[]}"#), []);
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_path() {
+        let payload = r#"{"version": 2, "entries": [
+            {"name": "a", "username": "u\u202ex", "password": 5, "color": "red", "history": [{"password": "o"}, 3]},
+            {"name": "a", "username": "", "password": "p", "pinned": 1, "tags": ["x y", "z", "z"], "created_at": -1},
+            {"username": "", "password": "p", "url": "", "match": "exact", "expires_at": "soon", "fields": {" ": "x", "pin": 1}}
+        ], "extra": true}"#;
+        assert_eq!(
+            messages(payload),
+            [
+                "/extra: unknown property",
+                "/version: must be 1",
+                "/entries/0/username: contains bidirectional or zero-width characters",
+                "/entries/0/password: must be a string",
+                "/entries/0/history/0: missing property 'replaced_at'",
+                "/entries/0/history/1: must be an object",
+                "/entries/1/created_at: must be a non-negative integer",
+                "/entries/1/pinned: must be a boolean",
+                "/entries/1/tags/0: must not contain whitespace or commas",
+                "/entries/1/tags/2: duplicate tag",
+                "/entries/1/name: same name as /entries/0",
+                "/entries/2: missing property 'name'",
+                "/entries/2/url: must not be empty",
+                "/entries/2/match: must be one of domain, host, prefix",
+                "/entries/2/expires_at: must be a non-negative integer",
+                "/entries/2/fields/ : must not be empty",
+                "/entries/2/fields/pin: must be a string",
+            ]
+        );
+    }
+
+    #[test]
+    fn allows_unknown_entry_properties() {
+        let payload = r#"{"version": 1, "entries": [
+            {"name": "a", "username": "", "password": "p", "notes": "from a newer pw"}
+        ]}"#;
+        assert_eq!(validate(payload.as_bytes()), []);
+    }
+
+    #[test]
+    fn rejects_non_envelopes() {
+        assert_eq!(messages("[]").len(), 1);
+        assert_eq!(messages("42"), ["must be an object"]);
+        assert!(messages("{").first().unwrap().starts_with("not valid JSON"));
+        assert_eq!(
+            messages("{}"),
+            ["missing property 'version'", "missing property 'entries'"]
+        );
+    }
+}
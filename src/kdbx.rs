@@ -0,0 +1,456 @@
+//! KeePass KDBX 4 files, for `pw export --format kdbx`.
+//!
+//! The file is protected by a password only, with the AES-KDF key
+//! derivation and AES-256-CBC, which every KDBX 4 reader supports (Argon2
+//! would need a dependency pw does not have). The payload is not compressed,
+//! and no value is marked protected, so the inner random stream is declared
+//! but never used. Entries go into one group; their title, username,
+//! password, url, notes, tags, times, expiry, custom fields and TOTP secret
+//! (as the `otp` attribute KeePassXC reads) are kept. Previous passwords are
+//! left out.
+//!
+//! pw writes KDBX but does not read it. The files KeePass apps write use what
+//! [`write`] avoids: Argon2 key derivation, ChaCha20 or Twofish encryption,
+//! gzip compression and protected values, and older files are KDBX 3.1 with
+//! a Salsa20 inner stream. A reader for pw's own files only would open none
+//! of them, so [`is_keepass`] only recognizes them, for a clear error.
+
+use aes::cipher::{BlockCipherEncrypt, KeyInit};
+use aes::Aes256;
+use hmac::{Hmac, Mac};
+use rand::rngs::SysRng;
+use rand::TryRng;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::{Passphrase, PasswordEntry};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// AES-KDF rounds for [`write`]: about a second of work for an attacker's
+/// guess on a current CPU, and less than that for the reader.
+pub const DEFAULT_ROUNDS: u64 = 2_000_000;
+
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+/// Minor version 0, major version 4, as little-endian u16s.
+const VERSION: [u8; 4] = [0x00, 0x00, 0x04, 0x00];
+const AES256_CBC: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const AES_KDF: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const CHACHA20_STREAM: u32 = 3;
+const BLOCK_SIZE: usize = 1 << 20;
+/// Seconds from 0001-01-01, where KDBX times count from, to the Unix epoch.
+const UNIX_EPOCH_SECONDS: u64 = 62_135_596_800;
+
+/// The KDBX 4 file holding `entries`, encrypted with `passphrase`, with
+/// `rounds` of AES-KDF (see [`DEFAULT_ROUNDS`]).
+pub fn write(entries: &[PasswordEntry], passphrase: &Passphrase, rounds: u64) -> Vec<u8> {
+    let master_seed: [u8; 32] = random();
+    let iv: [u8; 16] = random();
+    let kdf_seed: [u8; 32] = random();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&SIGNATURE);
+    header.extend_from_slice(&VERSION);
+    let mut kdf_parameters = vec![0x00, 0x01];
+    push_variant(&mut kdf_parameters, 0x42, "$UUID", &AES_KDF);
+    push_variant(&mut kdf_parameters, 0x05, "R", &rounds.to_le_bytes());
+    push_variant(&mut kdf_parameters, 0x42, "S", &kdf_seed);
+    kdf_parameters.push(0x00);
+    push_field(&mut header, 2, &AES256_CBC);
+    push_field(&mut header, 3, &0u32.to_le_bytes());
+    push_field(&mut header, 4, &master_seed);
+    push_field(&mut header, 7, &iv);
+    push_field(&mut header, 11, &kdf_parameters);
+    push_field(&mut header, 0, b"\r\n\r\n");
+
+    let transformed = aes_kdf(passphrase, &kdf_seed, rounds);
+    let (cipher_key, hmac_key) = keys(&master_seed, &transformed);
+
+    let mut payload = Zeroizing::new(Vec::new());
+    push_inner_field(&mut payload, 1, &CHACHA20_STREAM.to_le_bytes());
+    push_inner_field(&mut payload, 2, &random::<64>());
+    push_inner_field(&mut payload, 0, &[]);
+    payload.extend_from_slice(xml(entries).as_bytes());
+    let ciphertext = aes_cbc_encrypt(&cipher_key, &iv, &payload);
+
+    let mut out = header.clone();
+    out.extend_from_slice(&Sha256::digest(&header));
+    out.extend_from_slice(&block_hmac(&hmac_key, u64::MAX, &header));
+    let blocks = ciphertext.chunks(BLOCK_SIZE).chain([&[][..]]);
+    for (index, block) in (0..).zip(blocks) {
+        let size = (block.len() as i32).to_le_bytes();
+        let mut mac = block_mac(&hmac_key, index);
+        mac.update(&index.to_le_bytes());
+        mac.update(&size);
+        mac.update(block);
+        out.extend_from_slice(&mac.finalize().into_bytes());
+        out.extend_from_slice(&size);
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+/// Whether `data` starts like a KeePass database of any version.
+pub fn is_keepass(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE[..4])
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SysRng
+        .try_fill_bytes(&mut bytes)
+        .expect("failed to read from the OS random source");
+    bytes
+}
+
+/// A header field: id, u32 length, data.
+fn push_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// An inner header field: id, i32 length, data.
+fn push_inner_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// A VariantDictionary item: type, key length and key, value length and
+/// value.
+fn push_variant(out: &mut Vec<u8>, kind: u8, key: &str, value: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(key.len() as i32).to_le_bytes());
+    out.extend_from_slice(key.as_bytes());
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// The composite key of `passphrase`, transformed by `rounds` of AES-256
+/// with `seed` as the key, then hashed.
+fn aes_kdf(passphrase: &Passphrase, seed: &[u8; 32], rounds: u64) -> Zeroizing<[u8; 32]> {
+    let composite = Sha256::digest(Sha256::digest(passphrase.as_bytes()));
+    let mut key = Zeroizing::new([0u8; 32]);
+    key.copy_from_slice(&composite);
+    let cipher = Aes256::new(seed.into());
+    for _ in 0..rounds {
+        for half in key.chunks_exact_mut(16) {
+            cipher.encrypt_block(half.try_into().expect("16-byte half"));
+        }
+    }
+    let mut transformed = Zeroizing::new([0u8; 32]);
+    transformed.copy_from_slice(&Sha256::digest(key.as_slice()));
+    transformed
+}
+
+/// The cipher key and the HMAC base key.
+fn keys(
+    master_seed: &[u8; 32],
+    transformed: &[u8; 32],
+) -> (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>) {
+    let cipher_key = Sha256::new()
+        .chain_update(master_seed)
+        .chain_update(transformed)
+        .finalize();
+    let hmac_key = Sha512::new()
+        .chain_update(master_seed)
+        .chain_update(transformed)
+        .chain_update([0x01])
+        .finalize();
+    (
+        Zeroizing::new(cipher_key.to_vec()),
+        Zeroizing::new(hmac_key.to_vec()),
+    )
+}
+
+/// The HMAC for block `index`, keyed from the HMAC base key; `u64::MAX`
+/// is the header's.
+fn block_mac(hmac_key: &[u8], index: u64) -> HmacSha256 {
+    let key = Sha512::new()
+        .chain_update(index.to_le_bytes())
+        .chain_update(hmac_key)
+        .finalize();
+    HmacSha256::new_from_slice(&key).expect("HMAC key of any length is valid")
+}
+
+fn block_hmac(hmac_key: &[u8], index: u64, data: &[u8]) -> [u8; 32] {
+    let mut mac = block_mac(hmac_key, index);
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// AES-256-CBC with PKCS#7 padding.
+fn aes_cbc_encrypt(key: &[u8], iv: &[u8; 16], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new_from_slice(key).expect("AES-256 key size is fixed");
+    let padding = 16 - plaintext.len() % 16;
+    let mut out = Vec::with_capacity(plaintext.len() + padding);
+    out.extend_from_slice(plaintext);
+    out.resize(plaintext.len() + padding, padding as u8);
+    let mut previous = *iv;
+    for block in out.chunks_exact_mut(16) {
+        for (byte, prev) in block.iter_mut().zip(previous) {
+            *byte ^= prev;
+        }
+        cipher.encrypt_block(block.try_into().expect("16-byte block"));
+        previous.copy_from_slice(block);
+    }
+    out
+}
+
+/// The KeePass XML document with `entries` in one group.
+fn xml(entries: &[PasswordEntry]) -> Zeroizing<String> {
+    let mut xml = Zeroizing::new(String::new());
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n");
+    xml.push_str("<KeePassFile><Meta><Generator>pw</Generator>");
+    xml.push_str("<DatabaseName>pw</DatabaseName></Meta><Root><Group>");
+    xml.push_str(&format!("<UUID>{}</UUID>", base64(&random::<16>())));
+    xml.push_str("<Name>pw</Name>");
+    for entry in entries {
+        xml.push_str(&format!("<Entry><UUID>{}</UUID>", base64(&random::<16>())));
+        if !entry.tags.is_empty() {
+            xml.push_str("<Tags>");
+            push_escaped(&mut xml, &entry.tags.join(";"));
+            xml.push_str("</Tags>");
+        }
+        xml.push_str("<Times>");
+        if let Some(created) = entry.created_at {
+            xml.push_str(&format!("<CreationTime>{}</CreationTime>", time(created)));
+        }
+        if let Some(updated) = entry.updated_at.or(entry.created_at) {
+            xml.push_str(&format!(
+                "<LastModificationTime>{}</LastModificationTime>",
+                time(updated)
+            ));
+        }
+        match entry.expires_at {
+            Some(expires) => xml.push_str(&format!(
+                "<Expires>True</Expires><ExpiryTime>{}</ExpiryTime>",
+                time(expires)
+            )),
+            None => xml.push_str("<Expires>False</Expires>"),
+        }
+        xml.push_str("</Times>");
+        push_string(&mut xml, "Title", &entry.name);
+        push_string(&mut xml, "UserName", &entry.username);
+        push_string(&mut xml, "Password", entry.password.expose());
+        push_string(&mut xml, "URL", entry.url.as_deref().unwrap_or_default());
+        push_string(
+            &mut xml,
+            "Notes",
+            entry.notes.as_deref().unwrap_or_default(),
+        );
+        if let Some(totp) = &entry.totp {
+            let totp = totp.expose();
+            if totp.starts_with("otpauth://") {
+                push_string(&mut xml, "otp", totp);
+            } else {
+                let uri = Zeroizing::new(format!("otpauth://totp/pw?secret={totp}"));
+                push_string(&mut xml, "otp", &uri);
+            }
+        }
+        for (name, value) in &entry.fields {
+            let key = match name.as_str() {
+                "Title" | "UserName" | "Password" | "URL" | "Notes" | "otp" => {
+                    format!("{name} (pw)")
+                }
+                _ => name.clone(),
+            };
+            push_string(&mut xml, &key, value.expose());
+        }
+        xml.push_str("</Entry>");
+    }
+    xml.push_str("</Group></Root></KeePassFile>\n");
+    xml
+}
+
+fn push_string(xml: &mut String, key: &str, value: &str) {
+    xml.push_str("<String><Key>");
+    push_escaped(xml, key);
+    xml.push_str("</Key><Value>");
+    push_escaped(xml, value);
+    xml.push_str("</Value></String>");
+}
+
+/// Append `text` to `xml` as XML character data, without an intermediate
+/// copy of what may be a secret.
+fn push_escaped(xml: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\'' => xml.push_str("&apos;"),
+            c => xml.push(c),
+        }
+    }
+}
+
+/// A Unix time as KDBX 4 writes times: base64 of the little-endian seconds
+/// since 0001-01-01.
+fn time(unix: u64) -> String {
+    base64(&(unix + UNIX_EPOCH_SECONDS).to_le_bytes())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockCipherDecrypt;
+
+    /// An independent reader for what [`write`] produces: checks the header
+    /// hash and every HMAC, decrypts, and returns the XML.
+    fn read(file: &[u8], passphrase: &Passphrase) -> String {
+        assert_eq!(file[..8], SIGNATURE);
+        assert_eq!(file[8..12], VERSION);
+        let mut pos = 12;
+        let (mut seed, mut iv, mut kdf) = (vec![], vec![], vec![]);
+        loop {
+            let id = file[pos];
+            let len = u32::from_le_bytes(file[pos + 1..pos + 5].try_into().unwrap()) as usize;
+            let data = &file[pos + 5..pos + 5 + len];
+            pos += 5 + len;
+            match id {
+                0 => break,
+                2 => assert_eq!(data, AES256_CBC),
+                3 => assert_eq!(data, [0, 0, 0, 0]),
+                4 => seed = data.to_vec(),
+                7 => iv = data.to_vec(),
+                11 => kdf = data.to_vec(),
+                _ => panic!("unexpected header field {id}"),
+            }
+        }
+        let header = &file[..pos];
+        assert_eq!(file[pos..pos + 32], Sha256::digest(header)[..]);
+        assert_eq!(kdf[..2], [0x00, 0x01]);
+        let mut params = std::collections::HashMap::new();
+        let mut at = 2;
+        while kdf[at] != 0 {
+            let int = |at: usize| i32::from_le_bytes(kdf[at..at + 4].try_into().unwrap()) as usize;
+            let key_len = int(at + 1);
+            let key = std::str::from_utf8(&kdf[at + 5..at + 5 + key_len]).unwrap();
+            let value_at = at + 5 + key_len + 4;
+            let value = &kdf[value_at..value_at + int(at + 5 + key_len)];
+            params.insert(key, value);
+            at = value_at + value.len();
+        }
+        assert_eq!(params["$UUID"], AES_KDF);
+        let rounds = u64::from_le_bytes(params["R"].try_into().unwrap());
+        let kdf_seed: [u8; 32] = params["S"].try_into().unwrap();
+        let transformed = aes_kdf(passphrase, &kdf_seed, rounds);
+        let (cipher_key, hmac_key) = keys(&seed.try_into().unwrap(), &transformed);
+        assert_eq!(
+            file[pos + 32..pos + 64],
+            block_hmac(&hmac_key, u64::MAX, header)
+        );
+
+        let mut pos = pos + 64;
+        let mut ciphertext = Vec::new();
+        for index in 0u64.. {
+            let mac = &file[pos..pos + 32];
+            let size = &file[pos + 32..pos + 36];
+            let len = i32::from_le_bytes(size.try_into().unwrap()) as usize;
+            let block = &file[pos + 36..pos + 36 + len];
+            let mut expected = block_mac(&hmac_key, index);
+            expected.update(&index.to_le_bytes());
+            expected.update(size);
+            expected.update(block);
+            expected.verify_slice(mac).expect("block HMAC");
+            pos += 36 + len;
+            if len == 0 {
+                break;
+            }
+            ciphertext.extend_from_slice(block);
+        }
+        assert_eq!(pos, file.len());
+
+        let cipher = Aes256::new_from_slice(&cipher_key).unwrap();
+        let mut previous: [u8; 16] = iv.try_into().unwrap();
+        let mut plaintext = Vec::new();
+        for block in ciphertext.chunks_exact(16) {
+            let mut decrypted: [u8; 16] = block.try_into().unwrap();
+            cipher.decrypt_block((&mut decrypted).into());
+            for (byte, prev) in decrypted.iter_mut().zip(previous) {
+                *byte ^= prev;
+            }
+            plaintext.extend_from_slice(&decrypted);
+            previous.copy_from_slice(block);
+        }
+        let padding = *plaintext.last().unwrap() as usize;
+        plaintext.truncate(plaintext.len() - padding);
+
+        // Inner header: stream id, stream key, end.
+        assert_eq!(plaintext[..5], [1, 4, 0, 0, 0]);
+        assert_eq!(plaintext[5..9], CHACHA20_STREAM.to_le_bytes());
+        assert_eq!(plaintext[9..14], [2, 64, 0, 0, 0]);
+        assert_eq!(plaintext[78..83], [0, 0, 0, 0, 0]);
+        String::from_utf8(plaintext[83..].to_vec()).unwrap()
+    }
+
+    fn entry() -> PasswordEntry {
+        let mut entry: PasswordEntry = serde_json::from_str(
+            r#"{"name": "git<hub>", "username": "alice", "password": "p&ss",
+                "url": "https://github.com", "notes": "line 1\nline 2",
+                "totp": "JBSWY3DPEHPK3PXP", "tags": ["code", "work"],
+                "fields": {"PIN": "1234", "Title": "clash"}}"#,
+        )
+        .unwrap();
+        entry.created_at = Some(0);
+        entry
+    }
+
+    #[test]
+    fn writes_a_readable_kdbx_4_file() {
+        let passphrase = Passphrase::new("correct horse".to_string());
+        let file = write(&[entry()], &passphrase, 10);
+        let xml = read(&file, &passphrase);
+        for expected in [
+            "<Tags>code;work</Tags>",
+            // 0001-01-01 to 1970-01-01, as base64 of the little-endian seconds.
+            "<CreationTime>APeRdw4AAAA=</CreationTime>",
+            "<Expires>False</Expires>",
+            "<String><Key>Title</Key><Value>git&lt;hub&gt;</Value></String>",
+            "<String><Key>UserName</Key><Value>alice</Value></String>",
+            "<String><Key>Password</Key><Value>p&amp;ss</Value></String>",
+            "<String><Key>URL</Key><Value>https://github.com</Value></String>",
+            "<String><Key>Notes</Key><Value>line 1\nline 2</Value></String>",
+            "<Key>otp</Key><Value>otpauth://totp/pw?secret=JBSWY3DPEHPK3PXP</Value>",
+            "<String><Key>PIN</Key><Value>1234</Value></String>",
+            "<String><Key>Title (pw)</Key><Value>clash</Value></String>",
+        ] {
+            assert!(xml.contains(expected), "{expected} in {xml}");
+        }
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+    }
+}
@@ -0,0 +1,458 @@
+//! Caching the vault passphrase in the Linux kernel keyring, with a timeout,
+//! so consecutive commands do not each prompt for it — a lighter-weight
+//! alternative to an agent process.
+//!
+//! It is the passphrase that is cached, not the key derived from it: every
+//! write of the scrypt format uses a fresh salt, and with it a fresh key.
+//! Keys go in the session keyring as `user` keys, described by the vault
+//! path ([`description`]), readable only by processes of the same login
+//! session (or, for processes outside any keyring session, of the same
+//! user). The kernel discards a key when its timeout expires.
+//!
+//! [`Backend`] also offers the keychain of the desktop instead: the Secret
+//! Service (GNOME Keyring, KWallet) through `secret-tool`, or the macOS
+//! Keychain through `security`. These know no timeouts, so the secret is
+//! stored with its expiry time, and an expired one is removed the next time
+//! it is looked up (or by `pw lock`).
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use zeroize::Zeroizing;
+
+/// The key description for the passphrase of `file`: `pw:` and the absolute
+/// path of the vault.
+pub fn description(file: &Path) -> String {
+    let path = std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+    format!("pw:{}", path.display())
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+
+    // From <linux/keyctl.h>; stable kernel ABI.
+    const KEY_SPEC_SESSION_KEYRING: libc::c_long = -3;
+    const KEYCTL_GET_KEYRING_ID: libc::c_long = 0;
+    const KEYCTL_SEARCH: libc::c_long = 10;
+    const KEYCTL_READ: libc::c_long = 11;
+    const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+    const KEYCTL_INVALIDATE: libc::c_long = 21;
+
+    const KEY_TYPE: &std::ffi::CStr = c"user";
+
+    fn check(ret: libc::c_long) -> io::Result<libc::c_long> {
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    fn c_description(description: &str) -> io::Result<CString> {
+        CString::new(description).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    pub fn add(description: &str, payload: &[u8], timeout_secs: u32) -> io::Result<()> {
+        let description = c_description(description)?;
+        // SAFETY: all pointers are valid for the stated lengths for the
+        // duration of the calls; the kernel copies the payload.
+        unsafe {
+            // Resolve the session keyring without asking for creation: a
+            // process without one then falls back to the user's default
+            // session keyring, shared by later commands, rather than getting
+            // a new anonymous keyring that dies with it.
+            let keyring = check(libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_GET_KEYRING_ID,
+                KEY_SPEC_SESSION_KEYRING,
+                0 as libc::c_long,
+            ))?;
+            let serial = check(libc::syscall(
+                libc::SYS_add_key,
+                KEY_TYPE.as_ptr(),
+                description.as_ptr(),
+                payload.as_ptr(),
+                payload.len(),
+                keyring,
+            ))?;
+            check(libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SET_TIMEOUT,
+                serial,
+                timeout_secs as libc::c_ulong,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// The serial of the key, or `None` if there is none.
+    pub fn search(description: &str) -> io::Result<Option<libc::c_long>> {
+        let description = c_description(description)?;
+        // SAFETY: both strings are NUL-terminated and outlive the call.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SEARCH,
+                KEY_SPEC_SESSION_KEYRING,
+                KEY_TYPE.as_ptr(),
+                description.as_ptr(),
+                0 as libc::c_long,
+            )
+        };
+        match check(ret) {
+            Ok(serial) => Ok(Some(serial)),
+            Err(e)
+                if matches!(
+                    e.raw_os_error(),
+                    Some(libc::ENOKEY | libc::EKEYEXPIRED | libc::EKEYREVOKED)
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn read(serial: libc::c_long, buf: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: the kernel writes at most `buf.len()` bytes into `buf`.
+        let len = check(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                serial,
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        })?;
+        Ok(len as usize)
+    }
+
+    pub fn invalidate(serial: libc::c_long) -> io::Result<()> {
+        // SAFETY: plain scalar arguments.
+        check(unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) })?;
+        Ok(())
+    }
+}
+
+/// Store `secret` under `description`, replacing any previous value, for
+/// `timeout` (whole seconds, at least one).
+#[cfg(target_os = "linux")]
+pub fn store(description: &str, secret: &[u8], timeout: Duration) -> io::Result<()> {
+    let secs = timeout.as_secs().clamp(1, u32::MAX.into()) as u32;
+    sys::add(description, secret, secs)
+}
+
+/// The secret stored under `description`, if there is one that has not
+/// expired.
+#[cfg(target_os = "linux")]
+pub fn load(description: &str) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+    let Some(serial) = sys::search(description)? else {
+        return Ok(None);
+    };
+    let mut buf = Zeroizing::new(vec![0; 4096]);
+    let len = sys::read(serial, &mut buf)?;
+    if len > buf.len() {
+        return Err(io::Error::other("cached secret is too large"));
+    }
+    buf.truncate(len);
+    Ok(Some(buf))
+}
+
+/// Remove the secret stored under `description`. Returns false if there was
+/// none.
+#[cfg(target_os = "linux")]
+pub fn remove(description: &str) -> io::Result<bool> {
+    let Some(serial) = sys::search(description)? else {
+        return Ok(false);
+    };
+    sys::invalidate(serial)?;
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the kernel keyring is only available on Linux",
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn store(_description: &str, _secret: &[u8], _timeout: Duration) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn load(_description: &str) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn remove(_description: &str) -> io::Result<bool> {
+    Err(unsupported())
+}
+
+/// Where cached passphrases are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The Linux kernel session keyring, as [`store`], [`load`] and
+    /// [`remove`].
+    Kernel,
+    /// The Secret Service of the desktop, through `secret-tool` from
+    /// libsecret.
+    SecretService,
+    /// The login keychain of macOS, through `security`.
+    Keychain,
+}
+
+impl Backend {
+    /// The keychain on macOS, the kernel keyring elsewhere.
+    pub fn platform_default() -> Self {
+        if cfg!(target_os = "macos") {
+            Backend::Keychain
+        } else {
+            Backend::Kernel
+        }
+    }
+
+    /// The name to use in messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Kernel => "the kernel keyring",
+            Backend::SecretService => "the Secret Service",
+            Backend::Keychain => "the macOS Keychain",
+        }
+    }
+
+    /// Store `secret` under `description`, replacing any previous value,
+    /// for `timeout`.
+    pub fn store(self, description: &str, secret: &[u8], timeout: Duration) -> io::Result<()> {
+        let expires_at = crate::unix_now() + timeout.as_secs().max(1);
+        match self {
+            Backend::Kernel => store(description, secret, timeout),
+            Backend::SecretService => {
+                let payload = wrap(secret, expires_at);
+                let label = format!("pw passphrase for {description}");
+                let args = [
+                    "store",
+                    "--label",
+                    &label,
+                    "service",
+                    "pw",
+                    "vault",
+                    description,
+                ];
+                run("secret-tool", &args, Some(payload.as_bytes()))?;
+                Ok(())
+            }
+            Backend::Keychain => {
+                let payload = wrap(secret, expires_at);
+                // A command on stdin, so that the secret is not in the
+                // arguments, where other processes can see it.
+                let command = Zeroizing::new(format!(
+                    "add-generic-password -U -s pw -a {} -w {}\n",
+                    keychain_quote(description)?,
+                    payload.as_str()
+                ));
+                run("security", &["-i"], Some(command.as_bytes()))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The secret stored under `description`, if there is one that has not
+    /// expired.
+    pub fn load(self, description: &str) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+        let output = match self {
+            Backend::Kernel => return load(description),
+            Backend::SecretService => {
+                let args = ["lookup", "service", "pw", "vault", description];
+                run_lookup("secret-tool", &args)?
+            }
+            Backend::Keychain => {
+                let args = ["find-generic-password", "-s", "pw", "-a", description, "-w"];
+                run_lookup("security", &args)?
+            }
+        };
+        let Some(payload) = output else {
+            return Ok(None);
+        };
+        match unwrap(&payload, crate::unix_now()) {
+            Some(secret) => Ok(Some(secret)),
+            None => {
+                self.remove(description)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the secret stored under `description`. Returns false if there
+    /// was none.
+    pub fn remove(self, description: &str) -> io::Result<bool> {
+        match self {
+            Backend::Kernel => remove(description),
+            Backend::SecretService => {
+                let args = ["lookup", "service", "pw", "vault", description];
+                if run_lookup("secret-tool", &args)?.is_none() {
+                    return Ok(false);
+                }
+                run(
+                    "secret-tool",
+                    &["clear", "service", "pw", "vault", description],
+                    None,
+                )?;
+                Ok(true)
+            }
+            Backend::Keychain => {
+                let args = ["delete-generic-password", "-s", "pw", "-a", description];
+                Ok(run_lookup("security", &args)?.is_some())
+            }
+        }
+    }
+}
+
+/// `secret` with its expiry time, as stored in a keychain:
+/// `<unix time>:<secret in hex>`.
+fn wrap(secret: &[u8], expires_at: u64) -> Zeroizing<String> {
+    let mut payload = Zeroizing::new(format!("{expires_at}:"));
+    for byte in secret {
+        payload.push_str(&format!("{byte:02x}"));
+    }
+    payload
+}
+
+/// The secret of a payload made by [`wrap`], if it has not expired by `now`.
+fn unwrap(payload: &[u8], now: u64) -> Option<Zeroizing<Vec<u8>>> {
+    let payload = std::str::from_utf8(payload).ok()?.trim_end();
+    let (expires_at, hex) = payload.split_once(':')?;
+    if expires_at.parse::<u64>().ok()? <= now || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .map(Zeroizing::new)
+}
+
+/// `description` quoted for a command of `security -i`.
+fn keychain_quote(description: &str) -> io::Result<String> {
+    if description.contains(['"', '\\']) || description.chars().any(char::is_control) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the vault path cannot be used in the keychain",
+        ));
+    }
+    Ok(format!("\"{description}\""))
+}
+
+/// Run `program` with `args`, and `input` on its stdin, and fail unless it
+/// succeeds.
+fn run(program: &str, args: &[&str], input: Option<&[u8]>) -> io::Result<Output> {
+    let output = spawn(program, args, input)?;
+    if !output.status.success() {
+        return Err(failure(program, &output));
+    }
+    Ok(output)
+}
+
+/// Run a lookup with `program`: its output if it succeeds, `None` if it
+/// fails quietly, as both tools do when there is no such secret.
+fn run_lookup(program: &str, args: &[&str]) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+    let mut output = spawn(program, args, None)?;
+    let stdout = Zeroizing::new(std::mem::take(&mut output.stdout));
+    if output.status.success() {
+        return Ok(Some(stdout));
+    }
+    // `security` prints that the item could not be found, and exits 44.
+    if output.stderr.is_empty() || output.status.code() == Some(44) {
+        return Ok(None);
+    }
+    Err(failure(program, &output))
+}
+
+fn spawn(program: &str, args: &[&str], input: Option<&[u8]>) -> io::Result<Output> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(io::ErrorKind::NotFound, format!("cannot find {program}"))
+            }
+            _ => io::Error::new(e.kind(), format!("cannot run {program}: {e}")),
+        })?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    Ok(output)
+}
+
+fn failure(program: &str, output: &Output) -> io::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    io::Error::other(format!(
+        "{program} failed ({}): {}",
+        output.status,
+        stderr.trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_carries_the_expiry() {
+        let payload = wrap(b"pass:phrase", 1000);
+        assert_eq!(payload.as_str(), "1000:706173733a706872617365");
+        assert_eq!(
+            unwrap(payload.as_bytes(), 999).unwrap().as_slice(),
+            b"pass:phrase"
+        );
+        assert!(unwrap(payload.as_bytes(), 1000).is_none());
+        assert!(unwrap(b"1000:abc", 0).is_none());
+        assert!(unwrap(b"secret", 0).is_none());
+        // `security find-generic-password -w` ends its output with a newline.
+        assert!(unwrap(b"1000:00\n", 0).is_some());
+    }
+
+    #[test]
+    fn keychain_descriptions_are_quoted() {
+        assert_eq!(
+            keychain_quote("pw:/home/a b/pw.scrypt").unwrap(),
+            "\"pw:/home/a b/pw.scrypt\""
+        );
+        assert!(keychain_quote("pw:/tmp/\"x").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn store_load_remove() {
+        let description = description(Path::new(&format!(
+            "/nonexistent/keyring-test-{}",
+            std::process::id()
+        )));
+        // Containers and sandboxes often deny the keyring system calls.
+        if let Err(e) = store(&description, b"secret", Duration::from_secs(60)) {
+            eprintln!("skipping: kernel keyring unavailable: {e}");
+            return;
+        }
+        assert_eq!(load(&description).unwrap().unwrap().as_slice(), b"secret");
+        store(&description, b"other", Duration::from_secs(60)).unwrap();
+        assert_eq!(load(&description).unwrap().unwrap().as_slice(), b"other");
+        assert!(remove(&description).unwrap());
+        assert!(load(&description).unwrap().is_none());
+        assert!(!remove(&description).unwrap());
+    }
+}
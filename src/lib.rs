@@ -3,20 +3,44 @@
 //! A command line password manager.
 //!
 //! Layering: [`scrypt_format`] is the pure byte codec, [`vault`] is encrypted
-//! file storage, and this module holds the domain operations. Nothing here
-//! ever prompts or assumes a terminal — the passphrase enters every operation
-//! as a [`Passphrase`] parameter, so the same functions serve the CLI and any
-//! future non-interactive host.
-
+//! file storage ([`format`] describes its JSON payload), and this module holds
+//! the domain operations. Nothing here ever prompts or assumes a terminal —
+//! the passphrase enters every operation as a [`Passphrase`] parameter, so
+//! the same functions serve the CLI and any future non-interactive host.
+
+pub mod agent;
+pub mod bitwarden;
+pub mod breach;
+pub mod crypt;
+pub mod csv;
+pub mod dsn;
+pub mod filter;
+pub mod format;
+pub mod kdbx;
+pub mod keyring;
+pub mod manifest;
+pub mod pass;
+pub mod paths;
+pub mod policy;
+pub mod safety;
+pub mod scan;
 pub mod scrypt_format;
+pub mod search;
+pub mod strength;
+pub mod totp;
 pub mod vault;
+pub mod yubikey;
 
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::path::{Path, PathBuf};
 
+use icu_normalizer::properties::CanonicalCombiningClassMapBorrowed;
+use icu_normalizer::{ComposingNormalizerBorrowed, DecomposingNormalizerBorrowed};
 use rand::rngs::SysRng;
-use rand::{RngExt, SeedableRng};
+use rand::{CryptoRng, RngExt, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
 use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
@@ -26,8 +50,19 @@ pub use vault::Passphrase;
 
 /// Longest accepted entry name or username, in characters.
 pub const MAX_NAME_LEN: usize = 256;
+/// Longest accepted notes of an entry, in characters.
+pub const MAX_NOTES_LEN: usize = 8192;
+/// The characters of generated passwords unless others are asked for.
+pub const DEFAULT_CHARSET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
+/// Length of the key files [`generate_key_file`] makes, in bytes.
+pub const KEY_FILE_LEN: usize = 64;
 /// Longest password [`generate_password`] will produce.
 pub const MAX_PASSWORD_LEN: u32 = 1024;
+/// Shortest password [`password_hint`] reveals any characters of.
+pub const HINT_MIN_LEN: usize = 8;
+/// Most previous passwords an entry keeps in its `history`; older ones are
+/// forgotten.
+pub const MAX_HISTORY_LEN: usize = 10;
 
 #[derive(thiserror::Error, Debug)]
 pub enum PwError {
@@ -88,6 +123,61 @@ impl fmt::Debug for Secret {
     }
 }
 
+/// A valid entry name: trimmed of surrounding whitespace, normalized to
+/// Unicode NFC, then checked by [`validate_name`]. The operations that look
+/// entries up by name take one, so an invalid name is rejected before any
+/// vault is opened; the CLI parses its name arguments into one with
+/// [`FromStr`](std::str::FromStr).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryName(String);
+
+impl EntryName {
+    pub fn new(name: impl Into<String>) -> Result<Self, PwError> {
+        let name = name.into();
+        let name = nfc(name.trim()).into_owned();
+        validate_name(&name)?;
+        Ok(EntryName(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for EntryName {
+    type Err = PwError;
+
+    fn from_str(name: &str) -> Result<Self, PwError> {
+        EntryName::new(name)
+    }
+}
+
+impl TryFrom<String> for EntryName {
+    type Error = PwError;
+
+    fn try_from(name: String) -> Result<Self, PwError> {
+        EntryName::new(name)
+    }
+}
+
+impl From<EntryName> for String {
+    fn from(name: EntryName) -> Self {
+        name.0
+    }
+}
+
+impl AsRef<str> for EntryName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for EntryName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
 pub struct PasswordEntry {
     pub name: String,
@@ -105,6 +195,315 @@ pub struct PasswordEntry {
     /// format.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    /// How `url` is matched against a site; see [`UrlMatch`]. Not serialized
+    /// when it is the default.
+    #[serde(
+        default,
+        rename = "match",
+        skip_serializing_if = "UrlMatch::is_default"
+    )]
+    #[zeroize(skip)]
+    pub url_match: UrlMatch,
+    /// Command template run by `pw launch`; see [`launch_argv`]. Not
+    /// serialized when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch: Option<String>,
+    /// Free-form, possibly multi-line text, such as security questions or
+    /// recovery codes. Not serialized when absent; entries written before
+    /// this field existed load without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// TOTP secret for `pw otp`: an `otpauth://totp/…` URI or a base32 key;
+    /// see [`totp::Totp::parse`]. Not serialized when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<Secret>,
+    /// Listed before other entries. Not serialized when false.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Free-form labels for grouping entries (`work`, `shared`, …), kept
+    /// sorted and without duplicates. Not serialized when empty, so untagged
+    /// entries stay byte-identical to the pre-`tags` format.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Custom secrets by field name, such as a PIN or recovery codes, read
+    /// with `pw get --field`. The values are zeroized on drop like the
+    /// password. Not serialized when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[zeroize(skip)]
+    pub fields: BTreeMap<String, Secret>,
+    /// Passwords the entry had before, oldest first, at most
+    /// [`MAX_HISTORY_LEN`] of them. [`update`] adds the replaced password
+    /// when it changes. Not serialized when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<OldPassword>,
+    /// When [`add`] created the entry, in seconds since the Unix epoch. Set
+    /// by pw; absent on entries created before pw recorded it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// When [`add`], [`update`] or [`update_keep_password`] last changed the
+    /// entry, in seconds since the Unix epoch. Set by pw; absent on entries
+    /// not changed since before pw recorded it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    /// When the password is due for rotation, in seconds since the Unix
+    /// epoch; see `pw audit --expiring`. Kept by [`update`] unless the new
+    /// entry sets it. Not serialized when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Fields this version of pw does not know, written by a newer version or
+    /// another tool. Kept as-is and written back, so rewriting the vault does
+    /// not silently drop them. Unlike the fields above, these are plain JSON
+    /// values and are not zeroized on drop: a secret that a newer version
+    /// stores in a field of its own lingers in memory here until overwritten.
+    /// The known fields are still read straight into their own types, never
+    /// buffered alongside these.
+    #[serde(flatten)]
+    #[zeroize(skip)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A password an entry had before, in its `history`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct OldPassword {
+    pub password: Secret,
+    /// When it was replaced, in seconds since the Unix epoch.
+    pub replaced_at: u64,
+}
+
+/// Keep `old`, replaced at `now`, in `history`, unless it is the same as
+/// `new`.
+fn remember_password(history: &mut Vec<OldPassword>, old: &Secret, new: &Secret, now: u64) {
+    if old == new {
+        return;
+    }
+    history.push(OldPassword {
+        password: old.clone(),
+        replaced_at: now,
+    });
+    if history.len() > MAX_HISTORY_LEN {
+        history.drain(..history.len() - MAX_HISTORY_LEN);
+    }
+}
+
+/// How an entry's `url` is matched against a site, by [`match_url`] and
+/// [`matching_entries`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlMatch {
+    /// The url's host, or any subdomain of it within the same registrable
+    /// domain.
+    #[default]
+    Domain,
+    /// The url's host exactly.
+    Host,
+    /// The url's host exactly, and paths starting with the url's path at a
+    /// segment boundary. A bare hostname alone gives no path, so it cannot
+    /// be checked, and matches like [`UrlMatch::Host`].
+    Prefix,
+}
+
+impl UrlMatch {
+    /// The name used in the vault and on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UrlMatch::Domain => "domain",
+            UrlMatch::Host => "host",
+            UrlMatch::Prefix => "prefix",
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == UrlMatch::Domain
+    }
+}
+
+/// The editable fields of an entry, for [`apply_edits`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(deny_unknown_fields)]
+pub struct EntryEdit {
+    pub name: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(
+        default,
+        rename = "match",
+        skip_serializing_if = "UrlMatch::is_default"
+    )]
+    #[zeroize(skip)]
+    pub url_match: UrlMatch,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The new password; `None` keeps the current one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<Secret>,
+    /// The new notes, empty to remove them; `None` keeps the current ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// The new custom fields; `None` keeps the current ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[zeroize(skip)]
+    pub fields: Option<BTreeMap<String, Secret>>,
+}
+
+impl EntryEdit {
+    /// The current state of `entry`, including its password only if
+    /// `with_password` is set.
+    pub fn of(entry: &PasswordEntry, with_password: bool) -> Self {
+        EntryEdit {
+            name: entry.name.clone(),
+            username: entry.username.clone(),
+            url: entry.url.clone(),
+            url_match: entry.url_match,
+            pinned: entry.pinned,
+            tags: entry.tags.clone(),
+            password: with_password.then(|| entry.password.clone()),
+            notes: None,
+            fields: None,
+        }
+    }
+
+    /// The current state of `entry` with everything editable, including its
+    /// password, notes and custom fields, for editing a single entry.
+    pub fn whole(entry: &PasswordEntry) -> Self {
+        let mut edit = EntryEdit::of(entry, true);
+        edit.notes = Some(entry.notes.clone().unwrap_or_default());
+        edit.fields = Some(entry.fields.clone());
+        edit
+    }
+}
+
+/// The non-secret metadata of an entry, for documentation and inventories.
+/// It has no password field, so nothing built from it can leak one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InventoryItem {
+    pub name: String,
+    pub username: String,
+    pub url: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl From<&PasswordEntry> for InventoryItem {
+    fn from(entry: &PasswordEntry) -> Self {
+        InventoryItem {
+            name: entry.name.clone(),
+            username: entry.username.clone(),
+            url: entry.url.clone(),
+            tags: entry.tags.clone(),
+        }
+    }
+}
+
+/// What [`import`] reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// A vault payload such as [`export`] returns
+    #[default]
+    Vault,
+    /// An unencrypted Bitwarden JSON export, see [`bitwarden`]
+    Bitwarden,
+    /// A 1Password CSV export, see [`csv`]
+    OnePasswordCsv,
+    /// A LastPass CSV export, see [`csv`]
+    LastPassCsv,
+    /// A pass store. It is a directory of gpg-encrypted files rather than
+    /// one document, so [`import`] cannot read it: decrypt the files found
+    /// by [`pass::files`], convert them with [`pass::entry`] and pass the
+    /// entries to [`import_entries`].
+    Pass,
+}
+
+impl ImportFormat {
+    /// Where entries in this format come from, for `{source}` in
+    /// [`ImportOptions::rename`].
+    pub fn source(self) -> &'static str {
+        match self {
+            ImportFormat::Vault => "pw",
+            ImportFormat::Bitwarden => "bitwarden",
+            ImportFormat::OnePasswordCsv => "1password",
+            ImportFormat::LastPassCsv => "lastpass",
+            ImportFormat::Pass => "pass",
+        }
+    }
+}
+
+/// What [`import`] reads, and how it treats entries like ones already in
+/// the vault.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportOptions<'a> {
+    pub format: ImportFormat,
+    /// Replace an entry with the same name instead of failing.
+    pub replace: bool,
+    /// Instead of failing, give an entry whose name is taken a new name from
+    /// this pattern, in which `{name}` is its name, `{source}` is
+    /// [`ImportFormat::source`] and `{n}` counts up from 2 until the name is
+    /// free. A pattern without `{n}` gets `-{n}` appended when its first
+    /// result is taken too. `replace` wins over this.
+    pub rename: Option<&'a str>,
+    /// Import entries with the same username, password and url as one
+    /// already in the vault, instead of skipping them.
+    pub keep_duplicates: bool,
+    /// Check the import and report what it would do, without writing.
+    pub dry_run: bool,
+}
+
+/// What [`import`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Entries added or replaced
+    pub imported: usize,
+    /// Entries skipped as duplicates of ones in the vault
+    pub duplicates: usize,
+    /// Items skipped because pw cannot store their kind, such as Bitwarden
+    /// cards
+    pub unsupported: usize,
+    /// The entries given a new name by [`ImportOptions::rename`], as (name
+    /// in the input, name in the vault)
+    pub renamed: Vec<(String, String)>,
+    /// The names of the entries added or replaced, in input order
+    pub names: Vec<String>,
+}
+
+/// What [`apply`] did, or would do on a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyReport {
+    /// The declared entries that were missing and have been created, in
+    /// manifest order
+    pub created: Vec<String>,
+    /// The declared entries that exist but differ from the manifest, with
+    /// how (see [`manifest::ManifestEntry::drift`])
+    pub drift: Vec<(String, Vec<String>)>,
+    /// The declared entries that exist and match the manifest
+    pub in_sync: usize,
+}
+
+/// A bulk change of entry metadata for [`rewrite`], such as after moving to
+/// a new email address or domain. Each is a pair of what to match and what
+/// to put in its place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rewrite {
+    /// Replace usernames equal to the first with the second.
+    pub username: Option<(String, String)>,
+    /// Move urls whose host is the first domain, or a subdomain of it, to
+    /// the second, keeping any subdomain, scheme, port and path. Domains
+    /// are compared case-insensitively, after IDNA normalization.
+    pub domain: Option<(String, String)>,
+}
+
+/// Complete or roll back a write of `file` that was interrupted by a crash,
+/// as [`vault::recover`]; writing does this anyway, so this is for tools
+/// that start by reading.
+pub fn recover(file: &Path) -> Result<vault::Recovery, PwError> {
+    vault::recover(file).map_err(|e| vault_err(file, e))
+}
+
+/// What the next write of `file` will recover, as
+/// [`vault::pending_recovery`], without changing anything.
+pub fn pending_recovery(file: &Path) -> Result<vault::Recovery, PwError> {
+    vault::pending_recovery(file).map_err(|e| vault_err(file, e))
 }
 
 /// Create a new empty vault. Fails if the file already exists.
@@ -115,12 +514,42 @@ pub fn init(file: &Path, passphrase: &Passphrase, params: &Params) -> Result<(),
     store(file, passphrase, &[], params)
 }
 
+/// The scrypt parameters of the vault at `file`, read without the
+/// passphrase, e.g. to tell whether [`rekey`] with other parameters would
+/// make it stronger.
+pub fn vault_params(file: &Path) -> Result<Params, PwError> {
+    if !file.exists() {
+        return Err(PwError::FileNotFound(file.to_path_buf()));
+    }
+    vault::params(file).map_err(|e| vault_err(file, e))
+}
+
+/// Re-encrypt the vault with `new_passphrase`, and with `params`, so this
+/// also moves an old vault to the current scrypt parameters. The write is
+/// the usual atomic one, but keeps no backup: [`vault::backup_path`] is
+/// removed, as it would still open with the old passphrase. Returns the
+/// number of entries.
+pub fn rekey(
+    file: &Path,
+    passphrase: &Passphrase,
+    new_passphrase: &Passphrase,
+    params: &Params,
+) -> Result<usize, PwError> {
+    let mut vault = Vault::open(file, passphrase)?;
+    vault.rekey(new_passphrase, params)?;
+    Ok(vault.entries.len())
+}
+
 /// Look up the entry named `name`.
-pub fn get(file: &Path, passphrase: &Passphrase, name: &str) -> Result<PasswordEntry, PwError> {
+pub fn get(
+    file: &Path,
+    passphrase: &Passphrase,
+    name: &EntryName,
+) -> Result<PasswordEntry, PwError> {
     let entries = load(file, passphrase)?;
     entries
         .into_iter()
-        .find(|e| e.name == name)
+        .find(|e| same_name(&e.name, name.as_str()))
         .ok_or_else(|| PwError::NotFound {
             name: name.to_string(),
             file: file.to_path_buf(),
@@ -139,235 +568,1381 @@ pub fn add(
     new_entry: PasswordEntry,
     params: &Params,
 ) -> Result<(), PwError> {
-    validate_entry(&new_entry)?;
-    let mut entries = load(file, passphrase)?;
-    if entries.iter().any(|e| e.name == new_entry.name) {
-        return Err(PwError::AlreadyExists {
-            name: new_entry.name.clone(),
-            file: file.to_path_buf(),
-        });
-    }
-    entries.push(new_entry);
-    store(file, passphrase, &entries, params)
+    transaction(file, passphrase, params, |tx| tx.add(new_entry))
 }
 
-/// Replace the username, password and `url` of an existing entry.
+/// Replace the username, password and `url` of an existing entry. Its tags,
+/// launch command, notes, whether it is pinned, and any fields unknown to
+/// this version of pw, are kept. A replaced password is added to its
+/// `history`.
 pub fn update(
     file: &Path,
     passphrase: &Passphrase,
     new_entry: PasswordEntry,
     params: &Params,
 ) -> Result<(), PwError> {
-    validate_entry(&new_entry)?;
-    let mut entries = load(file, passphrase)?;
-    let Some(entry) = entries.iter_mut().find(|e| e.name == new_entry.name) else {
-        return Err(PwError::NotFound {
-            name: new_entry.name.clone(),
-            file: file.to_path_buf(),
-        });
-    };
-    *entry = new_entry;
-    store(file, passphrase, &entries, params)
+    transaction(file, passphrase, params, |tx| tx.update(new_entry))
 }
 
-/// Replace the username and `url` of an existing entry while keeping its
-/// current password, so the user can re-point an entry at another site (or
-/// relabel it) without rotating the secret. Fails if no entry is named `name`.
+/// Replace the username, `url` and `url_match` of an existing entry while
+/// keeping its current password, so the user can re-point an entry at
+/// another site (or relabel it) without rotating the secret. Fails if no
+/// entry is named `name`.
 pub fn update_keep_password(
     file: &Path,
     passphrase: &Passphrase,
-    name: &str,
+    name: &EntryName,
     username: String,
     url: Option<String>,
+    url_match: UrlMatch,
     params: &Params,
 ) -> Result<(), PwError> {
-    validate_name(name)?;
-    validate_username(&username)?;
-    if let Some(url) = &url {
-        validate_url(url)?;
-    }
-    let mut entries = load(file, passphrase)?;
-    let Some(entry) = entries.iter_mut().find(|e| e.name == name) else {
-        return Err(PwError::NotFound {
-            name: name.to_string(),
-            file: file.to_path_buf(),
-        });
-    };
-    entry.username = username;
-    entry.url = url;
-    store(file, passphrase, &entries, params)
+    transaction(file, passphrase, params, |tx| {
+        tx.update_keep_password(name, username, url, url_match)
+    })
 }
 
-/// Remove the entry named `name`.
-pub fn remove(
+/// Rename the entry named `old` to `new`, keeping everything else about it.
+/// Fails with [`PwError::AlreadyExists`] if another entry is named `new`.
+pub fn rename(
     file: &Path,
     passphrase: &Passphrase,
-    name: &str,
+    old: &EntryName,
+    new: &EntryName,
     params: &Params,
 ) -> Result<(), PwError> {
-    let mut entries = load(file, passphrase)?;
-    let original_len = entries.len();
-    entries.retain(|e| e.name != name);
-    if entries.len() == original_len {
-        return Err(PwError::NotFound {
-            name: name.to_string(),
-            file: file.to_path_buf(),
-        });
-    }
-    store(file, passphrase, &entries, params)
-}
-
-/// The decrypted vault as JSON (the same envelope that is stored encrypted),
-/// for backup and migration.
-pub fn export(file: &Path, passphrase: &Passphrase) -> Result<Zeroizing<String>, PwError> {
-    let entries = load(file, passphrase)?;
-    vault::to_json(&entries).map_err(|e| vault_err(file, e))
-}
-
-fn load(file: &Path, passphrase: &Passphrase) -> Result<Vec<PasswordEntry>, PwError> {
-    if !file.exists() {
-        return Err(PwError::FileNotFound(file.to_path_buf()));
-    }
-    vault::load(file, passphrase).map_err(|e| vault_err(file, e))
+    transaction(file, passphrase, params, |tx| tx.rename(old, new))
 }
 
-fn store(
+/// Remove the entry named `name`.
+pub fn remove(
     file: &Path,
     passphrase: &Passphrase,
-    entries: &[PasswordEntry],
+    name: &EntryName,
     params: &Params,
 ) -> Result<(), PwError> {
-    vault::store(file, passphrase, entries, params).map_err(|e| vault_err(file, e))
+    transaction(file, passphrase, params, |tx| tx.remove(name).map(drop))
 }
 
-fn vault_err(file: &Path, err: vault::Error) -> PwError {
-    match err {
-        vault::Error::Format(scrypt_format::Error::WrongPassphrase) => PwError::WrongPassphrase,
-        e @ (vault::Error::Read { .. } | vault::Error::Write { .. }) => PwError::Io(e),
-        e => PwError::CorruptVault {
-            file: file.to_path_buf(),
-            source: e,
-        },
-    }
+/// Load the vault, let `f` change its entries in memory through a
+/// [`Vault`], and write the result once. If `f` fails, nothing is written and
+/// its error is returned; if it changes nothing, the vault is not rewritten
+/// either.
+pub fn transaction<T>(
+    file: &Path,
+    passphrase: &Passphrase,
+    params: &Params,
+    f: impl FnOnce(&mut Vault) -> Result<T, PwError>,
+) -> Result<T, PwError> {
+    let mut vault = Vault::open(file, passphrase)?;
+    let value = f(&mut vault)?;
+    vault.commit(passphrase, params)?;
+    Ok(value)
 }
 
-/// Returns true for Unicode bidirectional control and zero-width code points
-/// that can reorder or disguise how text renders without being "control"
-/// characters per [`char::is_control`] (a Trojan-Source / homoglyph style
-/// display spoof). Used both to reject such characters on input and to
-/// replace them on output.
-pub fn is_display_spoofing_char(c: char) -> bool {
-    matches!(c,
-        // Bidirectional embeddings, overrides and isolates
-        '\u{202A}'..='\u{202E}'   // LRE, RLE, PDF, LRO, RLO
-        | '\u{2066}'..='\u{2069}' // LRI, RLI, FSI, PDI
-        // Bidirectional marks
-        | '\u{200E}' | '\u{200F}' // LRM, RLM
-        | '\u{061C}'              // Arabic Letter Mark
-        // Zero-width characters
-        | '\u{200B}'              // Zero Width Space
-        | '\u{200C}' | '\u{200D}' // ZWNJ, ZWJ
-        | '\u{2060}'              // Word Joiner
-        | '\u{FEFF}'              // Zero Width No-Break Space / BOM
-    )
+/// The decrypted entries of a vault, held in memory so that a caller can
+/// read and change them without a decrypt and encrypt cycle per operation.
+/// Changes are written only by [`Vault::commit`]; dropping a `Vault`
+/// discards them. Each operation validates and fails exactly like its
+/// single-write counterpart, and leaves the entries untouched when it does.
+pub struct Vault {
+    file: PathBuf,
+    entries: Vec<PasswordEntry>,
+    changed: bool,
 }
 
-/// Entry names must be non-empty, at most [`MAX_NAME_LEN`] characters and
-/// free of control, bidirectional and zero-width characters. Everything a
-/// hostname can contain is allowed.
-pub fn validate_name(name: &str) -> Result<(), PwError> {
-    if name.is_empty() {
-        return Err(PwError::InvalidInput {
-            what: "entry name",
-            reason: "must not be empty".to_string(),
-        });
+impl Vault {
+    /// Decrypt the vault at `file`.
+    pub fn open(file: &Path, passphrase: &Passphrase) -> Result<Self, PwError> {
+        Ok(Vault {
+            file: file.to_path_buf(),
+            entries: load(file, passphrase)?,
+            changed: false,
+        })
     }
-    validate_text("entry name", name)
-}
 
-/// Usernames may be empty, but obey the same length and character rules as
-/// entry names.
-pub fn validate_username(username: &str) -> Result<(), PwError> {
-    validate_text("username", username)
-}
+    /// Write the changes made since the vault was opened or last committed,
+    /// encrypted with `passphrase`. Does nothing if there are none.
+    pub fn commit(&mut self, passphrase: &Passphrase, params: &Params) -> Result<(), PwError> {
+        if self.changed {
+            store(&self.file, passphrase, &self.entries, params)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
 
-/// The optional `url` matching hint, when present, must be non-empty and obey
-/// the same length and character rules as entry names. Callers map "no url"
-/// to `None`, so an empty string is rejected rather than stored.
-pub fn validate_url(url: &str) -> Result<(), PwError> {
-    if url.is_empty() {
-        return Err(PwError::InvalidInput {
-            what: "url",
-            reason: "must not be empty".to_string(),
-        });
+    /// Write the vault, with any changes, encrypted with `new_passphrase`,
+    /// removing the backup, as [`rekey`] does. From then on, commit with
+    /// `new_passphrase`.
+    pub fn rekey(&mut self, new_passphrase: &Passphrase, params: &Params) -> Result<(), PwError> {
+        vault::store_rekeyed(&self.file, new_passphrase, &self.entries, params)
+            .map_err(|e| vault_err(&self.file, e))?;
+        self.changed = false;
+        Ok(())
     }
-    validate_text("url", url)
-}
 
-/// Validate the user-supplied fields of an entry before it is stored.
-fn validate_entry(entry: &PasswordEntry) -> Result<(), PwError> {
-    validate_name(&entry.name)?;
-    validate_username(&entry.username)?;
-    if let Some(url) = &entry.url {
-        validate_url(url)?;
+    /// Whether there are changes that [`Vault::commit`] would write.
+    pub fn is_changed(&self) -> bool {
+        self.changed
     }
-    Ok(())
-}
 
-fn validate_text(what: &'static str, value: &str) -> Result<(), PwError> {
-    if value.chars().count() > MAX_NAME_LEN {
-        return Err(PwError::InvalidInput {
-            what,
-            reason: format!("longer than {MAX_NAME_LEN} characters"),
-        });
+    /// All entries, including changes made so far.
+    pub fn entries(&self) -> &[PasswordEntry] {
+        &self.entries
     }
-    if value.chars().any(char::is_control) {
-        return Err(PwError::InvalidInput {
-            what,
-            reason: "contains control characters".to_string(),
-        });
+
+    /// The entry named `name`, if any.
+    pub fn get(&self, name: &EntryName) -> Option<&PasswordEntry> {
+        self.entries
+            .iter()
+            .find(|e| same_name(&e.name, name.as_str()))
     }
-    if value.chars().any(is_display_spoofing_char) {
-        return Err(PwError::InvalidInput {
-            what,
-            reason: "contains bidirectional or zero-width characters".to_string(),
-        });
+
+    /// Add a new entry, as [`add`].
+    pub fn add(&mut self, mut new_entry: PasswordEntry) -> Result<(), PwError> {
+        new_entry.name = EntryName::new(std::mem::take(&mut new_entry.name))?.0;
+        validate_entry(&new_entry)?;
+        if self
+            .entries
+            .iter()
+            .any(|e| same_name(&e.name, &new_entry.name))
+        {
+            return Err(PwError::AlreadyExists {
+                name: new_entry.name.clone(),
+                file: self.file.clone(),
+            });
+        }
+        let now = unix_now();
+        new_entry.created_at = Some(now);
+        new_entry.updated_at = Some(now);
+        self.entries.push(new_entry);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Add `entries` as they are, timestamps and history included, as
+    /// [`import`].
+    pub fn import(
+        &mut self,
+        entries: Vec<PasswordEntry>,
+        options: ImportOptions<'_>,
+    ) -> Result<ImportReport, PwError> {
+        if let Some(pattern) = options.rename {
+            if !pattern.contains("{name}") {
+                return Err(PwError::InvalidInput {
+                    what: "rename pattern",
+                    reason: "must contain {name}".to_string(),
+                });
+            }
+        }
+        let mut counts = ImportReport::default();
+        let mut accepted: Vec<PasswordEntry> = Vec::new();
+        for mut entry in entries {
+            entry.name = EntryName::new(std::mem::take(&mut entry.name))?.0;
+            validate_entry(&entry)?;
+            let same_login = |e: &PasswordEntry| {
+                e.username == entry.username && e.password == entry.password && e.url == entry.url
+            };
+            if !options.keep_duplicates
+                && (self.entries.iter().any(same_login) || accepted.iter().any(same_login))
+            {
+                counts.duplicates += 1;
+                continue;
+            }
+            let taken = |name: &str| {
+                accepted.iter().any(|e| same_name(&e.name, name))
+                    || !options.replace && self.entries.iter().any(|e| same_name(&e.name, name))
+            };
+            if taken(&entry.name) {
+                let Some(pattern) = options.rename.filter(|_| !options.replace) else {
+                    return Err(PwError::AlreadyExists {
+                        name: entry.name.clone(),
+                        file: self.file.clone(),
+                    });
+                };
+                let source = options.format.source();
+                let new_name = (0..)
+                    .map(|n| renamed(pattern, &entry.name, source, n))
+                    .find(|name| !taken(name))
+                    .expect("{n} makes every candidate distinct");
+                validate_name(&new_name)?;
+                let old_name = std::mem::replace(&mut entry.name, new_name);
+                counts.renamed.push((old_name, entry.name.clone()));
+            }
+            accepted.push(entry);
+        }
+        counts.imported = accepted.len();
+        counts.names = accepted.iter().map(|e| e.name.clone()).collect();
+        for entry in accepted {
+            match self
+                .entries
+                .iter()
+                .position(|e| same_name(&e.name, &entry.name))
+            {
+                Some(index) => self.entries[index] = entry,
+                None => self.entries.push(entry),
+            }
+        }
+        self.changed |= counts.imported > 0;
+        Ok(counts)
+    }
+
+    /// Replace an existing entry, as [`update`].
+    pub fn update(&mut self, mut new_entry: PasswordEntry) -> Result<(), PwError> {
+        new_entry.name = EntryName::new(std::mem::take(&mut new_entry.name))?.0;
+        validate_entry(&new_entry)?;
+        let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|e| same_name(&e.name, &new_entry.name))
+        else {
+            return Err(PwError::NotFound {
+                name: new_entry.name.clone(),
+                file: self.file.clone(),
+            });
+        };
+        new_entry.launch = entry.launch.take();
+        new_entry.notes = entry.notes.take();
+        new_entry.totp = entry.totp.take();
+        new_entry.pinned = entry.pinned;
+        new_entry.tags = std::mem::take(&mut entry.tags);
+        new_entry.fields = std::mem::take(&mut entry.fields);
+        let now = unix_now();
+        new_entry.history = std::mem::take(&mut entry.history);
+        remember_password(
+            &mut new_entry.history,
+            &entry.password,
+            &new_entry.password,
+            now,
+        );
+        new_entry.created_at = entry.created_at;
+        new_entry.updated_at = Some(now);
+        new_entry.expires_at = new_entry.expires_at.or(entry.expires_at);
+        new_entry.extra = std::mem::take(&mut entry.extra);
+        *entry = new_entry;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Replace the username, `url` and `url_match` of an existing entry, as
+    /// [`update_keep_password`].
+    pub fn update_keep_password(
+        &mut self,
+        name: &EntryName,
+        username: String,
+        url: Option<String>,
+        url_match: UrlMatch,
+    ) -> Result<(), PwError> {
+        validate_username(&username)?;
+        if let Some(url) = &url {
+            validate_url(url)?;
+        }
+        let entry = self.entry_mut(name)?;
+        entry.username = username;
+        entry.url = url;
+        entry.url_match = url_match;
+        entry.updated_at = Some(unix_now());
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Make the password of the entry named `name` the one it had `back`
+    /// changes ago: 1 is the password before the current one. The current
+    /// password goes into the history, so restoring can be undone the same
+    /// way.
+    pub fn restore_password(&mut self, name: &EntryName, back: usize) -> Result<(), PwError> {
+        let entry = self.entry_mut(name)?;
+        let len = entry.history.len();
+        if back == 0 || back > len {
+            return Err(PwError::InvalidInput {
+                what: "history position",
+                reason: match len {
+                    0 => "the entry has no previous passwords".to_string(),
+                    1 => "the entry has only 1 previous password".to_string(),
+                    len => format!("must be from 1 to {len}"),
+                },
+            });
+        }
+        let old = entry.history.remove(len - back);
+        let now = unix_now();
+        remember_password(&mut entry.history, &entry.password, &old.password, now);
+        entry.password = old.password.clone();
+        entry.updated_at = Some(now);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Set or, with `None`, remove the notes of the entry named `name`.
+    pub fn set_notes(&mut self, name: &EntryName, notes: Option<String>) -> Result<(), PwError> {
+        if let Some(notes) = &notes {
+            validate_notes(notes)?;
+        }
+        self.entry_mut(name)?.notes = notes;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Tag the entry named `name` with `tag`, as [`add_tag`]. Tags are kept
+    /// sorted.
+    pub fn add_tag(&mut self, name: &EntryName, tag: &str) -> Result<bool, PwError> {
+        validate_tag(tag)?;
+        let entry = self.entry_mut(name)?;
+        let Err(pos) = entry.tags.binary_search_by(|t| t.as_str().cmp(tag)) else {
+            return Ok(false);
+        };
+        entry.tags.insert(pos, tag.to_string());
+        self.changed = true;
+        Ok(true)
     }
-    Ok(())
-}
 
-/// Extract the hostname from a web origin, applying the eligibility rules of
-/// the browser integration: only `https:`
-/// origins are accepted, plus `http://localhost` and `http://127.0.0.1` for
-/// local development. The returned hostname is IDNA/punycode-normalized and
-/// lowercased. Any ineligible or unparsable origin yields `None` (mapped to
-/// the `invalid-origin` error by the browser host); no other scheme,
-/// including `http:` on a real host, `file:` or `moz-extension:`, is accepted.
-pub fn origin_hostname(origin: &str) -> Option<String> {
-    let (scheme, rest) = origin.split_once("://")?;
-    let host = normalize_host(host_from_authority(rest))?;
-    match scheme.to_ascii_lowercase().as_str() {
-        "https" => Some(host),
-        "http" if host == "localhost" || host == "127.0.0.1" => Some(host),
-        _ => None,
+    /// Remove `tag` from the entry named `name`, as [`remove_tag`].
+    pub fn remove_tag(&mut self, name: &EntryName, tag: &str) -> Result<bool, PwError> {
+        let entry = self.entry_mut(name)?;
+        let Some(pos) = entry.tags.iter().position(|t| t == tag) else {
+            return Ok(false);
+        };
+        entry.tags.remove(pos);
+        self.changed = true;
+        Ok(true)
     }
-}
 
-/// Reduce the part of a URL after `scheme://` to its bare host: drop any
+    /// Rename the tag `old` to `new` on every entry that has it, as
+    /// [`rename_tag`].
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> Result<usize, PwError> {
+        validate_tag(new)?;
+        if old == new {
+            return Ok(0);
+        }
+        let mut changed = 0;
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| e.tags.iter().any(|t| t == old))
+        {
+            entry.tags.retain(|t| t != old);
+            if let Err(pos) = entry.tags.binary_search_by(|t| t.as_str().cmp(new)) {
+                entry.tags.insert(pos, new.to_string());
+            }
+            changed += 1;
+        }
+        self.changed |= changed > 0;
+        Ok(changed)
+    }
+
+    /// Apply `edits`, keyed by the current name of the entry each one
+    /// replaces, as [`apply_edits`].
+    pub fn apply_edits(&mut self, edits: BTreeMap<String, EntryEdit>) -> Result<usize, PwError> {
+        let mut indexed = Vec::with_capacity(edits.len());
+        for (name, mut edit) in edits {
+            let Some(index) = self.entries.iter().position(|e| same_name(&e.name, &name)) else {
+                return Err(PwError::NotFound {
+                    name,
+                    file: self.file.clone(),
+                });
+            };
+            edit.name = EntryName::new(std::mem::take(&mut edit.name))?.0;
+            validate_name(&edit.name)?;
+            validate_username(&edit.username)?;
+            if let Some(url) = &edit.url {
+                validate_url(url)?;
+            }
+            for tag in &edit.tags {
+                validate_tag(tag)?;
+            }
+            if let Some(notes) = &edit.notes {
+                validate_notes(notes)?;
+            }
+            for field in edit.fields.iter().flat_map(BTreeMap::keys) {
+                validate_field_name(field)?;
+            }
+            edit.tags.sort();
+            edit.tags.dedup();
+            indexed.push((index, edit));
+        }
+
+        let mut names: Vec<&str> = self.entries.iter().map(|e| e.name.as_str()).collect();
+        for (index, edit) in &indexed {
+            names[*index] = &edit.name;
+        }
+        let mut seen = HashSet::new();
+        if let Some(clash) = names
+            .into_iter()
+            .find(|name| !seen.insert(nfc(name.trim())))
+        {
+            return Err(PwError::AlreadyExists {
+                name: clash.to_string(),
+                file: self.file.clone(),
+            });
+        }
+
+        let mut changed = 0;
+        for (index, mut edit) in indexed {
+            let entry = &mut self.entries[index];
+            let password = edit.password.take();
+            let notes = edit
+                .notes
+                .take()
+                .map(|notes| Some(notes).filter(|n| !n.is_empty()));
+            let fields = edit.fields.take();
+            if EntryEdit::of(entry, false) == edit
+                && password.as_ref().is_none_or(|p| *p == entry.password)
+                && notes.as_ref().is_none_or(|n| *n == entry.notes)
+                && fields.as_ref().is_none_or(|f| *f == entry.fields)
+            {
+                continue;
+            }
+            entry.name = std::mem::take(&mut edit.name);
+            entry.username = std::mem::take(&mut edit.username);
+            entry.url = edit.url.take();
+            entry.url_match = edit.url_match;
+            entry.pinned = edit.pinned;
+            entry.tags = std::mem::take(&mut edit.tags);
+            if let Some(password) = password {
+                remember_password(&mut entry.history, &entry.password, &password, unix_now());
+                entry.password = password;
+            }
+            if let Some(notes) = notes {
+                entry.notes = notes;
+            }
+            if let Some(fields) = fields {
+                entry.fields = fields;
+            }
+            entry.updated_at = Some(unix_now());
+            changed += 1;
+        }
+        self.changed |= changed > 0;
+        Ok(changed)
+    }
+
+    /// Pin or unpin the entry named `name`, as [`set_pinned`].
+    pub fn set_pinned(&mut self, name: &EntryName, pinned: bool) -> Result<bool, PwError> {
+        let entry = self.entry_mut(name)?;
+        if entry.pinned == pinned {
+            return Ok(false);
+        }
+        entry.pinned = pinned;
+        entry.updated_at = Some(unix_now());
+        self.changed = true;
+        Ok(true)
+    }
+
+    /// Set or, with `None`, remove the launch command of the entry named
+    /// `name`, as [`set_launch`].
+    pub fn set_launch(&mut self, name: &EntryName, launch: Option<String>) -> Result<(), PwError> {
+        if let Some(launch) = &launch {
+            validate_launch(launch)?;
+        }
+        let entry = self.entry_mut(name)?;
+        entry.launch = launch;
+        entry.updated_at = Some(unix_now());
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Set or, with `None`, remove the time the password of the entry named
+    /// `name` expires, in seconds since the Unix epoch.
+    pub fn set_expiry(&mut self, name: &EntryName, expires_at: Option<u64>) -> Result<(), PwError> {
+        self.entry_mut(name)?.expires_at = expires_at;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Set or, with `None`, remove the TOTP secret of the entry named `name`.
+    pub fn set_totp(&mut self, name: &EntryName, totp: Option<Secret>) -> Result<(), PwError> {
+        if let Some(totp) = &totp {
+            totp::Totp::parse(totp.expose())?;
+        }
+        self.entry_mut(name)?.totp = totp;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Set the custom field `field` of the entry named `name` to `value`,
+    /// replacing any previous value.
+    pub fn set_field(
+        &mut self,
+        name: &EntryName,
+        field: &str,
+        value: Secret,
+    ) -> Result<(), PwError> {
+        validate_field_name(field)?;
+        self.entry_mut(name)?
+            .fields
+            .insert(field.to_string(), value);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Remove the custom field `field` from the entry named `name`. Returns
+    /// false if it has no such field.
+    pub fn remove_field(&mut self, name: &EntryName, field: &str) -> Result<bool, PwError> {
+        if self.entry_mut(name)?.fields.remove(field).is_none() {
+            return Ok(false);
+        }
+        self.changed = true;
+        Ok(true)
+    }
+
+    /// Rename the entry named `old` to `new`, as [`rename`].
+    pub fn rename(&mut self, old: &EntryName, new: &EntryName) -> Result<(), PwError> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| same_name(&e.name, old.as_str()))
+            .ok_or_else(|| PwError::NotFound {
+                name: old.to_string(),
+                file: self.file.clone(),
+            })?;
+        if self
+            .entries
+            .iter()
+            .enumerate()
+            .any(|(i, e)| i != index && same_name(&e.name, new.as_str()))
+        {
+            return Err(PwError::AlreadyExists {
+                name: new.to_string(),
+                file: self.file.clone(),
+            });
+        }
+        let entry = &mut self.entries[index];
+        if entry.name != new.as_str() {
+            entry.name = new.to_string();
+            self.changed = true;
+        }
+        Ok(())
+    }
+
+    /// Remove the entry named `name`, as [`remove`], and return it.
+    pub fn remove(&mut self, name: &EntryName) -> Result<PasswordEntry, PwError> {
+        let Some(index) = self
+            .entries
+            .iter()
+            .position(|e| same_name(&e.name, name.as_str()))
+        else {
+            return Err(PwError::NotFound {
+                name: name.to_string(),
+                file: self.file.clone(),
+            });
+        };
+        self.changed = true;
+        Ok(self.entries.remove(index))
+    }
+
+    fn entry_mut(&mut self, name: &EntryName) -> Result<&mut PasswordEntry, PwError> {
+        self.entries
+            .iter_mut()
+            .find(|e| same_name(&e.name, name.as_str()))
+            .ok_or_else(|| PwError::NotFound {
+                name: name.to_string(),
+                file: self.file.clone(),
+            })
+    }
+}
+
+/// Tag the entry named `name` with `tag`. Returns false, without writing the
+/// vault, if the entry already has the tag.
+pub fn add_tag(
+    file: &Path,
+    passphrase: &Passphrase,
+    name: &EntryName,
+    tag: &str,
+    params: &Params,
+) -> Result<bool, PwError> {
+    transaction(file, passphrase, params, |tx| tx.add_tag(name, tag))
+}
+
+/// Remove `tag` from the entry named `name`. Returns false, without writing
+/// the vault, if the entry does not have the tag.
+pub fn remove_tag(
+    file: &Path,
+    passphrase: &Passphrase,
+    name: &EntryName,
+    tag: &str,
+    params: &Params,
+) -> Result<bool, PwError> {
+    transaction(file, passphrase, params, |tx| tx.remove_tag(name, tag))
+}
+
+/// Pin or unpin the entry named `name`. Returns false, without writing the
+/// vault, if it already was.
+pub fn set_pinned(
+    file: &Path,
+    passphrase: &Passphrase,
+    name: &EntryName,
+    pinned: bool,
+    params: &Params,
+) -> Result<bool, PwError> {
+    transaction(file, passphrase, params, |tx| tx.set_pinned(name, pinned))
+}
+
+/// Set or, with `None`, remove the launch command of the entry named `name`.
+pub fn set_launch(
+    file: &Path,
+    passphrase: &Passphrase,
+    name: &EntryName,
+    launch: Option<String>,
+    params: &Params,
+) -> Result<(), PwError> {
+    transaction(file, passphrase, params, |tx| tx.set_launch(name, launch))
+}
+
+/// Rename `old` to `new` on every entry that has it, in a single vault write.
+/// An entry that already has `new` just loses `old`. Returns the number of
+/// entries changed; the vault is not written if that is zero.
+pub fn rename_tag(
+    file: &Path,
+    passphrase: &Passphrase,
+    old: &str,
+    new: &str,
+    params: &Params,
+) -> Result<usize, PwError> {
+    transaction(file, passphrase, params, |tx| tx.rename_tag(old, new))
+}
+
+/// Apply `edits`, keyed by the current name of the entry each one replaces,
+/// in a single vault write. Entries without an edit are left alone. Every
+/// edit is validated, and renames checked for clashes, before anything is
+/// written. A replaced password is added to the entry's `history`, and
+/// every changed entry gets a new `updated_at`. Returns
+/// the number of entries that actually changed; the vault is not written if
+/// that is zero.
+pub fn apply_edits(
+    file: &Path,
+    passphrase: &Passphrase,
+    edits: BTreeMap<String, EntryEdit>,
+    params: &Params,
+) -> Result<usize, PwError> {
+    transaction(file, passphrase, params, |tx| tx.apply_edits(edits))
+}
+
+/// Every tag in use, with the number of entries that have it.
+pub fn tag_counts(entries: &[PasswordEntry]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tag in entries.iter().flat_map(|e| &e.tags) {
+        *counts.entry(tag.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// The decrypted vault as JSON (the same envelope that is stored encrypted),
+/// for backup and migration.
+pub fn export(file: &Path, passphrase: &Passphrase) -> Result<Zeroizing<String>, PwError> {
+    let entries = load(file, passphrase)?;
+    vault::to_json(&entries).map_err(|e| vault_err(file, e))
+}
+
+/// Add the entries of `json`, by default a vault payload such as [`export`]
+/// returns (see [`format::SCHEMA`]), with their timestamps and history, or
+/// the entries of another password manager's export (see [`ImportFormat`]).
+/// An entry with the same username, password and url as one already in the
+/// vault, or earlier in `json`, is skipped as a duplicate, so importing the
+/// same data twice changes nothing. An entry whose name is taken, or used
+/// twice in `json`, fails the import. [`ImportOptions`] can import
+/// duplicates, and replace or rename entries whose name is taken, and only
+/// report what would be done.
+/// Nothing is written if the payload does not match its format or any entry
+/// fails.
+pub fn import(
+    file: &Path,
+    passphrase: &Passphrase,
+    json: &[u8],
+    options: ImportOptions<'_>,
+    params: &Params,
+) -> Result<ImportReport, PwError> {
+    if kdbx::is_keepass(json) {
+        return Err(PwError::InvalidInput {
+            what: "import file",
+            reason: "pw cannot read KeePass databases; export it from KeePassXC as CSV \
+                     and import that with --format 1password-csv"
+                .to_string(),
+        });
+    }
+    let (entries, unsupported) = match options.format {
+        ImportFormat::Vault => (vault_entries(json)?, 0),
+        ImportFormat::Bitwarden => {
+            let converted = bitwarden::convert(json)?;
+            (converted.entries, converted.unsupported)
+        }
+        ImportFormat::OnePasswordCsv => (csv::convert(json, csv::Flavor::OnePassword)?, 0),
+        ImportFormat::LastPassCsv => (csv::convert(json, csv::Flavor::LastPass)?, 0),
+        ImportFormat::Pass => {
+            return Err(PwError::InvalidInput {
+                what: "import format",
+                reason: "a pass store is a directory, not a document".to_string(),
+            })
+        }
+    };
+    let report = import_entries(file, passphrase, entries, options, params)?;
+    Ok(ImportReport {
+        unsupported,
+        ..report
+    })
+}
+
+/// Add `entries` as [`import`] adds the entries of a document, e.g. those of
+/// a pass store.
+pub fn import_entries(
+    file: &Path,
+    passphrase: &Passphrase,
+    entries: Vec<PasswordEntry>,
+    options: ImportOptions<'_>,
+    params: &Params,
+) -> Result<ImportReport, PwError> {
+    let mut vault = Vault::open(file, passphrase)?;
+    let report = vault.import(entries, options)?;
+    if !options.dry_run {
+        vault.commit(passphrase, params)?;
+    }
+    Ok(report)
+}
+
+/// The entries of the vault payload `json`, checked against the schema.
+fn vault_entries(json: &[u8]) -> Result<Vec<PasswordEntry>, PwError> {
+    let invalid = |reason: String| PwError::InvalidInput {
+        what: "vault JSON",
+        reason,
+    };
+    let errors = format::validate(json);
+    if let Some(first) = errors.first() {
+        return Err(invalid(match errors.len() {
+            1 => first.to_string(),
+            n => format!("{first} (and {} more problems)", n - 1),
+        }));
+    }
+    vault::from_json(json).map_err(|e| invalid(e.to_string()))
+}
+
+/// Write the entries matching `filter` to a new vault at `out`, encrypted
+/// with `new_passphrase`, e.g. a reduced vault to travel with. Their password
+/// history is left out. Returns the number of entries written; fails without
+/// writing if there are none, or if `out` already exists.
+pub fn export_vault(
+    file: &Path,
+    passphrase: &Passphrase,
+    filter: &filter::Filter,
+    out: &Path,
+    new_passphrase: &Passphrase,
+    params: &Params,
+) -> Result<usize, PwError> {
+    let mut entries: Vec<_> = load(file, passphrase)?
+        .into_iter()
+        .filter(|e| filter.matches(e))
+        .collect();
+    if entries.is_empty() {
+        return Err(PwError::InvalidInput {
+            what: "selection",
+            reason: "no entry matches".to_string(),
+        });
+    }
+    if out.exists() {
+        return Err(PwError::FileAlreadyExists(out.to_path_buf()));
+    }
+    for entry in &mut entries {
+        entry.history.clear();
+    }
+    store(out, new_passphrase, &entries, params)?;
+    Ok(entries.len())
+}
+
+/// The whole vault as a KeePass KDBX 4 file encrypted with `kdbx_passphrase`
+/// (see [`kdbx`]), with the number of entries in it.
+pub fn export_kdbx(
+    file: &Path,
+    passphrase: &Passphrase,
+    kdbx_passphrase: &Passphrase,
+) -> Result<(Vec<u8>, usize), PwError> {
+    let entries = load(file, passphrase)?;
+    let kdbx = kdbx::write(&entries, kdbx_passphrase, kdbx::DEFAULT_ROUNDS);
+    Ok((kdbx, entries.len()))
+}
+
+/// Reconcile the vault with `manifest`: create the declared entries that are
+/// missing, with generated passwords, and report how existing ones differ.
+/// Existing entries are never changed, and entries the manifest does not
+/// declare are left alone. With `dry_run`, nothing is written.
+pub fn apply(
+    file: &Path,
+    passphrase: &Passphrase,
+    manifest: &manifest::Manifest,
+    dry_run: bool,
+    params: &Params,
+) -> Result<ApplyReport, PwError> {
+    let mut vault = Vault::open(file, passphrase)?;
+    let mut report = ApplyReport::default();
+    for declared in &manifest.entries {
+        let existing = vault
+            .entries()
+            .iter()
+            .find(|e| same_name(&e.name, &declared.name));
+        match existing {
+            Some(entry) => {
+                let drift = declared.drift(entry);
+                if drift.is_empty() {
+                    report.in_sync += 1;
+                } else {
+                    report.drift.push((entry.name.clone(), drift));
+                }
+            }
+            None => {
+                let password = generate_password(declared.length, &declared.charset)?;
+                vault.add(declared.to_entry(password))?;
+                report.created.push(declared.name.clone());
+            }
+        }
+    }
+    if !dry_run {
+        vault.commit(passphrase, params)?;
+    }
+    Ok(report)
+}
+
+/// Apply `rewrite` to every entry it matches, in a single vault write, and
+/// report each changed entry with its changes, in vault order. Changed
+/// entries get a new `updated_at`; passwords are never touched. With
+/// `dry_run`, or when nothing matches, nothing is written.
+pub fn rewrite(
+    file: &Path,
+    passphrase: &Passphrase,
+    rewrite: &Rewrite,
+    dry_run: bool,
+    params: &Params,
+) -> Result<Vec<(String, Vec<String>)>, PwError> {
+    if let Some((_, to)) = &rewrite.username {
+        validate_username(to)?;
+    }
+    let domain = match &rewrite.domain {
+        Some((from, to)) => {
+            let normalize = |domain: &str| {
+                normalize_host(domain)
+                    .filter(|domain| {
+                        domain.split('.').all(|label| {
+                            !label.is_empty()
+                                && label
+                                    .bytes()
+                                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                        })
+                    })
+                    .ok_or_else(|| PwError::InvalidInput {
+                        what: "domain",
+                        reason: format!("'{domain}' is not a domain name"),
+                    })
+            };
+            Some((normalize(from)?, normalize(to)?))
+        }
+        None => None,
+    };
+
+    let mut vault = Vault::open(file, passphrase)?;
+    let mut report = Vec::new();
+    for i in 0..vault.entries.len() {
+        let entry = &vault.entries[i];
+        let mut changes = Vec::new();
+        let mut username = entry.username.clone();
+        if let Some((from, to)) = &rewrite.username {
+            if entry.username == *from && from != to {
+                changes.push(format!("username '{from}' -> '{to}'"));
+                username = to.clone();
+            }
+        }
+        let mut url = entry.url.clone();
+        if let (Some((from, to)), Some(old)) = (&domain, &entry.url) {
+            if let Some(new) = rewrite_url_domain(old, from, to).filter(|new| new != old) {
+                changes.push(format!("url {old} -> {new}"));
+                url = Some(new);
+            }
+        }
+        if !changes.is_empty() {
+            // By position rather than by name, which entries written by older
+            // versions of pw may hold in a form no longer valid.
+            if let Some(url) = &url {
+                validate_url(url)?;
+            }
+            let entry = &mut vault.entries[i];
+            entry.username = username;
+            entry.url = url;
+            entry.updated_at = Some(unix_now());
+            vault.changed = true;
+            report.push((entry.name.clone(), changes));
+        }
+    }
+    if !dry_run {
+        vault.commit(passphrase, params)?;
+    }
+    Ok(report)
+}
+
+/// The non-secret metadata of every entry in the vault, in vault order.
+pub fn inventory(file: &Path, passphrase: &Passphrase) -> Result<Vec<InventoryItem>, PwError> {
+    Ok(load(file, passphrase)?
+        .iter()
+        .map(InventoryItem::from)
+        .collect())
+}
+
+/// Decrypt the vault and validate its payload against [`format::SCHEMA`].
+/// Fails only if the vault cannot be read or decrypted; problems with the
+/// content are returned.
+pub fn validate_vault(
+    file: &Path,
+    passphrase: &Passphrase,
+) -> Result<Vec<format::ValidationError>, PwError> {
+    if !file.exists() {
+        return Err(PwError::FileNotFound(file.to_path_buf()));
+    }
+    let payload = vault::load_payload(file, passphrase).map_err(|e| vault_err(file, e))?;
+    Ok(format::validate(&payload))
+}
+
+/// The keys for the vault as it is now, such as `pw agent` holds: with
+/// them, reading the vault needs neither the passphrase nor scrypt until it
+/// is next written. Fails as opening the vault does.
+pub fn derive_key(
+    file: &Path,
+    passphrase: &Passphrase,
+) -> Result<scrypt_format::DerivedKey, PwError> {
+    if !file.exists() {
+        return Err(PwError::FileNotFound(file.to_path_buf()));
+    }
+    vault::derive_key(file, passphrase).map_err(|e| vault_err(file, e))
+}
+
+/// The current time in seconds since the Unix epoch; 0 if the clock is set
+/// before it.
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The bytes of lowercase or uppercase hex, or `None` if it is not hex.
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn load(file: &Path, passphrase: &Passphrase) -> Result<Vec<PasswordEntry>, PwError> {
+    if !file.exists() {
+        return Err(PwError::FileNotFound(file.to_path_buf()));
+    }
+    vault::load(file, passphrase).map_err(|e| vault_err(file, e))
+}
+
+fn store(
+    file: &Path,
+    passphrase: &Passphrase,
+    entries: &[PasswordEntry],
+    params: &Params,
+) -> Result<(), PwError> {
+    vault::store(file, passphrase, entries, params).map_err(|e| vault_err(file, e))
+}
+
+fn vault_err(file: &Path, err: vault::Error) -> PwError {
+    match err {
+        vault::Error::Format(scrypt_format::Error::WrongPassphrase) => PwError::WrongPassphrase,
+        e
+        @ (vault::Error::Read { .. } | vault::Error::Write { .. } | vault::Error::Response(_)) => {
+            PwError::Io(e)
+        }
+        e => PwError::CorruptVault {
+            file: file.to_path_buf(),
+            source: e,
+        },
+    }
+}
+
+/// Returns true for Unicode bidirectional control and zero-width code points
+/// that can reorder or disguise how text renders without being "control"
+/// characters per [`char::is_control`] (a Trojan-Source / homoglyph style
+/// display spoof). Used both to reject such characters on input and to
+/// replace them on output.
+pub fn is_display_spoofing_char(c: char) -> bool {
+    matches!(c,
+        // Bidirectional embeddings, overrides and isolates
+        '\u{202A}'..='\u{202E}'   // LRE, RLE, PDF, LRO, RLO
+        | '\u{2066}'..='\u{2069}' // LRI, RLI, FSI, PDI
+        // Bidirectional marks
+        | '\u{200E}' | '\u{200F}' // LRM, RLM
+        | '\u{061C}'              // Arabic Letter Mark
+        // Zero-width characters
+        | '\u{200B}'              // Zero Width Space
+        | '\u{200C}' | '\u{200D}' // ZWNJ, ZWJ
+        | '\u{2060}'              // Word Joiner
+        | '\u{FEFF}'              // Zero Width No-Break Space / BOM
+    )
+}
+
+/// `text` in Unicode Normalization Form C, so that visually identical names
+/// typed with precomposed or combining characters are the same name.
+fn nfc(text: &str) -> Cow<'_, str> {
+    ComposingNormalizerBorrowed::new_nfc().normalize(text)
+}
+
+/// Whether two entry names are the same up to surrounding whitespace and
+/// Unicode normalization. Names written by older versions of pw may be
+/// neither trimmed nor in NFC, and must still be found by the [`EntryName`]
+/// the user types for them.
+fn same_name(a: &str, b: &str) -> bool {
+    a == b || nfc(a.trim()) == nfc(b.trim())
+}
+
+/// The `attempt`th candidate for a new name of `name` from an
+/// [`ImportOptions::rename`] pattern, counting from 0.
+fn renamed(pattern: &str, name: &str, source: &str, attempt: usize) -> String {
+    let mut pattern = pattern.to_string();
+    let mut n = attempt + 2;
+    if !pattern.contains("{n}") && attempt > 0 {
+        pattern.push_str("-{n}");
+        n = attempt + 1;
+    }
+    let new_name = pattern
+        .replace("{name}", name)
+        .replace("{source}", source)
+        .replace("{n}", &n.to_string());
+    nfc(&new_name).into_owned()
+}
+
+/// A folder name from another password manager as a tag: `Work Stuff`
+/// becomes `Work-Stuff`.
+pub(crate) fn folder_tag(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Order entry names for display: case-insensitively and ignoring accents
+/// first, so `Ärzte`, `arzte` and `Arzt` sort together, then by the names
+/// themselves so the order is total. A simple approximation of locale
+/// collation that is the same for every locale.
+pub fn compare_names(a: &str, b: &str) -> Ordering {
+    fold(a).cmp(&fold(b)).then_with(|| nfc(a).cmp(&nfc(b)))
+}
+
+/// `text` lowercased and without accents, for comparing names loosely.
+pub(crate) fn fold(text: &str) -> String {
+    let combining = CanonicalCombiningClassMapBorrowed::new();
+    DecomposingNormalizerBorrowed::new_nfd()
+        .normalize(text)
+        .chars()
+        .filter(|&c| combining.get_u8(c) == 0)
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Entry names must be non-empty, at most [`MAX_NAME_LEN`] characters,
+/// without surrounding whitespace (which [`EntryName`] trims) and free of
+/// control, bidirectional and zero-width characters. Everything a hostname
+/// can contain is allowed.
+pub fn validate_name(name: &str) -> Result<(), PwError> {
+    if name.trim().is_empty() {
+        return Err(PwError::InvalidInput {
+            what: "entry name",
+            reason: "must not be empty".to_string(),
+        });
+    }
+    if name.trim() != name {
+        return Err(PwError::InvalidInput {
+            what: "entry name",
+            reason: "must not start or end with whitespace".to_string(),
+        });
+    }
+    validate_text("entry name", name)
+}
+
+/// Usernames may be empty, but obey the same length and character rules as
+/// entry names.
+pub fn validate_username(username: &str) -> Result<(), PwError> {
+    validate_text("username", username)
+}
+
+/// The optional `url` matching hint, when present, must be non-empty and obey
+/// the same length and character rules as entry names. Callers map "no url"
+/// to `None`, so an empty string is rejected rather than stored.
+pub fn validate_url(url: &str) -> Result<(), PwError> {
+    if url.is_empty() {
+        return Err(PwError::InvalidInput {
+            what: "url",
+            reason: "must not be empty".to_string(),
+        });
+    }
+    validate_text("url", url)
+}
+
+/// Tags must be non-empty and free of whitespace and commas (so a list of
+/// them can be written `a,b`), and otherwise obey the same length and
+/// character rules as entry names.
+pub fn validate_tag(tag: &str) -> Result<(), PwError> {
+    if tag.is_empty() {
+        return Err(PwError::InvalidInput {
+            what: "tag",
+            reason: "must not be empty".to_string(),
+        });
+    }
+    if tag.chars().any(|c| c.is_whitespace() || c == ',') {
+        return Err(PwError::InvalidInput {
+            what: "tag",
+            reason: "must not contain whitespace or commas".to_string(),
+        });
+    }
+    validate_text("tag", tag)
+}
+
+/// Parse a duration such as `90d` or `12w` (days or weeks) into seconds.
+pub fn parse_duration(text: &str) -> Result<u64, PwError> {
+    let invalid = || PwError::InvalidInput {
+        what: "duration",
+        reason: "must be a number of days or weeks, such as 90d or 12w".to_string(),
+    };
+    let (number, unit) = match text.strip_suffix('d') {
+        Some(days) => (days, 86_400),
+        None => (text.strip_suffix('w').ok_or_else(invalid)?, 7 * 86_400),
+    };
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(invalid)
+}
+
+/// Custom field names obey the same length and character rules as entry
+/// names, and must not be empty.
+pub fn validate_field_name(field: &str) -> Result<(), PwError> {
+    if field.trim().is_empty() {
+        return Err(PwError::InvalidInput {
+            what: "field name",
+            reason: "must not be empty".to_string(),
+        });
+    }
+    validate_text("field name", field)
+}
+
+/// Validate the user-supplied fields of an entry before it is stored.
+pub(crate) fn validate_entry(entry: &PasswordEntry) -> Result<(), PwError> {
+    validate_name(&entry.name)?;
+    validate_username(&entry.username)?;
+    if let Some(url) = &entry.url {
+        validate_url(url)?;
+    }
+    if let Some(launch) = &entry.launch {
+        validate_launch(launch)?;
+    }
+    if let Some(notes) = &entry.notes {
+        validate_notes(notes)?;
+    }
+    if let Some(secret) = &entry.totp {
+        totp::Totp::parse(secret.expose())?;
+    }
+    for tag in &entry.tags {
+        validate_tag(tag)?;
+    }
+    for field in entry.fields.keys() {
+        validate_field_name(field)?;
+    }
+    Ok(())
+}
+
+fn validate_text(what: &'static str, value: &str) -> Result<(), PwError> {
+    if value.chars().count() > MAX_NAME_LEN {
+        return Err(PwError::InvalidInput {
+            what,
+            reason: format!("longer than {MAX_NAME_LEN} characters"),
+        });
+    }
+    if value.chars().any(char::is_control) {
+        return Err(PwError::InvalidInput {
+            what,
+            reason: "contains control characters".to_string(),
+        });
+    }
+    if value.chars().any(is_display_spoofing_char) {
+        return Err(PwError::InvalidInput {
+            what,
+            reason: "contains bidirectional or zero-width characters".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Notes may span several lines and contain tabs, but no other control
+/// characters, no bidirectional or zero-width characters, and at most
+/// [`MAX_NOTES_LEN`] characters.
+pub fn validate_notes(notes: &str) -> Result<(), PwError> {
+    let invalid = |reason: &str| PwError::InvalidInput {
+        what: "notes",
+        reason: reason.to_string(),
+    };
+    if notes.chars().count() > MAX_NOTES_LEN {
+        return Err(invalid(&format!("longer than {MAX_NOTES_LEN} characters")));
+    }
+    if notes
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\t'))
+    {
+        return Err(invalid("contains control characters"));
+    }
+    if notes.chars().any(is_display_spoofing_char) {
+        return Err(invalid("contains bidirectional or zero-width characters"));
+    }
+    Ok(())
+}
+
+/// The `ssh` arguments that connect to `entry`: the host and port of its
+/// `url` (`ssh://router.example.com:2222`, or just `router.example.com`),
+/// logging in as its username, if it has one.
+pub fn ssh_args(entry: &PasswordEntry) -> Result<Vec<String>, PwError> {
+    let invalid = |reason: &str| PwError::InvalidInput {
+        what: "ssh destination",
+        reason: reason.to_string(),
+    };
+    let url = entry
+        .url
+        .as_deref()
+        .ok_or_else(|| invalid("the entry has no url"))?;
+    let host = url_host(url).ok_or_else(|| invalid("the url has no host"))?;
+    let mut args = Vec::new();
+    if let Some(port) = url_port(url).map_err(|reason| invalid(&reason))? {
+        args.extend(["-p".to_string(), port.to_string()]);
+    }
+    if !entry.username.is_empty() {
+        args.extend(["-l".to_string(), entry.username.clone()]);
+    }
+    // `--` so that a host can never be taken for an option.
+    args.extend(["--".to_string(), host]);
+    Ok(args)
+}
+
+/// The placeholders a launch command may contain.
+const LAUNCH_FIELDS: [&str; 4] = ["name", "username", "url", "host"];
+
+/// The word that makes `pw launch` hand the password to the command in its
+/// environment; see [`launch_wants_password`].
+const LAUNCH_ENV: &str = "{env}";
+
+/// A launch command must be non-empty, at most [`MAX_NAME_LEN`] characters,
+/// free of control, bidirectional and zero-width characters, and use only the
+/// placeholders [`launch_argv`] knows.
+pub fn validate_launch(launch: &str) -> Result<(), PwError> {
+    validate_text("launch command", launch)?;
+    expand_launch(launch, |_| Ok(String::new())).map(drop)
+}
+
+/// The program and arguments to run for `entry`'s launch command `launch`.
+/// The template is split into words at whitespace (it is not interpreted by
+/// a shell), then `{name}`, `{username}`, `{url}` and `{host}` in each word
+/// are replaced by the entry's fields, so `ssh {username}@{host}` becomes
+/// `["ssh", "alice@example.com"]`. The password is never substituted, and a
+/// `{env}` word is left out; see [`launch_wants_password`].
+pub fn launch_argv(launch: &str, entry: &PasswordEntry) -> Result<Vec<String>, PwError> {
+    expand_launch(launch, |field| {
+        let value = match field {
+            "name" => Some(entry.name.clone()),
+            "username" => Some(entry.username.clone()),
+            "url" => entry.url.clone(),
+            _ => entry.url.as_deref().and_then(url_host),
+        };
+        value.ok_or_else(|| PwError::InvalidInput {
+            what: "launch command",
+            reason: format!("uses {{{field}}}, but the entry has no url"),
+        })
+    })
+}
+
+/// Whether the launch command `launch` asks for the password in the
+/// environment of the command, with a word `{env}` of its own, as in
+/// `{env} sshpass -e ssh {username}@{host}`. Without it, the command gets no
+/// password at all.
+pub fn launch_wants_password(launch: &str) -> bool {
+    launch.split_whitespace().any(|word| word == LAUNCH_ENV)
+}
+
+fn expand_launch(
+    launch: &str,
+    mut field: impl FnMut(&str) -> Result<String, PwError>,
+) -> Result<Vec<String>, PwError> {
+    let invalid = |reason: String| PwError::InvalidInput {
+        what: "launch command",
+        reason,
+    };
+    let mut argv = Vec::new();
+    for word in launch.split_whitespace().filter(|word| *word != LAUNCH_ENV) {
+        let mut arg = String::new();
+        let mut rest = word;
+        while let Some(brace) = rest.find(['{', '}']) {
+            arg.push_str(&rest[..brace]);
+            let placeholder = &rest[brace..];
+            let end = placeholder
+                .find('}')
+                .filter(|_| placeholder.starts_with('{'))
+                .ok_or_else(|| invalid(format!("unmatched brace in '{word}'")))?;
+            let key = &placeholder[1..end];
+            if key == "env" {
+                return Err(invalid(format!(
+                    "{{env}} must be a word of its own, not in '{word}'"
+                )));
+            }
+            if !LAUNCH_FIELDS.contains(&key) {
+                return Err(invalid(format!(
+                    "unknown placeholder {{{key}}} (use {{{}}})",
+                    LAUNCH_FIELDS.join("}, {")
+                )));
+            }
+            arg.push_str(&field(key)?);
+            rest = &placeholder[end + 1..];
+        }
+        arg.push_str(rest);
+        argv.push(arg);
+    }
+    if argv.is_empty() {
+        return Err(invalid("it is empty".to_string()));
+    }
+    Ok(argv)
+}
+
+/// Extract the hostname from a web origin, applying the eligibility rules of
+/// the browser integration: only `https:`
+/// origins are accepted, plus `http://localhost` and `http://127.0.0.1` for
+/// local development. The returned hostname is IDNA/punycode-normalized and
+/// lowercased. Any ineligible or unparsable origin yields `None` (mapped to
+/// the `invalid-origin` error by the browser host); no other scheme,
+/// including `http:` on a real host, `file:` or `moz-extension:`, is accepted.
+pub fn origin_hostname(origin: &str) -> Option<String> {
+    let (scheme, rest) = origin.split_once("://")?;
+    let host = normalize_host(host_from_authority(rest))?;
+    match scheme.to_ascii_lowercase().as_str() {
+        "https" => Some(host),
+        "http" if host == "localhost" || host == "127.0.0.1" => Some(host),
+        _ => None,
+    }
+}
+
+/// Reduce the part of a URL after `scheme://` to its bare host: drop any
 /// path/query/fragment, then any userinfo, then the port. A bracketed IPv6
 /// literal is never an eligible host in this integration, so a plain
 /// rightmost-colon split for the port is sufficient.
 fn host_from_authority(after_scheme: &str) -> &str {
+    let authority = host_and_port(after_scheme);
+    authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host)
+}
+
+/// The `host[:port]` part of a URL after its scheme.
+fn host_and_port(after_scheme: &str) -> &str {
     let authority = after_scheme
         .split(['/', '?', '#'])
         .next()
         .unwrap_or(after_scheme);
-    let authority = authority
-        .rsplit_once('@')
-        .map_or(authority, |(_, host)| host);
     authority
-        .rsplit_once(':')
-        .map_or(authority, |(host, _)| host)
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
 }
 
 /// Entries that match `hostname`. An entry matches when the host part of its
@@ -375,7 +1950,9 @@ fn host_from_authority(after_scheme: &str) -> &str {
 /// at a label boundary, climbing no further than the registrable domain
 /// (eTLD+1, via the Public Suffix List). All values are IDNA/punycode-
 /// normalized and compared case-insensitively, so `example.co.uk` matches
-/// `login.example.co.uk` but `co.uk` matches nothing. Only entries with a
+/// `login.example.co.uk` but `co.uk` matches nothing. Entries whose
+/// [`UrlMatch`] is `host` or `prefix` match the exact host only; with no path
+/// to compare, a `prefix` entry matches every path. Only entries with a
 /// `url` set are eligible for web browser use; the entry `name` is never
 /// matched against the hostname.
 pub fn matching_entries<'a>(
@@ -385,18 +1962,101 @@ pub fn matching_entries<'a>(
     let Some(host) = normalize_host(hostname) else {
         return Vec::new();
     };
-    // The registrable domain bounds how far a parent-domain match may climb.
-    // A host with no registrable domain — a bare IP, `localhost`, or a public
-    // suffix itself — admits only an exact match.
-    let min_labels = psl::domain_str(&host).map_or_else(|| label_count(&host), label_count);
-    let candidate_matches =
-        |candidate: Option<String>| candidate.is_some_and(|c| host_matches(&host, &c, min_labels));
     entries
         .iter()
-        .filter(|e| candidate_matches(e.url.as_deref().and_then(url_host)))
+        .filter(|e| match_rank(e, &host, None).is_some())
         .collect()
 }
 
+/// Entries that match `url` (a full URL or a bare hostname, with or without
+/// a path), best match first: `prefix` entries by longest matching path,
+/// then exact host matches, then parent domains, closest first. Matching
+/// follows [`matching_entries`], with the path of `url` checked against
+/// `prefix` entries. Entries that match equally well keep vault order.
+pub fn match_url<'a>(url: &str, entries: &'a [PasswordEntry]) -> Vec<&'a PasswordEntry> {
+    let Some(host) = url_host(url) else {
+        return Vec::new();
+    };
+    let path = url_path(url);
+    let mut ranked: Vec<_> = entries
+        .iter()
+        .filter_map(|e| Some((match_rank(e, &host, Some(path))?, e)))
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| b.cmp(a));
+    ranked.into_iter().map(|(_, e)| e).collect()
+}
+
+/// How well `entry` matches `host` (normalized) and `path`, if it does at
+/// all: higher is better.
+fn match_rank(entry: &PasswordEntry, host: &str, path: Option<&str>) -> Option<(u8, usize)> {
+    let url = entry.url.as_deref()?;
+    let entry_host = url_host(url)?;
+    if entry_host != host {
+        // The registrable domain bounds how far a parent-domain match may
+        // climb. A host with no registrable domain — a bare IP, `localhost`,
+        // or a public suffix itself — admits only an exact match.
+        let min_labels = psl::domain_str(host).map_or_else(|| label_count(host), label_count);
+        return (entry.url_match == UrlMatch::Domain
+            && host_matches(host, &entry_host, min_labels))
+        .then(|| (0, label_count(&entry_host)));
+    }
+    match (entry.url_match, path) {
+        (UrlMatch::Prefix, Some(path)) => {
+            let prefix = url_path(url).trim_end_matches('/');
+            let rest = path.strip_prefix(prefix)?;
+            (rest.is_empty() || rest.starts_with('/')).then_some((2, prefix.len()))
+        }
+        _ => Some((1, 0)),
+    }
+}
+
+/// The port of a url or `host:port`, if it has one.
+fn url_port(url: &str) -> Result<Option<u16>, String> {
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    host_and_port(after_scheme)
+        .rsplit_once(':')
+        .map(|(_, port)| port.parse().map_err(|_| format!("'{port}' is not a port")))
+        .transpose()
+}
+
+/// The path of a url or `host/path`, without query or fragment; empty if
+/// there is none.
+fn url_path(url: &str) -> &str {
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = after_scheme
+        .find('/')
+        .map_or("", |start| &after_scheme[start..]);
+    path.split(['?', '#']).next().unwrap_or(path)
+}
+
+/// `url` with its host moved from the domain `from` to `to` (both
+/// normalized), keeping any subdomain, or `None` if its host is not `from`
+/// or below it. The new host is written normalized.
+fn rewrite_url_domain(url: &str, from: &str, to: &str) -> Option<String> {
+    let start = url.find("://").map_or(0, |i| i + 3);
+    let after_scheme = &url[start..];
+    let authority = after_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(after_scheme);
+    let host_start = authority.rfind('@').map_or(0, |i| i + 1);
+    let host_end = authority[host_start..]
+        .rfind(':')
+        .map_or(authority.len(), |i| host_start + i);
+    let host = normalize_host(&authority[host_start..host_end])?;
+    let new_host = if host == from {
+        to.to_string()
+    } else {
+        let subdomain = host.strip_suffix(from)?.strip_suffix('.')?;
+        format!("{subdomain}.{to}")
+    };
+    Some(format!(
+        "{}{new_host}{}",
+        &url[..start + host_start],
+        &url[start + host_end..]
+    ))
+}
+
 /// IDNA/punycode-normalize a hostname to lowercase ASCII, or `None` if it is
 /// not a usable domain. `domain_to_ascii` already lowercases and rejects the
 /// empty string and malformed labels.
@@ -414,6 +2074,22 @@ fn url_host(url: &str) -> Option<String> {
     normalize_host(host_from_authority(after_scheme))
 }
 
+/// The address to open in a web browser for an entry's `url` field. A bare
+/// hostname or `host/path` gets an `https://` scheme; a full URL is kept as-is
+/// but only when its scheme is `http` or `https`, so a stored `file:` or other
+/// URL is never handed to the system opener. `None` when the url cannot be
+/// opened.
+pub fn browsable_url(url: &str) -> Option<String> {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let scheme = scheme.to_ascii_lowercase();
+            (matches!(scheme.as_str(), "http" | "https") && url_host(url).is_some())
+                .then(|| format!("{scheme}://{rest}"))
+        }
+        None => url_host(url).map(|_| format!("https://{url}")),
+    }
+}
+
 fn label_count(domain: &str) -> usize {
     domain.split('.').filter(|label| !label.is_empty()).count()
 }
@@ -435,6 +2111,16 @@ fn host_matches(host: &str, name: &str, min_labels: usize) -> bool {
 /// Generate a random password of `length` characters from `charset`,
 /// using a cryptographically secure generator.
 pub fn generate_password(length: u32, charset: &str) -> Result<Secret, PwError> {
+    generate_password_with(&mut rng(), length, charset)
+}
+
+/// [`generate_password`] drawing from `rng`, such as a seeded
+/// [`ChaCha20Rng`] for reproducible output.
+pub fn generate_password_with(
+    rng: &mut impl CryptoRng,
+    length: u32,
+    charset: &str,
+) -> Result<Secret, PwError> {
     if length == 0 || length > MAX_PASSWORD_LEN {
         return Err(PwError::InvalidInput {
             what: "password length",
@@ -449,13 +2135,88 @@ pub fn generate_password(length: u32, charset: &str) -> Result<Secret, PwError>
             reason: "must contain at least 2 distinct characters".to_string(),
         });
     }
-    let mut rng =
-        ChaCha20Rng::try_from_rng(&mut SysRng).expect("failed to read from the OS random source");
-    // random_range uses rejection sampling: no modulo bias.
-    let password: String = (0..length)
-        .map(|_| chars[rng.random_range(0..chars.len())])
-        .collect();
-    Ok(Secret::new(password))
+    // With rand's "unbiased" feature, random_range rejects the samples that
+    // would make some characters likelier than others.
+    let password: String = (0..length)
+        .map(|_| chars[rng.random_range(0..chars.len())])
+        .collect();
+    Ok(Secret::new(password))
+}
+
+fn rng() -> ChaCha20Rng {
+    ChaCha20Rng::try_from_rng(&mut SysRng).expect("failed to read from the OS random source")
+}
+
+/// Random contents for a new key file (see [`Passphrase::with_key_file`]).
+pub fn generate_key_file() -> Zeroizing<Vec<u8>> {
+    let mut key = Zeroizing::new(vec![0; KEY_FILE_LEN]);
+    rand::Rng::fill_bytes(&mut rng(), &mut key);
+    key
+}
+
+/// Generate a pronounceable handle for use as a per-site username: three
+/// consonant-vowel syllables and two digits, like `tavobi47`.
+pub fn generate_handle() -> String {
+    generate_handle_with(&mut rng())
+}
+
+/// [`generate_handle`] drawing from `rng`.
+pub fn generate_handle_with(rng: &mut impl CryptoRng) -> String {
+    const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+    const VOWELS: &[u8] = b"aeiou";
+    let mut handle = String::new();
+    for _ in 0..3 {
+        handle.push(CONSONANTS[rng.random_range(0..CONSONANTS.len())] as char);
+        handle.push(VOWELS[rng.random_range(0..VOWELS.len())] as char);
+    }
+    handle.push_str(&format!("{:02}", rng.random_range(0..100)));
+    handle
+}
+
+/// Generate a unique email address at `domain` for use as a per-site
+/// username: `base+x7k2@domain` (sub-addressing, delivered to `base`) when
+/// `base` is given, else a fresh [`generate_handle`] at `domain`.
+pub fn generate_email_alias(base: Option<&str>, domain: &str) -> Result<String, PwError> {
+    let is_address_part =
+        |s: &str| !s.is_empty() && !s.contains(|c: char| c == '@' || c.is_whitespace());
+    if !is_address_part(domain) || !domain.contains('.') {
+        return Err(PwError::InvalidInput {
+            what: "email domain",
+            reason: "must be a domain name like example.com".to_string(),
+        });
+    }
+    let local = match base {
+        Some(base) if !is_address_part(base) || base.contains('+') => {
+            return Err(PwError::InvalidInput {
+                what: "email base",
+                reason: "must be the part of an address before the @, without +".to_string(),
+            });
+        }
+        Some(base) => {
+            let tag = generate_password(4, "abcdefghijklmnopqrstuvwxyz0123456789")?;
+            format!("{base}+{}", tag.expose())
+        }
+        None => generate_handle(),
+    };
+    let alias = format!("{local}@{domain}");
+    validate_username(&alias)?;
+    Ok(alias)
+}
+
+/// A partial reveal of a password: its first and last two characters and its
+/// length, e.g. `Ab…9- (16 chars)` — enough to tell which variant a site has
+/// without showing the secret. Passwords shorter than [`HINT_MIN_LEN`]
+/// characters reveal only their length, so at most half of a password is ever
+/// shown.
+pub fn password_hint(password: &Secret) -> String {
+    let chars: Vec<char> = password.expose().chars().collect();
+    let len = chars.len();
+    if len < HINT_MIN_LEN {
+        return format!("… ({len} chars)");
+    }
+    let head: String = chars[..2].iter().collect();
+    let tail: String = chars[len - 2..].iter().collect();
+    format!("{head}…{tail} ({len} chars)")
 }
 
 #[cfg(test)]
@@ -470,6 +2231,10 @@ mod tests {
         p: 1,
     };
 
+    fn entry_name(name: &str) -> EntryName {
+        EntryName::new(name).unwrap()
+    }
+
     fn passphrase() -> Passphrase {
         Passphrase::new(PASSPHRASE.to_string())
     }
@@ -480,6 +2245,18 @@ mod tests {
             username: format!("{name}-user"),
             password: password.into(),
             url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         }
     }
 
@@ -499,6 +2276,139 @@ mod tests {
         assert_eq!(list(&file, &passphrase()).unwrap(), Vec::new());
     }
 
+    #[test]
+    fn rekey_changes_the_passphrase_and_removes_the_backup() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let new = Passphrase::new("new passphrase".to_string());
+        assert!(vault::backup_path(&file).exists());
+
+        assert!(matches!(
+            rekey(&file, &new, &new, &TEST_PARAMS),
+            Err(PwError::WrongPassphrase)
+        ));
+        assert_eq!(rekey(&file, &passphrase(), &new, &TEST_PARAMS).unwrap(), 1);
+        assert_eq!(list(&file, &new).unwrap()[0].name, "a");
+        assert!(matches!(
+            list(&file, &passphrase()),
+            Err(PwError::WrongPassphrase)
+        ));
+        // The backup would still open with the old passphrase.
+        assert!(!vault::backup_path(&file).exists());
+
+        let stronger = Params {
+            log_n: TEST_PARAMS.log_n + 1,
+            ..TEST_PARAMS
+        };
+        assert_eq!(vault_params(&file).unwrap(), TEST_PARAMS);
+        rekey(&file, &new, &new, &stronger).unwrap();
+        assert_eq!(vault_params(&file).unwrap(), stronger);
+    }
+
+    #[test]
+    fn apply_creates_missing_entries_and_reports_drift() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let manifest = manifest::Manifest::from_json(
+            r#"{"entries": [
+                {"name": "a", "username": "bob"},
+                {"name": "svc", "username": "svc", "tags": ["team"], "length": 40}
+            ]}"#,
+        )
+        .unwrap();
+
+        let report = apply(&file, &passphrase(), &manifest, true, &TEST_PARAMS).unwrap();
+        assert_eq!(report.created, ["svc"]);
+        assert_eq!(list(&file, &passphrase()).unwrap().len(), 1);
+
+        let report = apply(&file, &passphrase(), &manifest, false, &TEST_PARAMS).unwrap();
+        assert_eq!(report.created, ["svc"]);
+        assert_eq!(report.drift.len(), 1);
+        assert_eq!(report.drift[0].0, "a");
+        let svc = get(&file, &passphrase(), &entry_name("svc")).unwrap();
+        assert_eq!(
+            (svc.username.as_str(), &svc.tags[..]),
+            ("svc", &["team".to_string()][..])
+        );
+        assert_eq!(svc.password.expose().len(), 40);
+
+        let report = apply(&file, &passphrase(), &manifest, false, &TEST_PARAMS).unwrap();
+        assert_eq!((report.created.len(), report.in_sync), (0, 1));
+    }
+
+    #[test]
+    fn rewrite_changes_usernames_and_domains_in_one_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pw.scrypt");
+        init(&file, &passphrase(), &TEST_PARAMS).unwrap();
+        for (name, username, url) in [
+            (
+                "mail",
+                "alice@corp.com",
+                Some("https://mail.corp.com:8443/inbox"),
+            ),
+            ("wiki", "alice@corp.com", Some("CORP.com")),
+            ("evil", "bob", Some("https://evilcorp.com/")),
+            ("home", "alice", None),
+        ] {
+            let mut new = entry(name, "pw");
+            new.username = username.to_string();
+            new.url = url.map(str::to_string);
+            add(&file, &passphrase(), new, &TEST_PARAMS).unwrap();
+        }
+        let rewrite = Rewrite {
+            username: Some(("alice@corp.com".to_string(), "alice@new.org".to_string())),
+            domain: Some(("corp.com".to_string(), "new.org".to_string())),
+        };
+
+        let report = super::rewrite(&file, &passphrase(), &rewrite, true, &TEST_PARAMS).unwrap();
+        assert_eq!(report.len(), 2);
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("mail"))
+                .unwrap()
+                .username,
+            "alice@corp.com"
+        );
+
+        let report = super::rewrite(&file, &passphrase(), &rewrite, false, &TEST_PARAMS).unwrap();
+        assert_eq!(
+            report,
+            [
+                (
+                    "mail".to_string(),
+                    vec![
+                        "username 'alice@corp.com' -> 'alice@new.org'".to_string(),
+                        "url https://mail.corp.com:8443/inbox -> https://mail.new.org:8443/inbox"
+                            .to_string(),
+                    ]
+                ),
+                (
+                    "wiki".to_string(),
+                    vec![
+                        "username 'alice@corp.com' -> 'alice@new.org'".to_string(),
+                        "url CORP.com -> new.org".to_string(),
+                    ]
+                ),
+            ]
+        );
+        let entries = list(&file, &passphrase()).unwrap();
+        assert_eq!(
+            entries[0].url.as_deref(),
+            Some("https://mail.new.org:8443/inbox")
+        );
+        assert_eq!(entries[0].password.expose(), "pw");
+        assert_eq!(entries[2].url.as_deref(), Some("https://evilcorp.com/"));
+        assert!(
+            super::rewrite(&file, &passphrase(), &rewrite, false, &TEST_PARAMS)
+                .unwrap()
+                .is_empty()
+        );
+
+        let bad = Rewrite {
+            domain: Some(("corp.com".to_string(), "not a domain".to_string())),
+            ..Rewrite::default()
+        };
+        assert!(super::rewrite(&file, &passphrase(), &bad, false, &TEST_PARAMS).is_err());
+    }
+
     #[test]
     fn init_refuses_existing_file() {
         let (_dir, file) = new_vault(&[]);
@@ -509,14 +2419,34 @@ mod tests {
     #[test]
     fn add_then_get() {
         let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
-        let got = get(&file, &passphrase(), "b").unwrap();
+        let mut got = get(&file, &passphrase(), &entry_name("b")).unwrap();
+        assert!(got.created_at.is_some() && got.updated_at == got.created_at);
+        (got.created_at, got.updated_at) = (None, None);
         assert_eq!(got, entry("b", "pw-b"));
     }
 
+    #[test]
+    fn timestamps_are_set_by_add_and_update() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let added = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        let created = added.created_at.unwrap();
+        assert!(created > 1_700_000_000);
+
+        update(&file, &passphrase(), entry("a", "new"), &TEST_PARAMS).unwrap();
+        let updated = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!(updated.created_at, Some(created));
+        assert!(updated.updated_at.unwrap() >= created);
+
+        // Entries from before timestamps load without them.
+        let json = br#"{"version":1,"entries":[{"name":"old","username":"","password":"p"}]}"#;
+        let old = &vault::from_json(json).unwrap()[0];
+        assert_eq!((old.created_at, old.updated_at), (None, None));
+    }
+
     #[test]
     fn get_unknown_name() {
         let (_dir, file) = new_vault(&[("a", "pw-a")]);
-        let err = get(&file, &passphrase(), "nope").unwrap_err();
+        let err = get(&file, &passphrase(), &entry_name("nope")).unwrap_err();
         assert!(matches!(err, PwError::NotFound { name, .. } if name == "nope"));
     }
 
@@ -532,7 +2462,9 @@ mod tests {
         let (_dir, file) = new_vault(&[("a", "pw-a")]);
         update(&file, &passphrase(), entry("a", "pw-new"), &TEST_PARAMS).unwrap();
         assert_eq!(
-            get(&file, &passphrase(), "a").unwrap().password,
+            get(&file, &passphrase(), &entry_name("a"))
+                .unwrap()
+                .password,
             "pw-new".into()
         );
     }
@@ -547,7 +2479,7 @@ mod tests {
     #[test]
     fn remove_existing() {
         let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
-        remove(&file, &passphrase(), "a", &TEST_PARAMS).unwrap();
+        remove(&file, &passphrase(), &entry_name("a"), &TEST_PARAMS).unwrap();
         let names: Vec<String> = list(&file, &passphrase())
             .unwrap()
             .into_iter()
@@ -559,7 +2491,7 @@ mod tests {
     #[test]
     fn remove_unknown_name() {
         let (_dir, file) = new_vault(&[]);
-        let err = remove(&file, &passphrase(), "a", &TEST_PARAMS).unwrap_err();
+        let err = remove(&file, &passphrase(), &entry_name("a"), &TEST_PARAMS).unwrap_err();
         assert!(matches!(err, PwError::NotFound { .. }));
     }
 
@@ -568,7 +2500,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let file = dir.path().join("pw.scrypt");
         for err in [
-            get(&file, &passphrase(), "a").unwrap_err(),
+            get(&file, &passphrase(), &entry_name("a")).unwrap_err(),
             list(&file, &passphrase()).map(|_| ()).unwrap_err(),
             add(&file, &passphrase(), entry("a", "pw"), &TEST_PARAMS).unwrap_err(),
         ] {
@@ -577,39 +2509,633 @@ mod tests {
     }
 
     #[test]
-    fn wrong_passphrase_is_distinct() {
-        let (_dir, file) = new_vault(&[]);
-        let err = list(&file, &Passphrase::new("wrong".to_string())).unwrap_err();
-        assert!(matches!(err, PwError::WrongPassphrase));
+    fn wrong_passphrase_is_distinct() {
+        let (_dir, file) = new_vault(&[]);
+        let err = list(&file, &Passphrase::new("wrong".to_string())).unwrap_err();
+        assert!(matches!(err, PwError::WrongPassphrase));
+    }
+
+    #[test]
+    fn import_renames_taken_names() {
+        let (_dir, file) = new_vault(&[("github", "pw-1"), ("github-2", "pw-2")]);
+        let json = br#"{"items":[
+            {"type":1,"name":"github","login":{"password":"pw-3"}},
+            {"type":1,"name":"github","login":{"password":"pw-4"}},
+            {"type":1,"name":"gitlab","login":{"password":"pw-5"}}]}"#;
+        let import_as = |pattern| {
+            let options = ImportOptions {
+                format: ImportFormat::Bitwarden,
+                rename: Some(pattern),
+                keep_duplicates: true,
+                ..Default::default()
+            };
+            import(&file, &passphrase(), json, options, &TEST_PARAMS)
+        };
+        let pair = |old: &str, new: &str| (old.to_string(), new.to_string());
+
+        let dry_run = ImportOptions {
+            format: ImportFormat::Bitwarden,
+            rename: Some("{name}-{n}"),
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = import(&file, &passphrase(), json, dry_run, &TEST_PARAMS).unwrap();
+        assert_eq!(report.names, ["github-3", "github-4", "gitlab"]);
+        assert_eq!(list(&file, &passphrase()).unwrap().len(), 2);
+
+        let report = import_as("{name}-{n}").unwrap();
+        assert_eq!(
+            report.renamed,
+            [pair("github", "github-3"), pair("github", "github-4")]
+        );
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("github-4"))
+                .unwrap()
+                .password,
+            "pw-4".into()
+        );
+        let report = import_as("{name} ({source})").unwrap();
+        assert_eq!(
+            report.renamed,
+            [
+                pair("github", "github (bitwarden)"),
+                pair("github", "github (bitwarden)-2"),
+                pair("gitlab", "gitlab (bitwarden)"),
+            ]
+        );
+
+        let err = import_as("imported").unwrap_err();
+        assert!(matches!(
+            err,
+            PwError::InvalidInput {
+                what: "rename pattern",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn import_refers_keepass_databases_to_their_csv_export() {
+        let (_dir, file) = new_vault(&[]);
+        let database = kdbx::write(&[], &passphrase(), 1);
+        let err = import(
+            &file,
+            &passphrase(),
+            &database,
+            Default::default(),
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot read KeePass"), "{err}");
+
+        // The columns of a KeePassXC CSV export.
+        let csv = "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\",\"TOTP\"\n\
+            \"Root\",\"github\",\"alice\",\"pw-1\",\"https://github.com\",\"codes\",\"\"\n";
+        let options = ImportOptions {
+            format: ImportFormat::OnePasswordCsv,
+            ..Default::default()
+        };
+        import(&file, &passphrase(), csv.as_bytes(), options, &TEST_PARAMS).unwrap();
+        let github = get(&file, &passphrase(), &entry_name("github")).unwrap();
+        assert_eq!(
+            (github.username.as_str(), github.url.as_deref()),
+            ("alice", Some("https://github.com"))
+        );
+        assert_eq!(github.notes.as_deref(), Some("codes"));
+    }
+
+    #[test]
+    fn export_round_trips_as_json() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let json = export(&file, &passphrase()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["entries"][0]["password"], "pw-a");
+    }
+
+    #[test]
+    fn import_restores_an_export() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
+        update(&file, &passphrase(), entry("a", "pw-a2"), &TEST_PARAMS).unwrap();
+        let json = export(&file, &passphrase()).unwrap();
+        let (_other_dir, other) = new_vault(&[("b", "other-b")]);
+
+        let replace = ImportOptions {
+            replace: true,
+            ..Default::default()
+        };
+        let import_json = |options| {
+            import(
+                &other,
+                &passphrase(),
+                json.as_bytes(),
+                options,
+                &TEST_PARAMS,
+            )
+        };
+        let err = import_json(ImportOptions::default()).unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { name, .. } if name == "b"));
+        assert_eq!(list(&other, &passphrase()).unwrap().len(), 1);
+
+        assert_eq!(
+            import_json(replace).unwrap(),
+            ImportReport {
+                imported: 2,
+                duplicates: 0,
+                names: vec!["a".to_string(), "b".to_string()],
+                ..Default::default()
+            }
+        );
+        let sorted = |file| {
+            let mut entries = list(file, &passphrase()).unwrap();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            entries
+        };
+        // Replaced entries keep their place in the vault.
+        assert_eq!(sorted(&other), sorted(&file));
+
+        // Importing again finds only duplicates, whatever their names.
+        assert_eq!(
+            import_json(ImportOptions::default()).unwrap(),
+            ImportReport {
+                imported: 0,
+                duplicates: 2,
+                ..Default::default()
+            }
+        );
+        let renamed = json.replace(r#""name":"a""#, r#""name":"a2""#);
+        let counts = import(
+            &other,
+            &passphrase(),
+            renamed.as_bytes(),
+            replace,
+            &TEST_PARAMS,
+        )
+        .unwrap();
+        assert_eq!(counts.duplicates, 2);
+        let keep_duplicates = ImportOptions {
+            keep_duplicates: true,
+            ..replace
+        };
+        let counts = import(
+            &other,
+            &passphrase(),
+            renamed.as_bytes(),
+            keep_duplicates,
+            &TEST_PARAMS,
+        )
+        .unwrap();
+        assert_eq!(counts.imported, 2);
+        assert_eq!(list(&other, &passphrase()).unwrap().len(), 3);
+        let twins = br#"{"version":1,"entries":[
+            {"name":"x","username":"u","password":"p"},
+            {"name":"y","username":"u","password":"p"}]}"#;
+        assert_eq!(
+            import(&other, &passphrase(), twins, replace, &TEST_PARAMS).unwrap(),
+            ImportReport {
+                imported: 1,
+                duplicates: 1,
+                names: vec!["x".to_string()],
+                ..Default::default()
+            }
+        );
+
+        // Unlike the vault schema, a Bitwarden export may reuse a name.
+        let same_name = br#"{"items":[
+            {"type":1,"name":"z","login":{"username":"u1"}},
+            {"type":1,"name":"z","login":{"username":"u2"}}]}"#;
+        let bitwarden = ImportOptions {
+            format: ImportFormat::Bitwarden,
+            ..replace
+        };
+        let err = import(&other, &passphrase(), same_name, bitwarden, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { name, .. } if name == "z"));
+
+        for bad in [
+            &br#"{"version":1,"entries":[{"name":"c"}]}"#[..],
+            b"[1]",
+            b"not json",
+        ] {
+            let err = import(&other, &passphrase(), bad, replace, &TEST_PARAMS).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    PwError::InvalidInput {
+                        what: "vault JSON",
+                        ..
+                    }
+                ),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn inventory_has_metadata_only() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        add_tag(&file, &passphrase(), &entry_name("a"), "db", &TEST_PARAMS).unwrap();
+        let items = inventory(&file, &passphrase()).unwrap();
+        assert_eq!(
+            items,
+            [InventoryItem {
+                name: "a".to_string(),
+                username: "a-user".to_string(),
+                url: None,
+                tags: vec!["db".to_string()],
+            }]
+        );
+        assert!(!serde_json::to_string(&items).unwrap().contains("pw-a"));
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let (_dir, file) = new_vault(&[]);
+        for name in [
+            "",
+            "   ",
+            "with\nnewline",
+            "with\x1b[31mescape",
+            &"x".repeat(257),
+        ] {
+            let err = add(&file, &passphrase(), entry(name, "pw"), &TEST_PARAMS).unwrap_err();
+            assert!(matches!(err, PwError::InvalidInput { .. }), "name {name:?}");
+        }
+    }
+
+    #[test]
+    fn allows_hostname_names() {
+        for name in [
+            "example.com",
+            "xn--bcher-kva.example",
+            "sub.host-1.example.org",
+        ] {
+            assert!(validate_name(name).is_ok(), "name {name:?}");
+        }
+    }
+
+    #[test]
+    fn entry_name_is_trimmed_and_validated() {
+        assert_eq!(entry_name("  github.com\t").as_str(), "github.com");
+        assert_eq!("a b".parse::<EntryName>().unwrap().to_string(), "a b");
+        for name in ["", "   ", "with\nnewline", "a\u{202e}b", &"x".repeat(257)] {
+            let err = EntryName::new(name).unwrap_err();
+            assert!(matches!(err, PwError::InvalidInput { .. }), "name {name:?}");
+        }
+    }
+
+    #[test]
+    fn padded_names_are_trimmed_when_added_or_imported() {
+        assert!(validate_name(" github").is_err());
+        assert!(validate_name("github\t").is_err());
+        let (_dir, file) = new_vault(&[(" padded ", "pw-1")]);
+        let json = br#"{"items":[{"type":1,"name":"  github  ","login":{"password":"pw-2"}}]}"#;
+        let options = ImportOptions {
+            format: ImportFormat::Bitwarden,
+            ..Default::default()
+        };
+        let report = import(&file, &passphrase(), json, options, &TEST_PARAMS).unwrap();
+        assert_eq!(report.names, ["github"]);
+        let names: Vec<_> = list(&file, &passphrase())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name.clone())
+            .collect();
+        assert_eq!(names, ["padded", "github"]);
+    }
+
+    #[test]
+    fn names_are_stored_nfc_and_matched_up_to_normalization() {
+        let (_dir, file) = new_vault(&[("Cafe\u{301}", "pw")]);
+        assert_eq!(list(&file, &passphrase()).unwrap()[0].name, "Caf\u{e9}");
+        assert_eq!(entry_name("Cafe\u{301}").as_str(), "Caf\u{e9}");
+        let found = get(&file, &passphrase(), &entry_name("Caf\u{e9}")).unwrap();
+        assert_eq!(found.password.expose(), "pw");
+        let err = add(
+            &file,
+            &passphrase(),
+            entry("Caf\u{e9}", "pw2"),
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { .. }));
+    }
+
+    #[test]
+    fn padded_names_from_older_versions_are_still_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pw.scrypt");
+        // As written before names were trimmed and normalized on input.
+        let payload = br#"{"version":1,"entries":[
+            {"name":"foo ","username":"u","password":"pw-1","url":"https://old.example"},
+            {"name":" Cafe\u0301","username":"u","password":"pw-2"}]}"#;
+        let data = scrypt_format::encrypt(payload, PASSPHRASE.as_bytes(), &TEST_PARAMS).unwrap();
+        std::fs::write(&file, data).unwrap();
+
+        let found = get(&file, &passphrase(), &entry_name("foo")).unwrap();
+        assert_eq!(found.password.expose(), "pw-1");
+        let found = get(&file, &passphrase(), &entry_name("Caf\u{e9}")).unwrap();
+        assert_eq!(found.password.expose(), "pw-2");
+
+        let rewrite_domain = Rewrite {
+            domain: Some(("old.example".to_string(), "new.example".to_string())),
+            ..Default::default()
+        };
+        let report = rewrite(&file, &passphrase(), &rewrite_domain, false, &TEST_PARAMS).unwrap();
+        assert_eq!(report[0].0, "foo ");
+        let found = get(&file, &passphrase(), &entry_name("foo")).unwrap();
+        assert_eq!(found.url.as_deref(), Some("https://new.example"));
+
+        rename(
+            &file,
+            &passphrase(),
+            &entry_name("foo"),
+            &entry_name("bar"),
+            &TEST_PARAMS,
+        )
+        .unwrap();
+        remove(&file, &passphrase(), &entry_name("Caf\u{e9}"), &TEST_PARAMS).unwrap();
+        let names: Vec<_> = list(&file, &passphrase())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name.clone())
+            .collect();
+        assert_eq!(names, ["bar"]);
+    }
+
+    #[test]
+    fn names_compare_ignoring_case_and_accents() {
+        let mut names = vec!["zoo", "\u{c4}rzte", "Arzt", "arzte", "A\u{308}rzte"];
+        names.sort_by(|a, b| compare_names(a, b));
+        assert_eq!(
+            names,
+            ["Arzt", "arzte", "\u{c4}rzte", "A\u{308}rzte", "zoo"]
+        );
+        assert_eq!(compare_names("\u{c4}rzte", "A\u{308}rzte"), Ordering::Equal);
+    }
+
+    #[test]
+    fn transaction_writes_once_or_not_at_all() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
+        let written = std::fs::read(&file).unwrap();
+
+        let err = transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.remove(&entry_name("a"))?;
+            tx.add(entry("c", "pw-c"))?;
+            assert_eq!(tx.entries().len(), 2);
+            tx.add(entry("b", "again"))
+        })
+        .unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { .. }));
+        assert_eq!(std::fs::read(&file).unwrap(), written);
+
+        let renamed = transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            let mut moved = tx.remove(&entry_name("a"))?;
+            moved.name = "c".to_string();
+            tx.add(moved)?;
+            tx.update(entry("b", "new-b"))?;
+            Ok(tx.get(&entry_name("c")).is_some())
+        })
+        .unwrap();
+        assert!(renamed);
+        let names: Vec<_> = list(&file, &passphrase())
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name.clone(), e.password.expose().to_string()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("b".to_string(), "new-b".to_string()),
+                ("c".to_string(), "pw-a".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn vault_changes_are_written_only_on_commit() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let mut vault = Vault::open(&file, &passphrase()).unwrap();
+        assert!(!vault.is_changed());
+        vault.add(entry("b", "pw-b")).unwrap();
+        vault.remove(&entry_name("a")).unwrap();
+        assert!(vault.is_changed());
+        assert_eq!(list(&file, &passphrase()).unwrap().len(), 1);
+        assert!(get(&file, &passphrase(), &entry_name("a")).is_ok());
+
+        vault.commit(&passphrase(), &TEST_PARAMS).unwrap();
+        assert!(!vault.is_changed());
+        assert!(get(&file, &passphrase(), &entry_name("b")).is_ok());
+        assert!(get(&file, &passphrase(), &entry_name("a")).is_err());
+
+        let mut vault = Vault::open(&file, &passphrase()).unwrap();
+        vault.remove(&entry_name("b")).unwrap();
+        drop(vault);
+        assert!(get(&file, &passphrase(), &entry_name("b")).is_ok());
+    }
+
+    #[test]
+    fn launch_commands_substitute_fields_per_word() {
+        let mut e = entry("db", "secret");
+        e.url = Some("https://db.example.com/admin".to_string());
+        assert_eq!(
+            launch_argv("ssh  -l {username} {host}", &e).unwrap(),
+            ["ssh", "-l", "db-user", "db.example.com"]
+        );
+        assert_eq!(
+            launch_argv("open {url}#{name}", &e).unwrap(),
+            ["open", "https://db.example.com/admin#db"]
+        );
+        assert_eq!(
+            launch_argv("{env} sshpass -e ssh {host}", &e).unwrap(),
+            ["sshpass", "-e", "ssh", "db.example.com"]
+        );
+        assert!(launch_wants_password("{env} sshpass -e ssh {host}"));
+        assert!(launch_wants_password("sshpass -e {env} ssh {host}"));
+        assert!(!launch_wants_password("sshpass -e ssh {host}"));
+        e.url = None;
+        assert!(launch_argv("ssh {host}", &e).is_err());
+
+        for bad in [
+            "",
+            "  ",
+            "ssh {password}",
+            "ssh {host",
+            "ssh host}",
+            "a\tb\n",
+            "{env}",
+            "ssh{env} host",
+        ] {
+            let err = validate_launch(bad).unwrap_err();
+            assert!(matches!(err, PwError::InvalidInput { .. }), "{bad:?}");
+        }
+        assert!(validate_launch("ssh {username}@{host}").is_ok());
+    }
+
+    #[test]
+    fn notes_are_kept_by_update_and_set_in_a_transaction() {
+        let mut noted = entry("a", "pw");
+        noted.notes = Some("Q: first pet?\n\tA: Rex".to_string());
+        let (_dir, file) = new_vault(&[]);
+        add(&file, &passphrase(), noted, &TEST_PARAMS).unwrap();
+        update(&file, &passphrase(), entry("a", "new"), &TEST_PARAMS).unwrap();
+        let a = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!(a.notes.as_deref(), Some("Q: first pet?\n\tA: Rex"));
+
+        transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.update_keep_password(&entry_name("a"), "u".to_string(), None, UrlMatch::Domain)?;
+            tx.set_notes(&entry_name("a"), None)
+        })
+        .unwrap();
+        let a = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!((a.username.as_str(), a.notes.as_deref()), ("u", None));
+
+        for bad in ["bell\u{7}", "\u{202e}", &"x".repeat(MAX_NOTES_LEN + 1)] {
+            let err = validate_notes(bad).unwrap_err();
+            assert!(matches!(err, PwError::InvalidInput { what: "notes", .. }));
+        }
+    }
+
+    #[test]
+    fn fields_are_set_removed_and_kept_by_update() {
+        let (_dir, file) = new_vault(&[("a", "pw")]);
+        let a = entry_name("a");
+        transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.set_field(&a, "pin", "1234".into())?;
+            tx.set_field(&a, "recovery", "one two".into())
+        })
+        .unwrap();
+        update(&file, &passphrase(), entry("a", "new"), &TEST_PARAMS).unwrap();
+        let fields = get(&file, &passphrase(), &a).unwrap().fields.clone();
+        assert_eq!(fields.keys().collect::<Vec<_>>(), ["pin", "recovery"]);
+        assert_eq!(fields["pin"].expose(), "1234");
+
+        let removed = transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            Ok((tx.remove_field(&a, "pin")?, tx.remove_field(&a, "pin")?))
+        });
+        assert_eq!(removed.unwrap(), (true, false));
+        let err = transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.set_field(&a, " ", "x".into())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            PwError::InvalidInput {
+                what: "field name",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn update_keeps_replaced_passwords_which_can_be_restored() {
+        let (_dir, file) = new_vault(&[("a", "pw-1")]);
+        let a = entry_name("a");
+        let history = |file: &Path| -> Vec<String> {
+            let entry = get(file, &passphrase(), &entry_name("a")).unwrap();
+            entry
+                .history
+                .iter()
+                .map(|old| old.password.expose().to_string())
+                .collect()
+        };
+        update(&file, &passphrase(), entry("a", "pw-2"), &TEST_PARAMS).unwrap();
+        update(&file, &passphrase(), entry("a", "pw-3"), &TEST_PARAMS).unwrap();
+        update(&file, &passphrase(), entry("a", "pw-3"), &TEST_PARAMS).unwrap();
+        assert_eq!(history(&file), ["pw-1", "pw-2"]);
+
+        transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.restore_password(&a, 2)
+        })
+        .unwrap();
+        let restored = get(&file, &passphrase(), &a).unwrap();
+        assert_eq!(restored.password.expose(), "pw-1");
+        assert!(restored.history[1].replaced_at >= restored.created_at.unwrap());
+        assert_eq!(history(&file), ["pw-2", "pw-3"]);
+
+        for back in [0, 3] {
+            let err = transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+                tx.restore_password(&a, back)
+            })
+            .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid history position: must be from 1 to 2"
+            );
+        }
+
+        for i in 0..MAX_HISTORY_LEN {
+            let password = format!("pw-{}", i + 4);
+            update(&file, &passphrase(), entry("a", &password), &TEST_PARAMS).unwrap();
+        }
+        let kept = history(&file);
+        assert_eq!(kept.len(), MAX_HISTORY_LEN);
+        assert_eq!(kept[0], "pw-1");
     }
 
     #[test]
-    fn export_round_trips_as_json() {
-        let (_dir, file) = new_vault(&[("a", "pw-a")]);
-        let json = export(&file, &passphrase()).unwrap();
-        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
-        assert_eq!(value["version"], 1);
-        assert_eq!(value["entries"][0]["password"], "pw-a");
+    fn durations_are_days_or_weeks() {
+        assert_eq!(parse_duration("90d").unwrap(), 90 * 86_400);
+        assert_eq!(parse_duration("2w").unwrap(), 14 * 86_400);
+        assert_eq!(parse_duration("0d").unwrap(), 0);
+        for bad in ["", "d", "90", "-1d", "1.5w", "3m", "99999999999999999999d"] {
+            assert!(
+                matches!(
+                    parse_duration(bad),
+                    Err(PwError::InvalidInput {
+                        what: "duration",
+                        ..
+                    })
+                ),
+                "{bad}"
+            );
+        }
     }
 
     #[test]
-    fn rejects_invalid_names() {
-        let (_dir, file) = new_vault(&[]);
-        for name in ["", "with\nnewline", "with\x1b[31mescape", &"x".repeat(257)] {
-            let err = add(&file, &passphrase(), entry(name, "pw"), &TEST_PARAMS).unwrap_err();
-            assert!(matches!(err, PwError::InvalidInput { .. }), "name {name:?}");
-        }
+    fn expiry_is_kept_by_update_unless_replaced() {
+        let (_dir, file) = new_vault(&[("a", "pw")]);
+        let a = entry_name("a");
+        transaction(&file, &passphrase(), &TEST_PARAMS, |tx| {
+            tx.set_expiry(&a, Some(1_000))
+        })
+        .unwrap();
+        update(&file, &passphrase(), entry("a", "new"), &TEST_PARAMS).unwrap();
+        assert_eq!(
+            get(&file, &passphrase(), &a).unwrap().expires_at,
+            Some(1_000)
+        );
+
+        let mut renewed = entry("a", "newer");
+        renewed.expires_at = Some(2_000);
+        update(&file, &passphrase(), renewed, &TEST_PARAMS).unwrap();
+        assert_eq!(
+            get(&file, &passphrase(), &a).unwrap().expires_at,
+            Some(2_000)
+        );
     }
 
     #[test]
-    fn allows_hostname_names() {
-        for name in [
-            "example.com",
-            "xn--bcher-kva.example",
-            "sub.host-1.example.org",
-        ] {
-            assert!(validate_name(name).is_ok(), "name {name:?}");
-        }
+    fn ssh_args_use_the_url_host_port_and_username() {
+        let mut e = entry("router", "pw");
+        e.url = Some("ssh://Router.Example.com:2222".to_string());
+        assert_eq!(
+            ssh_args(&e).unwrap(),
+            [
+                "-p",
+                "2222",
+                "-l",
+                "router-user",
+                "--",
+                "router.example.com"
+            ]
+        );
+        e.url = Some("switch.example.com".to_string());
+        e.username.clear();
+        assert_eq!(ssh_args(&e).unwrap(), ["--", "switch.example.com"]);
+        e.url = Some("switch.example.com:ssh".to_string());
+        assert!(ssh_args(&e).is_err());
+        e.url = None;
+        assert!(ssh_args(&e).is_err());
     }
 
     #[test]
@@ -620,6 +3146,18 @@ mod tests {
             username: "user\r\n".to_string(),
             password: "pw".into(),
             url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         };
         let err = add(&file, &passphrase(), bad, &TEST_PARAMS).unwrap_err();
         assert!(matches!(err, PwError::InvalidInput { .. }));
@@ -655,9 +3193,26 @@ mod tests {
             username: String::new(),
             password: "pw".into(),
             url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         };
         add(&file, &passphrase(), e, &TEST_PARAMS).unwrap();
-        assert_eq!(get(&file, &passphrase(), "a").unwrap().username, "");
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("a"))
+                .unwrap()
+                .username,
+            ""
+        );
     }
 
     #[test]
@@ -667,6 +3222,101 @@ mod tests {
         assert!(pw.expose().chars().all(|c| c.is_ascii_digit()));
     }
 
+    #[test]
+    fn generation_is_reproducible_with_a_seeded_rng() {
+        let seeded = || ChaCha20Rng::seed_from_u64(42);
+        let password = generate_password_with(&mut seeded(), 20, "abcdef").unwrap();
+        assert_eq!(
+            password,
+            generate_password_with(&mut seeded(), 20, "abcdef").unwrap()
+        );
+        assert_ne!(
+            password,
+            generate_password_with(&mut ChaCha20Rng::seed_from_u64(43), 20, "abcdef").unwrap()
+        );
+        assert_eq!(
+            generate_handle_with(&mut seeded()),
+            generate_handle_with(&mut seeded())
+        );
+    }
+
+    /// Pearson's chi-squared statistic of `samples` against a uniform
+    /// distribution over `alphabet`.
+    fn chi_squared(samples: impl IntoIterator<Item = char>, alphabet: &str) -> f64 {
+        let mut counts: BTreeMap<char, usize> = alphabet.chars().map(|c| (c, 0)).collect();
+        let mut total = 0;
+        for c in samples {
+            *counts.get_mut(&c).expect("sample outside the alphabet") += 1;
+            total += 1;
+        }
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .values()
+            .map(|&n| (n as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // The samples are drawn from fixed seeds, so these tests are
+    // deterministic; a fair generator passes them at p = 0.001, and a biased
+    // one (such as `% len` of a byte) fails them.
+
+    #[test]
+    fn generated_characters_are_uniform() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        for (charset, critical) in [("abcdefghij", 27.88), ("0123456", 22.46)] {
+            let samples: String = (0..300)
+                .map(|_| {
+                    let password = generate_password_with(&mut rng, 1000, charset).unwrap();
+                    password.expose().to_string()
+                })
+                .collect();
+            let statistic = chi_squared(samples.chars(), charset);
+            assert!(statistic < critical, "{charset}: {statistic}");
+        }
+    }
+
+    #[test]
+    fn generated_handles_are_uniform() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let handles: Vec<String> = (0..20_000)
+            .map(|_| generate_handle_with(&mut rng))
+            .collect();
+        let at = |positions: &'static [usize]| {
+            handles
+                .iter()
+                .flat_map(move |h| positions.iter().map(move |&i| h.as_bytes()[i] as char))
+        };
+        let consonants = chi_squared(at(&[0, 2, 4]), "bdfghjklmnprstvz");
+        assert!(consonants < 37.70, "{consonants}");
+        let vowels = chi_squared(at(&[1, 3, 5]), "aeiou");
+        assert!(vowels < 18.47, "{vowels}");
+        let digits = chi_squared(at(&[6, 7]), "0123456789");
+        assert!(digits < 27.88, "{digits}");
+    }
+
+    #[test]
+    fn generates_usernames() {
+        let handle = generate_handle();
+        assert_eq!(handle.len(), 8);
+        assert!(handle[..6].bytes().all(|b| b.is_ascii_lowercase()));
+        assert!(handle[6..].bytes().all(|b| b.is_ascii_digit()));
+
+        let alias = generate_email_alias(Some("shop"), "example.com").unwrap();
+        let tag = alias.strip_prefix("shop+").unwrap();
+        assert_eq!(tag.find('@'), Some(4));
+        assert!(alias.ends_with("@example.com"));
+        assert!(generate_email_alias(None, "example.com")
+            .unwrap()
+            .ends_with("@example.com"));
+
+        for (base, domain) in [(None, "localhost"), (None, "a@b.c"), (Some("a+b"), "b.c")] {
+            assert!(matches!(
+                generate_email_alias(base, domain).unwrap_err(),
+                PwError::InvalidInput { .. }
+            ));
+        }
+    }
+
     #[test]
     fn generate_rejects_bad_input() {
         assert!(matches!(
@@ -694,6 +3344,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn password_hint_shows_ends_and_length() {
+        assert_eq!(password_hint(&"Abcdefghijklm9-".into()), "Ab…9- (15 chars)");
+        // Characters, not bytes, are counted and revealed.
+        assert_eq!(password_hint(&"åäcdefgö".into()), "åä…gö (8 chars)");
+    }
+
+    #[test]
+    fn password_hint_hides_short_passwords() {
+        assert_eq!(password_hint(&"secret7".into()), "… (7 chars)");
+    }
+
     #[test]
     fn debug_redacts_secrets() {
         let e = entry("a", "super secret");
@@ -708,6 +3370,18 @@ mod tests {
             username: "user".to_string(),
             password: "pw".into(),
             url: Some(url.to_string()),
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         }
     }
 
@@ -726,6 +3400,56 @@ mod tests {
             .collect()
     }
 
+    fn with_rule(name: &str, url: &str, url_match: UrlMatch) -> PasswordEntry {
+        let mut entry = with_url(name, url);
+        entry.url_match = url_match;
+        entry
+    }
+
+    #[test]
+    fn match_url_ranks_prefix_then_host_then_closest_domain() {
+        let entries = [
+            with_url("domain", "example.com"),
+            with_url("sub-domain", "shop.example.com"),
+            with_rule("host", "https://shop.example.com/", UrlMatch::Host),
+            with_rule("admin", "shop.example.com/admin", UrlMatch::Prefix),
+            with_rule(
+                "admin-users",
+                "shop.example.com/admin/users/",
+                UrlMatch::Prefix,
+            ),
+            with_rule("other-host", "example.com", UrlMatch::Host),
+        ];
+        let names = |url: &str| -> Vec<&str> {
+            match_url(url, &entries)
+                .into_iter()
+                .map(|e| e.name.as_str())
+                .collect()
+        };
+        assert_eq!(
+            names("https://shop.example.com/admin/users/42?tab=1"),
+            ["admin-users", "admin", "sub-domain", "host", "domain"]
+        );
+        assert_eq!(
+            names("https://shop.example.com/administrator"),
+            ["sub-domain", "host", "domain"]
+        );
+        assert_eq!(names("example.com"), ["domain", "other-host"]);
+        assert_eq!(names("www.example.com/"), ["domain"]);
+        assert!(names("example.org").is_empty());
+    }
+
+    #[test]
+    fn host_and_prefix_rules_match_the_exact_host_only() {
+        let entries = [
+            with_rule("host", "example.com", UrlMatch::Host),
+            with_rule("prefix", "example.com/app", UrlMatch::Prefix),
+        ];
+        assert!(matching_entries("www.example.com", &entries).is_empty());
+        // Without a path to compare, a prefix rule matches the host.
+        assert_eq!(matching_entries("example.com", &entries).len(), 2);
+    }
+
     #[test]
     fn matches_exact_hostname() {
         assert_eq!(matches("github.com", &["github.com"]), vec!["github.com"]);
@@ -814,6 +3538,18 @@ mod tests {
             username: "user".to_string(),
             password: "pw".into(),
             url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         }];
         assert!(matching_entries("github.com", &entries).is_empty());
     }
@@ -864,7 +3600,10 @@ mod tests {
         // An entry with a url keeps it across the encrypt/decrypt round-trip.
         assert_eq!(value["entries"][1]["url"], "github.com");
         assert_eq!(
-            get(&file, &passphrase(), "work").unwrap().url.as_deref(),
+            get(&file, &passphrase(), &entry_name("work"))
+                .unwrap()
+                .url
+                .as_deref(),
             Some("github.com")
         );
     }
@@ -877,6 +3616,18 @@ mod tests {
             username: String::new(),
             password: "pw".into(),
             url: Some("with\nnewline".to_string()),
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         };
         let err = add(&file, &passphrase(), bad, &TEST_PARAMS).unwrap_err();
         assert!(matches!(err, PwError::InvalidInput { what: "url", .. }));
@@ -888,13 +3639,14 @@ mod tests {
         update_keep_password(
             &file,
             &passphrase(),
-            "a",
+            &entry_name("a"),
             "new-user".to_string(),
             Some("github.com".to_string()),
+            UrlMatch::Host,
             &TEST_PARAMS,
         )
         .unwrap();
-        let e = get(&file, &passphrase(), "a").unwrap();
+        let e = get(&file, &passphrase(), &entry_name("a")).unwrap();
         // The password is untouched; the username and url are replaced.
         assert_eq!(e.password, "pw-a".into());
         assert_eq!(e.username, "new-user");
@@ -902,14 +3654,390 @@ mod tests {
     }
 
     #[test]
-    fn update_keep_password_unknown_name() {
+    fn tags_are_kept_sorted_and_omitted_when_empty() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
+        assert!(add_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap());
+        assert!(add_tag(
+            &file,
+            &passphrase(),
+            &entry_name("a"),
+            "shared",
+            &TEST_PARAMS
+        )
+        .unwrap());
+        assert!(!add_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap());
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("a")).unwrap().tags,
+            ["shared", "work"]
+        );
+
+        let json = export(&file, &passphrase()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(value["entries"][1].get("tags").is_none());
+
+        assert!(remove_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap());
+        assert!(!remove_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap());
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("a")).unwrap().tags,
+            ["shared"]
+        );
+    }
+
+    #[test]
+    fn tag_unknown_entry() {
+        let (_dir, file) = new_vault(&[]);
+        let err = add_tag(
+            &file,
+            &passphrase(),
+            &entry_name("nope"),
+            "work",
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PwError::NotFound { .. }));
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for tag in ["", "two words", "a,b", "tab\t"] {
+            assert!(
+                matches!(
+                    validate_tag(tag),
+                    Err(PwError::InvalidInput { what: "tag", .. })
+                ),
+                "tag {tag:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rename_tag_merges_and_counts() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b"), ("c", "pw-c")]);
+        add_tag(&file, &passphrase(), &entry_name("a"), "job", &TEST_PARAMS).unwrap();
+        add_tag(&file, &passphrase(), &entry_name("b"), "job", &TEST_PARAMS).unwrap();
+        add_tag(&file, &passphrase(), &entry_name("b"), "work", &TEST_PARAMS).unwrap();
+
+        assert_eq!(
+            rename_tag(&file, &passphrase(), "job", "work", &TEST_PARAMS).unwrap(),
+            2
+        );
+        assert_eq!(
+            rename_tag(&file, &passphrase(), "job", "work", &TEST_PARAMS).unwrap(),
+            0
+        );
+        let written = std::fs::read(&file).unwrap();
+        assert_eq!(
+            rename_tag(&file, &passphrase(), "work", "work", &TEST_PARAMS).unwrap(),
+            0
+        );
+        assert_eq!(std::fs::read(&file).unwrap(), written);
+        let entries = list(&file, &passphrase()).unwrap();
+        assert_eq!(entries[1].tags, ["work"]);
+        assert_eq!(
+            tag_counts(&entries).into_iter().collect::<Vec<_>>(),
+            [("work".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn update_keeps_tags() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        add_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap();
+        update(&file, &passphrase(), entry("a", "pw-new"), &TEST_PARAMS).unwrap();
+        let e = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!(e.password, "pw-new".into());
+        assert_eq!(e.tags, ["work"]);
+    }
+
+    #[test]
+    fn pin_survives_update_and_is_idempotent() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let a = entry_name("a");
+        assert!(set_pinned(&file, &passphrase(), &a, true, &TEST_PARAMS).unwrap());
+        assert!(!set_pinned(&file, &passphrase(), &a, true, &TEST_PARAMS).unwrap());
+        update(&file, &passphrase(), entry("a", "pw-new"), &TEST_PARAMS).unwrap();
+        assert!(get(&file, &passphrase(), &a).unwrap().pinned);
+        assert!(set_pinned(&file, &passphrase(), &a, false, &TEST_PARAMS).unwrap());
+        assert!(!get(&file, &passphrase(), &a).unwrap().pinned);
+        let err = set_pinned(&file, &passphrase(), &entry_name("b"), true, &TEST_PARAMS);
+        assert!(matches!(err.unwrap_err(), PwError::NotFound { .. }));
+    }
+
+    #[test]
+    fn pin_and_launch_count_as_updates() {
         let (_dir, file) = new_vault(&[]);
+        // Imported entries keep their timestamps, so these look old.
+        let old = |name: &str| {
+            let mut entry = entry(name, "pw");
+            entry.updated_at = Some(1);
+            entry
+        };
+        let options = ImportOptions::default();
+        import_entries(
+            &file,
+            &passphrase(),
+            vec![old("a"), old("b")],
+            options,
+            &TEST_PARAMS,
+        )
+        .unwrap();
+        let (a, b) = (entry_name("a"), entry_name("b"));
+        assert!(set_pinned(&file, &passphrase(), &a, true, &TEST_PARAMS).unwrap());
+        assert!(get(&file, &passphrase(), &a).unwrap().updated_at > Some(1));
+        let launch = Some("ssh host".to_string());
+        set_launch(&file, &passphrase(), &b, launch, &TEST_PARAMS).unwrap();
+        let b = get(&file, &passphrase(), &b).unwrap();
+        assert_eq!(b.launch.as_deref(), Some("ssh host"));
+        assert!(b.updated_at > Some(1));
+    }
+
+    #[test]
+    fn rename_keeps_the_entry_and_refuses_taken_names() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
+        rename(
+            &file,
+            &passphrase(),
+            &entry_name("a"),
+            &entry_name("c"),
+            &TEST_PARAMS,
+        )
+        .unwrap();
+        let c = get(&file, &passphrase(), &entry_name("c")).unwrap();
+        assert_eq!(c.password.expose(), "pw-a");
+        assert!(matches!(
+            get(&file, &passphrase(), &entry_name("a")),
+            Err(PwError::NotFound { .. })
+        ));
+
+        let err = rename(
+            &file,
+            &passphrase(),
+            &entry_name("c"),
+            &entry_name("b"),
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { name, .. } if name == "b"));
+        let err = rename(
+            &file,
+            &passphrase(),
+            &entry_name("a"),
+            &entry_name("d"),
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PwError::NotFound { name, .. } if name == "a"));
+    }
+
+    #[test]
+    fn export_vault_writes_the_selection_under_a_new_passphrase() {
+        let (dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b"), ("c", "pw-c")]);
+        update(&file, &passphrase(), entry("a", "pw-a2"), &TEST_PARAMS).unwrap();
+        for name in ["a", "c"] {
+            add_tag(
+                &file,
+                &passphrase(),
+                &entry_name(name),
+                "travel",
+                &TEST_PARAMS,
+            )
+            .unwrap();
+        }
+        let out = dir.path().join("travel.scrypt");
+        let travel = Passphrase::new("travel passphrase".to_string());
+        let filter = filter::Filter::Tag("travel".to_string());
+
+        let count =
+            export_vault(&file, &passphrase(), &filter, &out, &travel, &TEST_PARAMS).unwrap();
+        assert_eq!(count, 2);
+        let exported = list(&out, &travel).unwrap();
+        let names: Vec<_> = exported.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a", "c"]);
+        assert_eq!(exported[0].password.expose(), "pw-a2");
+        assert!(exported[0].history.is_empty());
+        assert!(matches!(
+            list(&out, &passphrase()),
+            Err(PwError::WrongPassphrase)
+        ));
+
+        let err =
+            export_vault(&file, &passphrase(), &filter, &out, &travel, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(err, PwError::FileAlreadyExists(_)));
+        let none = filter::Filter::Tag("none".to_string());
+        let other = dir.path().join("other.scrypt");
         let err =
-            update_keep_password(&file, &passphrase(), "a", String::new(), None, &TEST_PARAMS)
-                .unwrap_err();
+            export_vault(&file, &passphrase(), &none, &other, &travel, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(
+            err,
+            PwError::InvalidInput {
+                what: "selection",
+                ..
+            }
+        ));
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn apply_edits_renames_and_retags_in_one_write() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b"), ("c", "pw-c")]);
+        let entries = list(&file, &passphrase()).unwrap();
+        let mut edits = BTreeMap::new();
+        let mut a = EntryEdit::of(&entries[0], false);
+        a.name = "renamed".to_string();
+        a.tags = vec!["work".to_string(), "home".to_string(), "work".to_string()];
+        edits.insert("a".to_string(), a);
+        // Unchanged edits are not counted.
+        edits.insert("b".to_string(), EntryEdit::of(&entries[1], true));
+
+        assert_eq!(
+            apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap(),
+            1
+        );
+        let e = get(&file, &passphrase(), &entry_name("renamed")).unwrap();
+        assert_eq!(e.password, "pw-a".into());
+        assert_eq!(e.tags, ["home", "work"]);
+        assert!(get(&file, &passphrase(), &entry_name("a")).is_err());
+    }
+
+    #[test]
+    fn apply_edits_rejects_clashing_renames() {
+        let (_dir, file) = new_vault(&[("a", "pw-a"), ("b", "pw-b")]);
+        let entries = list(&file, &passphrase()).unwrap();
+        let mut a = EntryEdit::of(&entries[0], false);
+        a.name = "b".to_string();
+        let edits = BTreeMap::from([("a".to_string(), a)]);
+        let err = apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(err, PwError::AlreadyExists { name, .. } if name == "b"));
+
+        // Swapping two names is fine.
+        let mut a = EntryEdit::of(&entries[0], false);
+        a.name = "b".to_string();
+        let mut b = EntryEdit::of(&entries[1], false);
+        b.name = "a".to_string();
+        let edits = BTreeMap::from([("a".to_string(), a), ("b".to_string(), b)]);
+        assert_eq!(
+            apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap(),
+            2
+        );
+        assert_eq!(
+            get(&file, &passphrase(), &entry_name("a"))
+                .unwrap()
+                .password,
+            "pw-b".into()
+        );
+    }
+
+    #[test]
+    fn apply_edits_whole_entry_sets_notes_and_fields() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let entry = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        let mut edit = EntryEdit::whole(&entry);
+        assert_eq!(edit.notes.as_deref(), Some(""));
+        let edits = BTreeMap::from([("a".to_string(), edit.clone())]);
+        assert_eq!(
+            apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap(),
+            0
+        );
+
+        edit.notes = Some("line 1\nline 2".to_string());
+        edit.fields = Some(BTreeMap::from([("pin".to_string(), "1234".into())]));
+        let edits = BTreeMap::from([("a".to_string(), edit.clone())]);
+        assert_eq!(
+            apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap(),
+            1
+        );
+        let edited = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!(edited.notes.as_deref(), Some("line 1\nline 2"));
+        assert_eq!(edited.fields["pin"], "1234".into());
+        assert!(edited.updated_at >= entry.updated_at);
+
+        // Empty notes remove them; leaving the fields out keeps them.
+        edit.notes = Some(String::new());
+        edit.fields = None;
+        let edits = BTreeMap::from([("a".to_string(), edit.clone())]);
+        apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap();
+        let edited = get(&file, &passphrase(), &entry_name("a")).unwrap();
+        assert_eq!(edited.notes, None);
+        assert_eq!(edited.fields.len(), 1);
+
+        edit.fields = Some(BTreeMap::from([("bad\tname".to_string(), "x".into())]));
+        let edits = BTreeMap::from([("a".to_string(), edit)]);
+        let err = apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(
+            err,
+            PwError::InvalidInput {
+                what: "field name",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn apply_edits_unknown_name() {
+        let (_dir, file) = new_vault(&[("a", "pw-a")]);
+        let entries = list(&file, &passphrase()).unwrap();
+        let edits = BTreeMap::from([("nope".to_string(), EntryEdit::of(&entries[0], false))]);
+        let err = apply_edits(&file, &passphrase(), edits, &TEST_PARAMS).unwrap_err();
+        assert!(matches!(err, PwError::NotFound { .. }));
+    }
+
+    #[test]
+    fn unknown_fields_survive_rewrites() {
+        let (_dir, file) = new_vault(&[]);
+        let from_newer: PasswordEntry = serde_json::from_str(
+            r#"{"name":"a","username":"u","password":"pw-a","notes":"n","otp":{"digits":6}}"#,
+        )
+        .unwrap();
+        add(&file, &passphrase(), from_newer, &TEST_PARAMS).unwrap();
+        update(&file, &passphrase(), entry("a", "pw-new"), &TEST_PARAMS).unwrap();
+        add_tag(&file, &passphrase(), &entry_name("a"), "work", &TEST_PARAMS).unwrap();
+
+        let json = export(&file, &passphrase()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["entries"][0]["password"], "pw-new");
+        assert_eq!(value["entries"][0]["notes"], "n");
+        assert_eq!(value["entries"][0]["otp"]["digits"], 6);
+    }
+
+    #[test]
+    fn update_keep_password_unknown_name() {
+        let (_dir, file) = new_vault(&[]);
+        let err = update_keep_password(
+            &file,
+            &passphrase(),
+            &entry_name("a"),
+            String::new(),
+            None,
+            UrlMatch::Domain,
+            &TEST_PARAMS,
+        )
+        .unwrap_err();
         assert!(matches!(err, PwError::NotFound { .. }));
     }
 
+    #[test]
+    fn browsable_url_adds_https_to_bare_hosts() {
+        assert_eq!(
+            browsable_url("github.com").as_deref(),
+            Some("https://github.com")
+        );
+        assert_eq!(
+            browsable_url("example.com/login").as_deref(),
+            Some("https://example.com/login")
+        );
+        assert_eq!(
+            browsable_url("HTTP://localhost:3000/").as_deref(),
+            Some("http://localhost:3000/")
+        );
+    }
+
+    #[test]
+    fn browsable_url_rejects_other_schemes() {
+        for url in ["file:///etc/passwd", "ftp://example.com", "https://", ""] {
+            assert!(browsable_url(url).is_none(), "url {url:?}");
+        }
+    }
+
     #[test]
     fn origin_hostname_accepts_https() {
         assert_eq!(
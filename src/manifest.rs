@@ -0,0 +1,218 @@
+//! Manifests for `pw apply`: the entries a vault should have, declared in a
+//! JSON file that can be kept under version control, with no passwords in it.
+//!
+//! ```json
+//! {"entries": [
+//!   {"name": "ci/deploy-token", "username": "deploy",
+//!    "url": "https://ci.example.com", "tags": ["ci", "team"],
+//!    "length": 32, "charset": "0123456789abcdef",
+//!    "notes": "Rotated by the platform team."}
+//! ]}
+//! ```
+//!
+//! `length` (16 by default) and `charset` are those of the password generated
+//! for a new entry. Manifests are JSON, like [`crate::policy`] files and the
+//! edits of `pw bulk-edit`, rather than TOML, which would need a parser of
+//! its own.
+
+use serde::Deserialize;
+
+use crate::{
+    generate_password, same_name, validate_entry, EntryName, PasswordEntry, PwError, Secret,
+    DEFAULT_CHARSET,
+};
+
+/// The length of generated passwords when an entry does not give one.
+pub const DEFAULT_LENGTH: u32 = 16;
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The declared entries, in file order.
+    #[serde(default)]
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// One declared entry. Everything but the name is optional.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    pub name: String,
+    #[serde(default)]
+    pub username: String,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    /// Length of the password generated for a new entry.
+    #[serde(default = "default_length")]
+    pub length: u32,
+    /// Characters of the password generated for a new entry.
+    #[serde(default = "default_charset")]
+    pub charset: String,
+}
+
+fn default_length() -> u32 {
+    DEFAULT_LENGTH
+}
+
+fn default_charset() -> String {
+    DEFAULT_CHARSET.to_string()
+}
+
+impl Manifest {
+    pub fn from_json(json: &str) -> Result<Self, PwError> {
+        let manifest: Manifest = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+        for (i, declared) in manifest.entries.iter().enumerate() {
+            EntryName::new(declared.name.as_str())?;
+            let earlier = &manifest.entries[..i];
+            if earlier.iter().any(|e| same_name(&e.name, &declared.name)) {
+                return Err(invalid(format!(
+                    "'{}' is declared more than once",
+                    declared.name
+                )));
+            }
+            validate_entry(&declared.to_entry(Secret::new(String::new())))?;
+            generate_password(declared.length, &declared.charset)?;
+        }
+        Ok(manifest)
+    }
+}
+
+impl ManifestEntry {
+    /// The entry as declared, with `password`.
+    pub fn to_entry(&self, password: Secret) -> PasswordEntry {
+        let mut tags = self.tags.clone();
+        tags.sort();
+        tags.dedup();
+        PasswordEntry {
+            name: self.name.clone(),
+            username: self.username.clone(),
+            password,
+            url: self.url.clone(),
+            url_match: Default::default(),
+            launch: None,
+            notes: self.notes.clone(),
+            totp: None,
+            pinned: false,
+            tags,
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }
+    }
+
+    /// How `entry` differs from the declaration, one line per attribute;
+    /// empty if it matches. Attributes the manifest leaves out are not
+    /// compared, and the password only against the length and charset.
+    pub fn drift(&self, entry: &PasswordEntry) -> Vec<String> {
+        debug_assert!(same_name(&entry.name, &self.name));
+        let mut drift = Vec::new();
+        if !self.username.is_empty() && entry.username != self.username {
+            drift.push(format!(
+                "username is '{}', the manifest has '{}'",
+                entry.username, self.username
+            ));
+        }
+        if let Some(url) = &self.url {
+            match &entry.url {
+                Some(actual) if actual == url => {}
+                Some(actual) => drift.push(format!("url is {actual}, the manifest has {url}")),
+                None => drift.push(format!("has no url, the manifest has {url}")),
+            }
+        }
+        let missing: Vec<_> = self
+            .tags
+            .iter()
+            .filter(|tag| !entry.tags.contains(tag))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            drift.push(format!("lacks the tags {}", missing.join(", ")));
+        }
+        if self.notes.is_some() && entry.notes != self.notes {
+            drift.push("notes differ from the manifest".to_string());
+        }
+        let password = entry.password.expose();
+        if (password.chars().count() as u64) < u64::from(self.length) {
+            drift.push(format!("password is shorter than {}", self.length));
+        }
+        if password.chars().any(|c| !self.charset.contains(c)) {
+            drift.push("password has characters outside the charset".to_string());
+        }
+        drift
+    }
+}
+
+fn invalid(reason: String) -> PwError {
+    PwError::InvalidInput {
+        what: "manifest",
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_entries_with_defaults() {
+        let json = r#"{"entries": [
+            {"name": "ci/deploy-token", "username": "deploy", "tags": ["ci", "team"],
+             "length": 20, "notes": "line one\nline two"},
+            {"name": "db é"}
+        ]}"#;
+        let manifest = Manifest::from_json(json).unwrap();
+        let [ci, db] = &manifest.entries[..] else {
+            panic!("expected two entries");
+        };
+        assert_eq!(ci.name, "ci/deploy-token");
+        assert_eq!(ci.username, "deploy");
+        assert_eq!(ci.tags, ["ci", "team"]);
+        assert_eq!(ci.length, 20);
+        assert_eq!(ci.notes.as_deref(), Some("line one\nline two"));
+        assert_eq!(db.name, "db é");
+        assert_eq!(
+            (db.length, db.charset.as_str()),
+            (DEFAULT_LENGTH, DEFAULT_CHARSET)
+        );
+        assert_eq!(Manifest::from_json("{}").unwrap(), Manifest::default());
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        let err = |json: &str| Manifest::from_json(json).unwrap_err().to_string();
+        assert!(err(r#"{"entries": [{"name": "a", "colour": "red"}]}"#)
+            .contains("unknown field `colour`"));
+        assert!(err(r#"{"entries": [{"name": "a"}, {"name": "a"}]}"#).contains("more than once"));
+        assert!(err(r#"{"entries": [{"username": "a"}]}"#).contains("missing field `name`"));
+        assert!(err(r#"{"entries": [{"name": "a", "length": 0}]}"#).contains("length"));
+    }
+
+    #[test]
+    fn drift_compares_declared_attributes() {
+        let manifest = Manifest::from_json(
+            r#"{"entries": [{"name": "a", "username": "alice", "tags": ["x"], "length": 8, "charset": "ab"}]}"#,
+        )
+        .unwrap();
+        let declared = &manifest.entries[0];
+        let entry = declared.to_entry(Secret::new("abababab".to_string()));
+        assert!(declared.drift(&entry).is_empty());
+        let mut entry = declared.to_entry(Secret::new("abc".to_string()));
+        entry.username = "bob".to_string();
+        entry.tags.clear();
+        entry.url = Some("https://example.com".to_string());
+        assert_eq!(
+            declared.drift(&entry),
+            [
+                "username is 'bob', the manifest has 'alice'",
+                "lacks the tags x",
+                "password is shorter than 8",
+                "password has characters outside the charset",
+            ]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! [pass](https://www.passwordstore.org/) stores, for
+//! `pw import --format pass`.
+//!
+//! A store is a directory tree of gpg-encrypted files, one per entry, named
+//! by their path: `email/github.com.gpg` is the entry `email/github.com`.
+//! Decrypting them is up to the caller; [`entry`] maps the decrypted text
+//! the way pass users conventionally write it: the password on the first
+//! line, then optional `username:` (or `user:` or `login:`) and `url:`
+//! lines and an `otpauth://` uri as pass-otp writes it. All other lines
+//! become the notes.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use crate::{totp, PasswordEntry, Secret};
+
+/// The encrypted files of the store at `dir` with their entry names, sorted
+/// by name. Hidden files and directories, such as `.git` and `.gpg-id`, are
+/// skipped, as are files that do not end in `.gpg`.
+pub fn files(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for dir_entry in fs::read_dir(&current)? {
+            let path = dir_entry?.path();
+            let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if file_name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if file_name.ends_with(".gpg") {
+                let relative = path.strip_prefix(dir).expect("walked from dir");
+                let name = relative
+                    .with_extension("")
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((name, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The entry `name` with the decrypted contents `text` of its file.
+pub fn entry(name: &str, text: &str) -> PasswordEntry {
+    let mut lines = text.lines();
+    let password = lines.next().unwrap_or_default();
+    let mut entry = PasswordEntry {
+        name: name.to_string(),
+        username: String::new(),
+        password: Secret::new(password.to_string()),
+        url: None,
+        url_match: Default::default(),
+        launch: None,
+        notes: None,
+        totp: None,
+        pinned: false,
+        tags: Vec::new(),
+        fields: BTreeMap::new(),
+        history: Vec::new(),
+        created_at: None,
+        updated_at: None,
+        expires_at: None,
+        extra: Default::default(),
+    };
+    let mut notes = Vec::new();
+    for line in lines {
+        let (key, value) = line.split_once(':').unwrap_or_default();
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "username" | "user" | "login" if entry.username.is_empty() => {
+                entry.username = value.to_string();
+            }
+            "url" if entry.url.is_none() && !value.is_empty() => {
+                entry.url = Some(value.to_string());
+            }
+            "otpauth" if entry.totp.is_none() && totp::Totp::parse(line).is_ok() => {
+                entry.totp = Some(Secret::new(line.to_string()));
+            }
+            _ => notes.push(line),
+        }
+    }
+    let notes = notes.join("\n");
+    if !notes.trim().is_empty() {
+        entry.notes = Some(notes.trim_end().to_string());
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_the_conventional_layout() {
+        let entry = entry(
+            "email/github.com",
+            "s3cret\nUsername: alice\nurl: https://github.com\n\
+             otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP\n\
+             recovery: abc-def\n",
+        );
+        assert_eq!(entry.name, "email/github.com");
+        assert_eq!(entry.password, "s3cret".into());
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+        assert!(entry.totp.is_some());
+        assert_eq!(entry.notes.as_deref(), Some("recovery: abc-def"));
+
+        let bare = super::entry("wifi", "hunter2");
+        assert_eq!(bare.password, "hunter2".into());
+        assert!(bare.username.is_empty() && bare.notes.is_none());
+    }
+
+    #[test]
+    fn files_are_named_by_their_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("email")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        for file in ["email/github.com.gpg", "bank.gpg", ".gpg-id", ".git/x.gpg"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        let names: Vec<_> = files(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["bank", "email/github.com"]);
+    }
+}
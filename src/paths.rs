@@ -0,0 +1,124 @@
+//! Where pw keeps its files on each platform, resolved in one place with the
+//! platform conventions of the `dirs` crate: the XDG base directories on
+//! Linux (honouring `$XDG_CONFIG_HOME` and the rest), `~/Library` on macOS
+//! and the known folders on Windows. Each directory is pw's own subdirectory,
+//! such as `~/.config/pw`; none of them is created here.
+//!
+//! The default vault is the exception: it stays `~/pw.scrypt` on every
+//! platform, where it has always been.
+
+use std::path::{Path, PathBuf};
+
+/// The directories pw uses, as printed by `pw status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// The home directory, or `.` if it cannot be determined.
+    pub home: PathBuf,
+    /// The default vault, `~/pw.scrypt`.
+    pub vault: PathBuf,
+    /// Configuration, such as the browser host's `browser.json`:
+    /// `~/.config/pw`, `~/Library/Application Support/pw` or
+    /// `%APPDATA%\pw`.
+    pub config: PathBuf,
+    /// Data that is not configuration: `~/.local/share/pw` on Linux.
+    pub data: PathBuf,
+    /// Files that can be recreated at any time: `~/.cache/pw` on Linux.
+    pub cache: PathBuf,
+    /// State to keep across runs, such as logs: `~/.local/state/pw` on
+    /// Linux, and the data directory where there is no such convention.
+    pub state: PathBuf,
+    /// Sockets and other files that live only as long as the login session:
+    /// `$XDG_RUNTIME_DIR/pw`. `None` where the platform has no such
+    /// directory (macOS and Windows) or it is not set.
+    pub runtime: Option<PathBuf>,
+}
+
+impl Paths {
+    /// The directories for the current user and platform.
+    pub fn resolve() -> Self {
+        Self::from_dirs(
+            dirs::home_dir(),
+            dirs::config_dir(),
+            dirs::data_dir(),
+            dirs::cache_dir(),
+            dirs::state_dir(),
+            dirs::runtime_dir(),
+        )
+    }
+
+    /// The paths under the platform directories given, each falling back to
+    /// its Linux location in the home directory when it is unknown.
+    fn from_dirs(
+        home: Option<PathBuf>,
+        config: Option<PathBuf>,
+        data: Option<PathBuf>,
+        cache: Option<PathBuf>,
+        state: Option<PathBuf>,
+        runtime: Option<PathBuf>,
+    ) -> Self {
+        let home = home.unwrap_or_else(|| PathBuf::from("."));
+        let under = |dir: Option<PathBuf>, fallback: &str| {
+            dir.unwrap_or_else(|| home.join(fallback)).join("pw")
+        };
+        let data = under(data, ".local/share");
+        Paths {
+            vault: home.join("pw.scrypt"),
+            config: under(config, ".config"),
+            cache: under(cache, ".cache"),
+            state: state.map_or_else(|| data.clone(), |state| state.join("pw")),
+            data,
+            runtime: runtime.map(|runtime| runtime.join("pw")),
+            home,
+        }
+    }
+
+    /// Expand a leading `~/` in a configured path to the home directory.
+    pub fn expand_tilde(&self, path: &str) -> PathBuf {
+        match path.strip_prefix("~/") {
+            Some(rest) => self.home.join(rest),
+            None => Path::new(path).to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_the_platform_directories() {
+        let paths = Paths::from_dirs(
+            Some("/home/alice".into()),
+            Some("/home/alice/.config".into()),
+            Some("/home/alice/.local/share".into()),
+            Some("/var/cache/alice".into()),
+            Some("/home/alice/.local/state".into()),
+            Some("/run/user/1001".into()),
+        );
+        assert_eq!(paths.vault, Path::new("/home/alice/pw.scrypt"));
+        assert_eq!(paths.config, Path::new("/home/alice/.config/pw"));
+        assert_eq!(paths.cache, Path::new("/var/cache/alice/pw"));
+        assert_eq!(paths.state, Path::new("/home/alice/.local/state/pw"));
+        assert_eq!(
+            paths.runtime.as_deref(),
+            Some(Path::new("/run/user/1001/pw"))
+        );
+        assert_eq!(
+            paths.expand_tilde("~/vaults/pw.scrypt"),
+            Path::new("/home/alice/vaults/pw.scrypt")
+        );
+        assert_eq!(paths.expand_tilde("/etc/pw"), Path::new("/etc/pw"));
+    }
+
+    #[test]
+    fn falls_back_to_the_home_directory() {
+        let paths = Paths::from_dirs(Some("/Users/bob".into()), None, None, None, None, None);
+        assert_eq!(paths.config, Path::new("/Users/bob/.config/pw"));
+        assert_eq!(paths.data, Path::new("/Users/bob/.local/share/pw"));
+        assert_eq!(paths.state, paths.data);
+        assert_eq!(paths.runtime, None);
+
+        let nowhere = Paths::from_dirs(None, None, None, None, None, None);
+        assert_eq!(nowhere.vault, Path::new("./pw.scrypt"));
+    }
+}
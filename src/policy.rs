@@ -0,0 +1,187 @@
+//! Declarative password policies, for checking stored entries with
+//! `pw audit --policy`.
+//!
+//! A policy is a small JSON document; every rule is optional:
+//!
+//! ```json
+//! {"min_length": 12, "require": ["lowercase", "uppercase", "digit"],
+//!  "forbid_username": true, "forbidden": ["acme", "2024"], "min_score": 3}
+//! ```
+//!
+//! Violations describe the rule that is broken, never the password.
+
+use serde::Deserialize;
+
+use crate::{strength, PasswordEntry, PwError};
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Shortest acceptable password, in characters.
+    #[serde(default)]
+    pub min_length: usize,
+    /// Character classes every password must contain.
+    #[serde(default)]
+    pub require: Vec<CharClass>,
+    /// Reject passwords that contain the entry's username.
+    #[serde(default)]
+    pub forbid_username: bool,
+    /// Text no password may contain, such as a company name.
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    /// Lowest acceptable [`strength::score`], 0 to 4.
+    #[serde(default)]
+    pub min_score: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn matches(self, c: char) -> bool {
+        match self {
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            CharClass::Lowercase => "a lowercase letter",
+            CharClass::Uppercase => "an uppercase letter",
+            CharClass::Digit => "a digit",
+            CharClass::Symbol => "a symbol",
+        }
+    }
+}
+
+impl Policy {
+    pub fn from_json(json: &str) -> Result<Self, PwError> {
+        serde_json::from_str(json).map_err(|e| PwError::InvalidInput {
+            what: "policy",
+            reason: e.to_string(),
+        })
+    }
+
+    /// The rules `entry`'s password breaks; empty if it complies.
+    pub fn check(&self, entry: &PasswordEntry) -> Vec<String> {
+        let password = entry.password.expose();
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(format!(
+                "shorter than {} characters ({length})",
+                self.min_length
+            ));
+        }
+        for class in &self.require {
+            if !password.chars().any(|c| class.matches(c)) {
+                violations.push(format!("does not contain {}", class.name()));
+            }
+        }
+        let lowercase = password.to_lowercase();
+        if self.forbid_username
+            && !entry.username.is_empty()
+            && lowercase.contains(&entry.username.to_lowercase())
+        {
+            violations.push("contains the username".to_string());
+        }
+        for text in &self.forbidden {
+            if !text.is_empty() && lowercase.contains(&text.to_lowercase()) {
+                violations.push(format!("contains '{text}'"));
+            }
+        }
+        if self.min_score > 0 {
+            let estimate = strength::estimate(password);
+            if estimate.score < self.min_score {
+                let mut violation = format!(
+                    "too easy to guess (score {} of 4, at least {} required)",
+                    estimate.score, self.min_score
+                );
+                if let Some(warning) = estimate.warnings.first() {
+                    violation.push_str(&format!(": {warning}"));
+                }
+                violations.push(violation);
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(username: &str, password: &str) -> PasswordEntry {
+        PasswordEntry {
+            name: "a".to_string(),
+            username: username.to_string(),
+            password: password.into(),
+            url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        let policy = Policy::from_json(
+            r#"{"min_length": 12, "require": ["uppercase", "digit", "symbol"],
+                "forbid_username": true, "forbidden": ["ACME"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.check(&entry("Alice", "alice-acme")),
+            [
+                "shorter than 12 characters (10)",
+                "does not contain an uppercase letter",
+                "does not contain a digit",
+                "contains the username",
+                "contains 'ACME'",
+            ]
+        );
+        assert!(policy.check(&entry("alice", "Xk9#mQ2v-pL7w")).is_empty());
+
+        let policy = Policy::from_json(r#"{"min_score": 3}"#).unwrap();
+        assert!(policy.check(&entry("alice", "Xk9#mQ2v-pL7w")).is_empty());
+        assert_eq!(
+            policy.check(&entry("bob", "Password1!")),
+            [
+                "too easy to guess (score 0 of 4, at least 3 required): is a common password or \
+                 a variation of one"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_policy_accepts_anything() {
+        let policy = Policy::from_json("{}").unwrap();
+        assert_eq!(policy, Policy::default());
+        assert!(policy.check(&entry("", "")).is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_rules() {
+        let err = Policy::from_json(r#"{"min_lenght": 12}"#).unwrap_err();
+        assert!(matches!(err, PwError::InvalidInput { what: "policy", .. }));
+        assert!(Policy::from_json(r#"{"require": ["emoji"]}"#).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! Soft limits on risky uses of pw, such as exporting the passwords
+//! unencrypted or printing them into a pipe.
+//!
+//! Each [`Risk`] but [`Risk::ShowToPipe`], the way scripts read secrets, is
+//! warned about by default. `~/.config/pw/safety.json` (see
+//! [`crate::paths::Paths::config`]) can instead allow it silently, warn about
+//! it or block it:
+//!
+//! ```json
+//! {"plaintext-export": "block", "show-to-pipe": "warn"}
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::PwError;
+
+/// Generated passwords shorter than this are a [`Risk::ShortPassword`].
+pub const MIN_PASSWORD_LENGTH: u32 = 12;
+
+/// The name of the configuration file in pw's configuration directory.
+pub const CONFIG_FILE: &str = "safety.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Risk {
+    /// Generating a password shorter than [`MIN_PASSWORD_LENGTH`].
+    ShortPassword,
+    /// Choosing a weak vault passphrase with `--allow-weak`.
+    WeakPassphrase,
+    /// Writing the vault with the weaker scrypt parameters of `--low-memory`.
+    WeakScrypt,
+    /// Exporting the passwords unencrypted, as JSON or CSV.
+    PlaintextExport,
+    /// Printing secrets to a terminal, where they stay in its scrollback.
+    ShowToTerminal,
+    /// Printing secrets into a pipe or file, where other programs or logs
+    /// may keep them; allowed by default.
+    ShowToPipe,
+}
+
+impl Risk {
+    /// The name used in `safety.json`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Risk::ShortPassword => "short-password",
+            Risk::WeakPassphrase => "weak-passphrase",
+            Risk::WeakScrypt => "weak-scrypt",
+            Risk::PlaintextExport => "plaintext-export",
+            Risk::ShowToTerminal => "show-to-terminal",
+            Risk::ShowToPipe => "show-to-pipe",
+        }
+    }
+
+    /// The action when `safety.json` does not configure one.
+    pub fn default_action(self) -> Action {
+        match self {
+            Risk::ShowToPipe => Action::Allow,
+            _ => Action::Warn,
+        }
+    }
+}
+
+/// What to do about a risk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Go ahead silently.
+    Allow,
+    /// Go ahead with a warning.
+    Warn,
+    /// Refuse.
+    Block,
+}
+
+/// The configured action for each risk; [`Risk::default_action`] for any
+/// not configured.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Safety {
+    actions: BTreeMap<Risk, Action>,
+}
+
+impl Safety {
+    /// Parse a `safety.json`; unknown risks and actions are errors.
+    pub fn from_json(text: &str) -> Result<Self, PwError> {
+        serde_json::from_str(text).map_err(|e| PwError::InvalidInput {
+            what: "safety configuration",
+            reason: e.to_string(),
+        })
+    }
+
+    /// Read the configuration at `path`; a missing file is the default.
+    pub fn load(path: &Path) -> Result<Self, PwError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_json(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(PwError::InvalidInput {
+                what: "safety configuration",
+                reason: format!("cannot read {}: {e}", path.display()),
+            }),
+        }
+    }
+
+    /// What to do about `risk`.
+    pub fn action(&self, risk: Risk) -> Action {
+        self.actions
+            .get(&risk)
+            .copied()
+            .unwrap_or_else(|| risk.default_action())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unconfigured_risks_get_their_default() {
+        let safety =
+            Safety::from_json(r#"{"plaintext-export": "block", "show-to-pipe": "warn"}"#).unwrap();
+        assert_eq!(safety.action(Risk::PlaintextExport), Action::Block);
+        assert_eq!(safety.action(Risk::ShowToPipe), Action::Warn);
+        assert_eq!(Safety::default().action(Risk::ShowToPipe), Action::Allow);
+        assert_eq!(safety.action(Risk::ShortPassword), Action::Warn);
+        assert_eq!(Safety::default().action(Risk::WeakScrypt), Action::Warn);
+    }
+
+    #[test]
+    fn unknown_risks_and_actions_are_errors() {
+        assert!(Safety::from_json(r#"{"show-to-pipe": "ignore"}"#).is_err());
+        assert!(Safety::from_json(r#"{"short-passwords": "block"}"#).is_err());
+        assert!(Safety::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn a_missing_file_is_the_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        assert_eq!(Safety::load(&path).unwrap(), Safety::default());
+        fs::write(&path, r#"{"weak-passphrase": "block"}"#).unwrap();
+        assert_eq!(
+            Safety::load(&path).unwrap().action(Risk::WeakPassphrase),
+            Action::Block
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Looking for plaintext copies of vault secrets left on disk, for
+//! `pw scan`: forgotten `pw export` files, `.env` files with a stored
+//! password pasted in, and the like.
+//!
+//! Findings name the file and the entries whose passwords it contains, never
+//! the passwords themselves.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use zeroize::Zeroizing;
+
+use crate::{vault, PasswordEntry};
+
+/// Passwords shorter than this are not searched for: they would match too
+/// much unrelated text.
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// Files larger than this are skipped; secrets files are small.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub path: PathBuf,
+    /// Why the file looks like it holds secrets, one reason per item.
+    pub reasons: Vec<String>,
+}
+
+/// Scan `dir` and its subdirectories for files holding any of `entries`'
+/// passwords, or a plaintext vault export. Symbolic links are not followed,
+/// and files or subdirectories that cannot be read are skipped. Findings are
+/// sorted by path.
+pub fn scan(dir: &Path, entries: &[PasswordEntry]) -> io::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    let mut first = true;
+    while let Some(dir) = pending.pop() {
+        let listing = match fs::read_dir(&dir) {
+            Ok(listing) => listing,
+            Err(_) if !first => continue,
+            Err(err) => return Err(err),
+        };
+        first = false;
+        for item in listing.flatten() {
+            let Ok(file_type) = item.file_type() else {
+                continue;
+            };
+            let path = item.path();
+            if file_type.is_dir() {
+                if item.file_name() != ".git" {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() {
+                let reasons = check_file(&path, entries);
+                if !reasons.is_empty() {
+                    findings.push(Finding { path, reasons });
+                }
+            }
+        }
+    }
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(findings)
+}
+
+fn check_file(path: &Path, entries: &[PasswordEntry]) -> Vec<String> {
+    if fs::metadata(path).map_or(true, |m| m.len() > MAX_FILE_SIZE) {
+        return Vec::new();
+    }
+    let Ok(content) = fs::read(path).map(Zeroizing::new) else {
+        return Vec::new();
+    };
+    let mut reasons = Vec::new();
+    if vault::from_json(&content).is_ok_and(|exported| !exported.is_empty()) {
+        reasons.push("a plaintext vault export".to_string());
+    }
+    let text = Zeroizing::new(String::from_utf8_lossy(&content).into_owned());
+    for entry in entries {
+        let password = entry.password.expose();
+        if password.chars().count() >= MIN_PASSWORD_LEN && text.contains(password) {
+            reasons.push(format!("contains the password of '{}'", entry.name));
+        }
+    }
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, password: &str) -> PasswordEntry {
+        PasswordEntry {
+            name: name.to_string(),
+            username: "alice".to_string(),
+            password: password.into(),
+            url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn finds_exports_and_stored_passwords() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = [entry("db", "Xk9#mQ2v-pL7w"), entry("pin", "1234")];
+        fs::create_dir(dir.path().join("app")).unwrap();
+        fs::write(dir.path().join("app/.env"), "DB_PASSWORD=Xk9#mQ2v-pL7w\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "pin is 1234\n").unwrap();
+        fs::write(
+            dir.path().join("backup.json"),
+            vault::to_json(&entries).unwrap().as_bytes(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("empty.json"), "[]").unwrap();
+
+        let findings = scan(dir.path(), &entries).unwrap();
+        assert_eq!(
+            findings,
+            [
+                Finding {
+                    path: dir.path().join("app/.env"),
+                    reasons: vec!["contains the password of 'db'".to_string()],
+                },
+                Finding {
+                    path: dir.path().join("backup.json"),
+                    reasons: vec![
+                        "a plaintext vault export".to_string(),
+                        "contains the password of 'db'".to_string(),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_only_if_the_directory_cannot_be_read() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(scan(dir.path(), &[]).unwrap().is_empty());
+        assert!(scan(&dir.path().join("missing"), &[]).is_err());
+    }
+}
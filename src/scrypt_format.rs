@@ -25,6 +25,8 @@
 //! where `dk = scrypt(passphrase, salt, N, r, p)` (64 bytes),
 //! `key_enc = dk[0..32]` and `key_hmac = dk[32..64]`.
 
+use std::time::{Duration, Instant};
+
 use aes::Aes256;
 use ctr::cipher::{KeyIvInit, StreamCipher};
 use hmac::{Hmac, KeyInit, Mac};
@@ -38,7 +40,7 @@ type Aes256Ctr = ctr::Ctr128BE<Aes256>;
 
 const MAGIC: &[u8; 6] = b"scrypt";
 const VERSION: u8 = 0;
-const SALT_LEN: usize = 32;
+pub const SALT_LEN: usize = 32;
 
 pub const HEADER_LEN: usize = 96;
 pub const TRAILER_LEN: usize = 32;
@@ -70,6 +72,75 @@ impl Default for Params {
     }
 }
 
+impl Params {
+    /// Reduced write-side parameters for memory-constrained devices such as
+    /// a Raspberry Pi: `N = 2^14, r = 8, p = 1` (~16 MiB). Each passphrase
+    /// guess costs an attacker about an eighth of the default.
+    pub fn low_memory() -> Self {
+        Params {
+            log_n: 14,
+            ..Params::default()
+        }
+    }
+
+    /// The memory the KDF needs: `128 * r * N` bytes.
+    pub fn memory(&self) -> u64 {
+        (128u64 << self.log_n) * u64::from(self.r)
+    }
+
+    /// The work the KDF does, and so the cost of each passphrase guess, in
+    /// units of `N * r * p`; comparable between parameter sets.
+    pub fn cost(&self) -> u64 {
+        (1u64 << self.log_n) * u64::from(self.r) * u64::from(self.p)
+    }
+
+    /// The strongest parameters (`r = 8, p = 1`, the largest `N`) whose KDF
+    /// needs at most `max_memory` bytes and takes at most `max_time` on this
+    /// machine, like the `-M` and `-t` options of the `scrypt` tool. The
+    /// time is estimated with a short benchmark. Never goes below
+    /// `N = 2^10`, nor above what [`decrypt`] accepts.
+    pub fn tuned(max_memory: u64, max_time: Duration) -> Self {
+        Self::fitting(max_memory, max_time, time_per_block())
+    }
+
+    fn fitting(max_memory: u64, max_time: Duration, per_block: Duration) -> Self {
+        let mut params = Params {
+            log_n: MIN_TUNED_LOG_N,
+            ..Params::default()
+        };
+        loop {
+            let next = Params {
+                log_n: params.log_n + 1,
+                ..params
+            };
+            let time = per_block.saturating_mul(u32::try_from(next.cost()).unwrap_or(u32::MAX));
+            if validate(&next).is_err() || next.memory() > max_memory || time > max_time {
+                return params;
+            }
+            params = next;
+        }
+    }
+}
+
+/// The smallest `log2(N)` [`Params::tuned`] picks.
+const MIN_TUNED_LOG_N: u8 = 10;
+
+/// How long the KDF takes per unit of [`Params::cost`] on this machine,
+/// measured by running it with `N = 2^10` for at least 50 ms.
+fn time_per_block() -> Duration {
+    let params = Params {
+        log_n: MIN_TUNED_LOG_N,
+        ..Params::default()
+    };
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while runs == 0 || start.elapsed() < Duration::from_millis(50) {
+        derive_keys(b"benchmark", &[0; SALT_LEN], &params).expect("valid parameters");
+        runs += 1;
+    }
+    start.elapsed() / runs / u32::try_from(params.cost()).expect("small cost")
+}
+
 #[derive(thiserror::Error, Debug, PartialEq, Eq)]
 pub enum Error {
     #[error("not an scrypt-encrypted file")]
@@ -140,37 +211,39 @@ fn hmac(key_hmac: &[u8], data: &[u8]) -> HmacSha256 {
 
 /// Encrypt `plaintext` into a self-contained scrypt-format file image.
 pub fn encrypt(plaintext: &[u8], passphrase: &[u8], params: &Params) -> Result<Vec<u8>, Error> {
-    validate(params)?;
+    encrypt_with_salt(plaintext, passphrase, params, &new_salt())
+}
+
+/// A random salt, as [`encrypt`] picks for every file image.
+pub fn new_salt() -> [u8; SALT_LEN] {
     let mut salt = [0u8; SALT_LEN];
     SysRng
         .try_fill_bytes(&mut salt)
         .expect("failed to read from the OS random source");
-    encrypt_with_salt(plaintext, passphrase, params, &salt)
+    salt
 }
 
-fn encrypt_with_salt(
+/// [`encrypt`] with a salt picked by the caller, for a passphrase that
+/// depends on it. The salt must be new, as from [`new_salt`].
+pub fn encrypt_with_salt(
     plaintext: &[u8],
     passphrase: &[u8],
     params: &Params,
     salt: &[u8; SALT_LEN],
 ) -> Result<Vec<u8>, Error> {
-    let mut out = Vec::with_capacity(OVERHEAD + plaintext.len());
-    out.extend_from_slice(MAGIC);
-    out.push(VERSION);
-    out.push(params.log_n);
-    out.extend_from_slice(&params.r.to_be_bytes());
-    out.extend_from_slice(&params.p.to_be_bytes());
-    out.extend_from_slice(salt);
-    let checksum = Sha256::digest(&out);
-    out.extend_from_slice(&checksum[..16]);
-
-    let dk = derive_keys(passphrase, salt, params)?;
-    let (key_enc, key_hmac) = dk.split_at(32);
-    let header_mac = hmac(key_hmac, &out).finalize().into_bytes();
-    out.extend_from_slice(&header_mac);
-    debug_assert_eq!(out.len(), HEADER_LEN);
+    Ok(encrypt_with_key(
+        plaintext,
+        &DerivedKey::new(passphrase, params, salt)?,
+    ))
+}
 
+/// [`encrypt`] with keys derived before, for a new salt: as AES-CTR runs
+/// with a zero nonce, keys must never encrypt two different plaintexts.
+pub fn encrypt_with_key(plaintext: &[u8], key: &DerivedKey) -> Vec<u8> {
+    let mut out = Vec::with_capacity(OVERHEAD + plaintext.len());
+    out.extend_from_slice(&key.header());
     out.extend_from_slice(plaintext);
+    let (key_enc, key_hmac) = key.key.split_at(32);
     let mut cipher = Aes256Ctr::new_from_slices(key_enc, &[0u8; 16])
         .expect("AES-256 key and CTR IV sizes are fixed");
     // Encrypt in place: the plaintext bytes just appended are overwritten
@@ -179,36 +252,117 @@ fn encrypt_with_salt(
 
     let trailer_mac = hmac(key_hmac, &out).finalize().into_bytes();
     out.extend_from_slice(&trailer_mac);
-    Ok(out)
+    out
 }
 
-/// Decrypt a scrypt-format file image. Errors distinguish "wrong file type"
-/// ([`Error::NotScryptFormat`]), "wrong passphrase"
-/// ([`Error::WrongPassphrase`]) and "damaged file" ([`Error::Corrupt`]).
-pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
-    if data.len() < 6 || &data[..6] != MAGIC {
-        return Err(Error::NotScryptFormat);
+/// The keys derived from a passphrase for the salt and parameters of one
+/// header. With them, [`decrypt_with_key`] opens a file image with that
+/// header without running the KDF again; a file written since, with the new
+/// salt every [`encrypt`] picks, needs new keys.
+#[derive(Clone)]
+pub struct DerivedKey {
+    /// Bytes 0..48 of the header: magic, version, parameters and salt.
+    header: [u8; 48],
+    /// `key_enc || key_hmac`
+    key: Zeroizing<[u8; 64]>,
+}
+
+impl DerivedKey {
+    /// Run the KDF for a new header with `params` and `salt`.
+    pub fn new(passphrase: &[u8], params: &Params, salt: &[u8; SALT_LEN]) -> Result<Self, Error> {
+        validate(params)?;
+        let mut header = [0u8; 48];
+        header[..6].copy_from_slice(MAGIC);
+        header[6] = VERSION;
+        header[7] = params.log_n;
+        header[8..12].copy_from_slice(&params.r.to_be_bytes());
+        header[12..16].copy_from_slice(&params.p.to_be_bytes());
+        header[16..].copy_from_slice(salt);
+        Ok(DerivedKey {
+            header,
+            key: derive_keys(passphrase, salt, params)?,
+        })
     }
-    if data[6] != VERSION {
-        return Err(Error::UnsupportedVersion(data[6]));
+
+    /// Run the KDF for the header of `data`, a file image or at least its
+    /// first [`HEADER_LEN`] bytes, and check the passphrase against it.
+    pub fn derive(data: &[u8], passphrase: &[u8]) -> Result<Self, Error> {
+        let params = header_params(data, HEADER_LEN)?;
+        let key = derive_keys(passphrase, &data[16..48], &params)?;
+        Self::from_parts(data, &key[..])
     }
-    if data.len() < OVERHEAD {
-        return Err(Error::Truncated);
+
+    /// Keys as [`DerivedKey::key`] returned them, for the header of `data`;
+    /// [`Error::WrongPassphrase`] if they are not the keys of that header.
+    pub fn from_parts(data: &[u8], key: &[u8]) -> Result<Self, Error> {
+        header_params(data, HEADER_LEN)?;
+        if key.len() != 64 {
+            return Err(Error::WrongPassphrase);
+        }
+        let mut keys = Zeroizing::new([0u8; 64]);
+        keys.copy_from_slice(key);
+        hmac(&keys[32..], &data[..64])
+            .verify_slice(&data[64..HEADER_LEN])
+            .map_err(|_| Error::WrongPassphrase)?;
+        Ok(DerivedKey {
+            header: data[..48].try_into().expect("fixed slice"),
+            key: keys,
+        })
     }
-    let params = Params {
-        log_n: data[7],
-        r: u32::from_be_bytes(data[8..12].try_into().expect("fixed slice")),
-        p: u32::from_be_bytes(data[12..16].try_into().expect("fixed slice")),
-    };
-    validate(&params)?;
-    let checksum = Sha256::digest(&data[..48]);
-    if checksum[..16] != data[48..64] {
-        return Err(Error::NotScryptFormat);
+
+    /// The 64 bytes of `key_enc || key_hmac`.
+    pub fn key(&self) -> &[u8] {
+        &self.key[..]
+    }
+
+    /// The scrypt parameters the keys were derived with.
+    pub fn params(&self) -> Params {
+        Params {
+            log_n: self.header[7],
+            r: u32::from_be_bytes(self.header[8..12].try_into().expect("fixed slice")),
+            p: u32::from_be_bytes(self.header[12..16].try_into().expect("fixed slice")),
+        }
+    }
+
+    /// The whole header the keys are for, with its checksum and the HMAC
+    /// that checks the passphrase.
+    pub fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[..48].copy_from_slice(&self.header);
+        header[48..64].copy_from_slice(&Sha256::digest(self.header)[..16]);
+        let mac = hmac(&self.key[32..], &header[..64]).finalize().into_bytes();
+        header[64..].copy_from_slice(&mac);
+        header
+    }
+
+    /// Whether these are the keys for the header of `data`.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.get(..48) == Some(&self.header[..])
+    }
+}
+
+impl std::fmt::Debug for DerivedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DerivedKey([redacted])")
     }
+}
+
+/// Decrypt a scrypt-format file image. Errors distinguish "wrong file type"
+/// ([`Error::NotScryptFormat`]), "wrong passphrase"
+/// ([`Error::WrongPassphrase`]) and "damaged file" ([`Error::Corrupt`]).
+pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    params(data)?;
+    decrypt_with_key(data, &DerivedKey::derive(data, passphrase)?)
+}
 
-    let salt = &data[16..48];
-    let dk = derive_keys(passphrase, salt, &params)?;
-    let (key_enc, key_hmac) = dk.split_at(32);
+/// Decrypt a scrypt-format file image with keys derived before, failing as
+/// [`decrypt`] does; keys for another header are a wrong passphrase.
+pub fn decrypt_with_key(data: &[u8], key: &DerivedKey) -> Result<Zeroizing<Vec<u8>>, Error> {
+    params(data)?;
+    if !key.matches(data) {
+        return Err(Error::WrongPassphrase);
+    }
+    let (key_enc, key_hmac) = key.key.split_at(32);
 
     hmac(key_hmac, &data[..64])
         .verify_slice(&data[64..HEADER_LEN])
@@ -226,6 +380,47 @@ pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, Err
     Ok(plaintext)
 }
 
+/// The KDF parameters in the header of a scrypt-format file image, which
+/// can be read without the passphrase. Fails as [`decrypt`] would on a
+/// file that is not in the format or has invalid parameters.
+pub fn params(data: &[u8]) -> Result<Params, Error> {
+    header_params(data, OVERHEAD)
+}
+
+/// The salt in the header of a scrypt-format file image, or at least its
+/// first [`HEADER_LEN`] bytes; not secret.
+pub fn salt(data: &[u8]) -> Result<[u8; SALT_LEN], Error> {
+    header_params(data, HEADER_LEN)?;
+    Ok(data[16..48].try_into().expect("fixed slice"))
+}
+
+/// [`params`], for data of at least `min_len` bytes.
+fn header_params(data: &[u8], min_len: usize) -> Result<Params, Error> {
+    if data.len() < 6 || &data[..6] != MAGIC {
+        return Err(Error::NotScryptFormat);
+    }
+    let Some(&version) = data.get(6) else {
+        return Err(Error::Truncated);
+    };
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    if data.len() < min_len {
+        return Err(Error::Truncated);
+    }
+    let params = Params {
+        log_n: data[7],
+        r: u32::from_be_bytes(data[8..12].try_into().expect("fixed slice")),
+        p: u32::from_be_bytes(data[12..16].try_into().expect("fixed slice")),
+    };
+    validate(&params)?;
+    let checksum = Sha256::digest(&data[..48]);
+    if checksum[..16] != data[48..64] {
+        return Err(Error::NotScryptFormat);
+    }
+    Ok(params)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,6 +466,76 @@ mod tests {
     #[test]
     fn default_params_are_valid() {
         assert_eq!(validate(&Params::default()), Ok(()));
+        assert_eq!(validate(&Params::low_memory()), Ok(()));
+    }
+
+    #[test]
+    fn params_are_read_from_the_header() {
+        assert_eq!(params(&encrypted()), Ok(TEST_PARAMS));
+        assert_eq!(params(b"scrypt\0"), Err(Error::Truncated));
+        assert_eq!(params(b"scrypt"), Err(Error::Truncated));
+        assert_eq!(params(b"plain text"), Err(Error::NotScryptFormat));
+    }
+
+    #[test]
+    fn tuning_fits_memory_and_time() {
+        let per_block = Duration::from_nanos(100);
+        // 2^17 * 8 * 100 ns = 105 ms, 2^18 * 8 * 100 ns = 210 ms
+        let params = Params::fitting(1 << 30, Duration::from_millis(200), per_block);
+        assert_eq!(params, Params::default());
+        let params = Params::fitting(16 << 20, Duration::from_secs(10), per_block);
+        assert_eq!(params, Params::low_memory());
+        // Capped at the 1 GiB decrypt accepts, and never below 2^10.
+        let params = Params::fitting(u64::MAX, Duration::MAX, per_block);
+        assert_eq!((params.log_n, params.memory()), (20, 1 << 30));
+        let params = Params::fitting(0, Duration::ZERO, per_block);
+        assert_eq!(params.log_n, 10);
+        assert!(Params::default().cost() > Params::low_memory().cost());
+    }
+
+    #[test]
+    fn derived_key_decrypts_without_the_kdf() {
+        let data = encrypted();
+        let key = DerivedKey::derive(&data[..HEADER_LEN], PASSPHRASE).unwrap();
+        assert_eq!(decrypt_with_key(&data, &key).unwrap().as_slice(), PLAINTEXT);
+        let copy = DerivedKey::from_parts(&data, key.key()).unwrap();
+        assert_eq!(
+            decrypt_with_key(&data, &copy).unwrap().as_slice(),
+            PLAINTEXT
+        );
+        assert_eq!(format!("{key:?}"), "DerivedKey([redacted])");
+
+        assert_eq!(
+            DerivedKey::derive(&data, b"wrong").unwrap_err(),
+            Error::WrongPassphrase
+        );
+        assert_eq!(
+            DerivedKey::from_parts(&data, &[0; 64]).unwrap_err(),
+            Error::WrongPassphrase
+        );
+        // A new encryption has a new salt, which needs new keys.
+        let newer = encrypted();
+        assert!(!key.matches(&newer));
+        assert_eq!(
+            decrypt_with_key(&newer, &key).unwrap_err(),
+            Error::WrongPassphrase
+        );
+    }
+
+    #[test]
+    fn derived_key_encrypts_for_its_salt() {
+        let salt = new_salt();
+        let key = DerivedKey::new(PASSPHRASE, &TEST_PARAMS, &salt).unwrap();
+        assert_eq!(key.params(), TEST_PARAMS);
+        let data = encrypt_with_key(PLAINTEXT, &key);
+        assert_eq!(
+            data,
+            encrypt_with_salt(PLAINTEXT, PASSPHRASE, &TEST_PARAMS, &salt).unwrap()
+        );
+        assert_eq!(data[..HEADER_LEN], key.header());
+        assert_eq!(decrypt(&data, PASSPHRASE).unwrap().as_slice(), PLAINTEXT);
+        let copy = DerivedKey::from_parts(&key.header(), key.key()).unwrap();
+        assert!(copy.matches(&data));
     }
 
     #[test]
@@ -0,0 +1,310 @@
+//! Ranking entries by how well they match a typed query, so that every place
+//! pw searches puts the same entry first.
+//!
+//! Text and query are compared ignoring case and accents. A text scores, from
+//! best to worst:
+//!
+//! 1. [`Score::Exact`]: it is the query;
+//! 2. [`Score::Prefix`]: it starts with the query;
+//! 3. [`Score::Substring`]: it contains the query;
+//! 4. [`Score::Fuzzy`]: some part of it is within [`max_distance`] edits
+//!    (Levenshtein distance: characters inserted, removed or replaced) of the
+//!    query, fewer edits being better.
+//!
+//! Anything else does not match. An entry scores the best of its name,
+//! username and url, or [`Score::Exact`] if the query is one of its tags.
+//! Equal scores are ordered by [`compare_names`](crate::compare_names).
+//!
+//! Longer text, such as notes, is not scored but searched with [`find`], and
+//! [`snippet`] cuts the line around a match for display.
+
+use std::cmp::{Ordering, Reverse};
+use std::ops::Range;
+
+use crate::{compare_names, fold, PasswordEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// Part of the name is this many edits away from the query
+    Fuzzy(usize),
+    Substring,
+    Prefix,
+    Exact,
+}
+
+impl Score {
+    fn key(self) -> (u8, Reverse<usize>) {
+        match self {
+            Score::Fuzzy(distance) => (0, Reverse(distance)),
+            Score::Substring => (1, Reverse(0)),
+            Score::Prefix => (2, Reverse(0)),
+            Score::Exact => (3, Reverse(0)),
+        }
+    }
+}
+
+/// Better matches are greater.
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The most edits a fuzzy match of a query of `len` characters may need: none
+/// below 4 characters, where almost anything would match, then one per 4
+/// characters.
+pub fn max_distance(len: usize) -> usize {
+    len / 4
+}
+
+/// How well `entry` matches `query`, or `None` if it does not.
+pub fn score_entry(entry: &PasswordEntry, query: &str) -> Option<Score> {
+    let folded = fold(query);
+    if entry.tags.iter().any(|tag| fold(tag) == folded) {
+        return Some(Score::Exact);
+    }
+    [Some(&entry.name), Some(&entry.username), entry.url.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|text| score(text, query))
+        .max()
+}
+
+/// How well `name` matches `query`, or `None` if it does not. An empty query
+/// is a prefix of every name.
+pub fn score(name: &str, query: &str) -> Option<Score> {
+    let (name, query) = (fold(name), fold(query));
+    if name == query {
+        Some(Score::Exact)
+    } else if name.starts_with(&query) {
+        Some(Score::Prefix)
+    } else if name.contains(&query) {
+        Some(Score::Substring)
+    } else {
+        let query: Vec<char> = query.chars().collect();
+        let distance = substring_distance(&query, &name);
+        (distance <= max_distance(query.len())).then_some(Score::Fuzzy(distance))
+    }
+}
+
+/// The entries that match `query`, best first.
+pub fn rank<'a>(entries: &'a [PasswordEntry], query: &str) -> Vec<(Score, &'a PasswordEntry)> {
+    let mut ranked: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| Some((score_entry(entry, query)?, entry)))
+        .collect();
+    ranked.sort_by(|(a, x), (b, y)| b.cmp(a).then_with(|| compare_names(&x.name, &y.name)));
+    ranked
+}
+
+/// Where `query` first occurs in `text`, ignoring case and accents, as a byte
+/// range of `text`; `None` if it does not, or if `query` is empty.
+pub fn find(text: &str, query: &str) -> Option<Range<usize>> {
+    let query: Vec<char> = fold(query).chars().collect();
+    if query.is_empty() {
+        return None;
+    }
+    // Each folded character, with the range of the character of `text` it
+    // comes from.
+    let folded: Vec<(char, Range<usize>)> = text
+        .char_indices()
+        .flat_map(|(i, c)| {
+            let range = i..i + c.len_utf8();
+            fold(c.encode_utf8(&mut [0; 4]))
+                .chars()
+                .map(move |f| (f, range.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    folded
+        .windows(query.len())
+        .find(|window| window.iter().map(|(c, _)| *c).eq(query.iter().copied()))
+        .map(|window| window[0].1.start..window[query.len() - 1].1.end)
+}
+
+/// A match in a longer text, with some of its line around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// Up to the match, starting with `…` if cut
+    pub before: String,
+    /// The match as it is in the text
+    pub matched: String,
+    /// From the match, ending with `…` if cut
+    pub after: String,
+}
+
+/// The line of `text` where `query` first occurs, as [`find`] finds it, cut
+/// to at most `context` characters on either side of the match.
+pub fn snippet(text: &str, query: &str, context: usize) -> Option<Snippet> {
+    let range = find(text, query)?;
+    let start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[range.end..]
+        .find('\n')
+        .map_or(text.len(), |i| range.end + i);
+
+    let before: Vec<char> = text[start..range.start].chars().collect();
+    let mut cut_before: String = before[before.len().saturating_sub(context)..]
+        .iter()
+        .collect();
+    if before.len() > context {
+        cut_before = format!("…{}", cut_before.trim_start());
+    }
+    let after: Vec<char> = text[range.end..end].chars().collect();
+    let mut cut_after: String = after[..after.len().min(context)].iter().collect();
+    if after.len() > context {
+        cut_after = format!("{}…", cut_after.trim_end());
+    }
+    Some(Snippet {
+        before: cut_before,
+        matched: text[range].to_string(),
+        after: cut_after,
+    })
+}
+
+/// The fewest edits that turn `query` into some substring of `text` (Sellers'
+/// algorithm: Levenshtein distance, with the substring free to start and end
+/// anywhere).
+fn substring_distance(query: &[char], text: &str) -> usize {
+    // column[i]: edits for query[..i] to match text ending at the current
+    // character.
+    let mut column: Vec<usize> = (0..=query.len()).collect();
+    let mut best = query.len();
+    for c in text.chars() {
+        let mut diagonal = column[0];
+        column[0] = 0;
+        for i in 1..=query.len() {
+            let left = column[i];
+            column[i] = (diagonal + usize::from(query[i - 1] != c))
+                .min(left + 1)
+                .min(column[i - 1] + 1);
+            diagonal = left;
+        }
+        best = best.min(column[query.len()]);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> PasswordEntry {
+        PasswordEntry {
+            name: name.to_string(),
+            username: String::new(),
+            password: "p".into(),
+            url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn exact_beats_prefix_beats_substring_beats_fuzzy() {
+        let entries = [
+            "work-github",
+            "GitHub",
+            "github-enterprise",
+            "gitlab",
+            "bank",
+            "gihub",
+        ]
+        .map(entry);
+        let ranked: Vec<_> = rank(&entries, "github")
+            .into_iter()
+            .map(|(score, e)| (score, e.name.as_str()))
+            .collect();
+        assert_eq!(
+            ranked,
+            [
+                (Score::Exact, "GitHub"),
+                (Score::Prefix, "github-enterprise"),
+                (Score::Substring, "work-github"),
+                (Score::Fuzzy(1), "gihub"),
+            ]
+        );
+        assert!(Score::Fuzzy(1) > Score::Fuzzy(2));
+        assert!(Score::Substring > Score::Fuzzy(0));
+    }
+
+    #[test]
+    fn entries_score_their_best_attribute() {
+        let mut e = entry("work-github");
+        e.username = "alice".to_string();
+        e.url = Some("https://github.com".to_string());
+        e.tags = vec!["Dev".to_string()];
+        assert_eq!(score_entry(&e, "alice"), Some(Score::Exact));
+        assert_eq!(score_entry(&e, "https"), Some(Score::Prefix));
+        assert_eq!(score_entry(&e, "dev"), Some(Score::Exact));
+        assert_eq!(score_entry(&e, "alise"), Some(Score::Fuzzy(1)));
+        assert_eq!(score_entry(&e, "bob"), None);
+    }
+
+    #[test]
+    fn fuzzy_matches_tolerate_a_typo_per_four_characters() {
+        assert_eq!(score("work-github", "githb"), Some(Score::Fuzzy(1)));
+        assert_eq!(score("Ärzte", "arzt"), Some(Score::Prefix));
+        assert_eq!(score("router", "rooter"), Some(Score::Fuzzy(1)));
+        assert_eq!(score("mail", "main"), Some(Score::Fuzzy(1)));
+        assert_eq!(score("mail", "mai1s"), None);
+        assert_eq!(score("paypal", "pyapla"), None);
+        assert_eq!(score("anything", ""), Some(Score::Prefix));
+    }
+
+    #[test]
+    fn finds_text_ignoring_case_and_accents() {
+        assert_eq!(find("Call Zoë at home", "zoe"), Some(5..9));
+        assert_eq!(find("ARZTE", "ärzte"), Some(0..5));
+        assert_eq!(find("no match here", "there"), None);
+        assert_eq!(find("anything", ""), None);
+    }
+
+    #[test]
+    fn snippets_cut_the_line_around_the_match() {
+        let notes = "first line\nrecovery codes are in the safe in the hall\nlast line";
+        assert_eq!(
+            snippet(notes, "safe", 8),
+            Some(Snippet {
+                before: "…in the ".to_string(),
+                matched: "safe".to_string(),
+                after: " in the…".to_string(),
+            })
+        );
+        assert_eq!(
+            snippet(notes, "LAST", 20),
+            Some(Snippet {
+                before: String::new(),
+                matched: "last".to_string(),
+                after: " line".to_string(),
+            })
+        );
+        assert_eq!(snippet(notes, "missing", 8), None);
+    }
+
+    #[test]
+    fn substring_distance_is_levenshtein_to_the_closest_part() {
+        let distance =
+            |query: &str, text| substring_distance(&query.chars().collect::<Vec<_>>(), text);
+        // Levenshtein distance 3, but the trailing "g" need not be matched.
+        assert_eq!(distance("kitten", "sitting"), 2);
+        assert_eq!(distance("hub", "github"), 0);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("", "abc"), 0);
+    }
+}
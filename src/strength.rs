@@ -0,0 +1,264 @@
+//! Estimating how hard a password or passphrase is to guess.
+//!
+//! A deliberately simple, conservative model in the spirit of zxcvbn: each
+//! character is worth the bits of the character classes the whole string
+//! draws from, except that repeats and runs (`aaaa`, `abcd`, `4321`) are
+//! worth next to nothing, a string made of one repeated chunk is worth that
+//! chunk, and well-known passwords — also with leetspeak, capitals and a few
+//! trailing digits or symbols — are worth their rank in a short embedded
+//! list. It does not know dictionary words, so multi-word passphrases are
+//! rated by length and character set alone.
+//!
+//! [`estimate`] is the one entry point for rating a password: passphrase
+//! checks, `pw audit --policy` and anything showing a strength meter all
+//! agree because they all use it.
+
+use std::fmt;
+
+/// Frequently used passwords and password stems, one per line, most common
+/// first.
+const COMMON: &str = include_str!("common-passwords.txt");
+
+/// How hard a password is to guess, as [`estimate`] rates it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// Estimated guessing entropy, in bits
+    pub bits: f64,
+    /// The [`score`] of `bits`, 0 to 4
+    pub score: u8,
+    /// What makes the password easier to guess than its length suggests,
+    /// each at most once
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    /// A well-known password, possibly with leetspeak, capitals or a short
+    /// suffix
+    Common,
+    /// The same chunk over and over, like `abcabcabc`
+    RepeatedChunk,
+    /// Three or more of the same character in a row, like `aaa`
+    Repeats,
+    /// Three or more consecutive characters, like `abc` or `4321`
+    Sequence,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Warning::Common => "is a common password or a variation of one",
+            Warning::RepeatedChunk => "repeats the same characters over and over",
+            Warning::Repeats => "has runs of the same character",
+            Warning::Sequence => "has sequences like abc or 4321",
+        })
+    }
+}
+
+/// Rate `password`.
+pub fn estimate(password: &str) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    let mut warnings = Vec::new();
+    let mut bits = 0.0;
+    if !chars.is_empty() {
+        bits = pattern_bits(&chars, &mut warnings);
+        if let Some(common) = common_bits(password) {
+            bits = bits.min(common);
+            warnings.insert(0, Warning::Common);
+        }
+    }
+    Estimate {
+        bits,
+        score: score(bits),
+        warnings,
+    }
+}
+
+/// Estimated guessing entropy of `password`, in bits: [`estimate`] without
+/// the details.
+pub fn estimate_bits(password: &str) -> f64 {
+    estimate(password).bits
+}
+
+/// A 0–4 rating of [`estimate_bits`]: 0 is trivially guessable, 3 resists
+/// offline guessing against a slow KDF like scrypt, 4 comfortably so.
+pub fn score(bits: f64) -> u8 {
+    match bits {
+        b if b < 20.0 => 0,
+        b if b < 35.0 => 1,
+        b if b < 50.0 => 2,
+        b if b < 65.0 => 3,
+        _ => 4,
+    }
+}
+
+/// The bits of `chars` by their patterns, adding a warning to `warnings` for
+/// each kind of pattern found.
+fn pattern_bits(chars: &[char], warnings: &mut Vec<Warning>) -> f64 {
+    let mut warn = |warning| {
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    };
+    // A string that is one chunk repeated is worth the chunk, plus the
+    // choice of how many times it is repeated.
+    for unit in 1..=chars.len() / 2 {
+        if chars.len().is_multiple_of(unit) && chars.chunks(unit).all(|c| c == &chars[..unit]) {
+            warn(if unit == 1 {
+                Warning::Repeats
+            } else {
+                Warning::RepeatedChunk
+            });
+            let repeats = (chars.len() / unit) as f64;
+            return pattern_bits(&chars[..unit], warnings) + repeats.log2();
+        }
+    }
+
+    let per_char = (pool_size(chars) as f64).log2();
+    let mut bits = 0.0;
+    let mut prev_step: Option<i64> = None;
+    // Characters in the current run of repeats or of a sequence.
+    let mut run = 1;
+    for (i, &c) in chars.iter().enumerate() {
+        let step = i.checked_sub(1).map(|p| c as i64 - chars[p] as i64);
+        let continues = step.is_some() && prev_step.is_none_or(|p| Some(p) == step);
+        bits += match step {
+            Some(0) => 1.0,
+            Some(s @ (-1 | 1)) if prev_step.is_none_or(|p| p == s) => 2.0,
+            _ => per_char,
+        };
+        run = match step {
+            Some(-1..=1) if continues => run + 1,
+            Some(-1..=1) => 2,
+            _ => 1,
+        };
+        if run == 3 {
+            warn(if step == Some(0) {
+                Warning::Repeats
+            } else {
+                Warning::Sequence
+            });
+        }
+        prev_step = step;
+    }
+    bits
+}
+
+/// The size of the alphabet `chars` appear to be drawn from.
+fn pool_size(chars: &[char]) -> u32 {
+    let mut pool = 0;
+    if chars.iter().any(char::is_ascii_lowercase) {
+        pool += 26;
+    }
+    if chars.iter().any(char::is_ascii_uppercase) {
+        pool += 26;
+    }
+    if chars.iter().any(char::is_ascii_digit) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    pool
+}
+
+/// If `password` is a common password, possibly with leetspeak, capitals
+/// and up to six trailing digits or symbols, the bits that costs an attacker
+/// who tries those first.
+fn common_bits(password: &str) -> Option<f64> {
+    let stem_len = password
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c.is_ascii_punctuation())
+        .len();
+    let (stem, suffix) = if password.len() - stem_len <= 6 && stem_len > 0 {
+        password.split_at(stem_len)
+    } else {
+        (password, "")
+    };
+    let normalized: String = stem
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '4' | '@' => 'a',
+            '3' => 'e',
+            '1' | '!' => 'i',
+            '0' => 'o',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect();
+    let lowercase = stem.to_ascii_lowercase();
+    let rank = COMMON
+        .lines()
+        .position(|p| p == lowercase || p == normalized)?;
+    let variations = if stem.chars().any(|c| c.is_ascii_uppercase()) {
+        1.0
+    } else {
+        0.0
+    } + if normalized != lowercase { 1.0 } else { 0.0 };
+    let suffix_bits: f64 = suffix
+        .chars()
+        .map(|c| {
+            if c.is_ascii_digit() {
+                10f64.log2()
+            } else {
+                5.0
+            }
+        })
+        .sum();
+    Some(((rank + 1) as f64).log2() + variations + suffix_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_passwords_score_low() {
+        for password in ["password", "P@ssw0rd", "qwerty123", "letmein"] {
+            assert_eq!(score(estimate_bits(password)), 0, "{password}");
+        }
+        assert_eq!(score(estimate_bits("Summer2024!")), 1);
+    }
+
+    #[test]
+    fn repeats_and_runs_are_cheap() {
+        assert!(estimate_bits("aaaaaaaaaaaaaaaa") < 10.0);
+        assert!(estimate_bits("abcdefghijklmnop") < 40.0);
+        assert!(estimate_bits("Xk9#Xk9#Xk9#Xk9#") < 30.0);
+    }
+
+    #[test]
+    fn long_random_strings_score_four() {
+        assert_eq!(score(estimate_bits("correct horse battery staple")), 4);
+        assert_eq!(score(estimate_bits("uZ7-qT2m-Kp9w-Rx4e")), 4);
+        assert_eq!(score(estimate_bits("")), 0);
+    }
+
+    #[test]
+    fn estimates_explain_weak_passwords() {
+        let weak = estimate("P@ssw0rd");
+        assert_eq!((weak.score, weak.bits), (0, estimate_bits("P@ssw0rd")));
+        assert_eq!(weak.warnings, [Warning::Common]);
+        assert_eq!(
+            estimate("xyzzy-aaaa-4321").warnings,
+            [Warning::Sequence, Warning::Repeats]
+        );
+        assert_eq!(estimate("Xk9#Xk9#Xk9#").warnings, [Warning::RepeatedChunk]);
+        assert_eq!(estimate("aaaaaaaa").warnings, [Warning::Repeats]);
+        // A pair is not a run.
+        assert!(estimate("uZ7-qT2m-Kp9w-Rx4ee").warnings.is_empty());
+        assert_eq!(estimate("").score, 0);
+    }
+
+    #[test]
+    fn score_thresholds() {
+        assert_eq!(score(19.9), 0);
+        assert_eq!(score(34.9), 1);
+        assert_eq!(score(49.9), 2);
+        assert_eq!(score(64.9), 3);
+        assert_eq!(score(65.0), 4);
+    }
+}
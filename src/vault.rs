@@ -1,5 +1,6 @@
 //! Encrypted vault storage: the JSON envelope inside the scrypt format,
-//! with atomic writes and restrictive permissions (PLAN.md §2.2, H-1, H-2).
+//! with journaled atomic writes and restrictive permissions (PLAN.md §2.2,
+//! H-1, H-2).
 //!
 //! This module never prompts and never assumes a terminal: the passphrase
 //! enters as a [`Passphrase`] parameter. [`load`] is strictly read-only.
@@ -8,27 +9,132 @@ use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use zeroize::Zeroizing;
 
-use crate::scrypt_format::{self, Params};
+use crate::scrypt_format::{self, DerivedKey, Params, SALT_LEN};
 use crate::PasswordEntry;
 
 /// Version of the JSON envelope inside the encrypted file. Bare arrays
 /// written by pw <= 0.1.x are still accepted on read (PLAN.md §2.2).
 const ENVELOPE_VERSION: u32 = 1;
 
+/// The response to a challenge, such as a YubiKey's, or why there is none.
+type Respond = dyn Fn(&[u8]) -> Result<Zeroizing<Vec<u8>>, String> + Send + Sync;
+
+/// The keys for the parameters and salt of a header, given the response to
+/// the salt if there is a challenge, from whoever holds the passphrase.
+type Derive =
+    dyn Fn(&Params, &[u8; SALT_LEN], Option<&[u8]>) -> Result<DerivedKey, String> + Send + Sync;
+
 /// The master passphrase. Zeroized on drop, redacted by `Debug`.
-pub struct Passphrase(Zeroizing<String>);
+#[derive(Clone)]
+pub struct Passphrase {
+    passphrase: Zeroizing<String>,
+    /// Keys derived from it before, used by [`load`] for a vault whose
+    /// header they match instead of running scrypt.
+    derived: Option<DerivedKey>,
+    /// Asked for the response to the salt of every header, see
+    /// [`Passphrase::with_challenge_response`].
+    respond: Option<Arc<Respond>>,
+    /// Asked for the keys in place of scrypt, see [`Passphrase::delegated`].
+    derive: Option<Arc<Derive>>,
+}
 
 impl Passphrase {
     pub fn new(passphrase: String) -> Self {
-        Passphrase(Zeroizing::new(passphrase))
+        Passphrase {
+            passphrase: Zeroizing::new(passphrase),
+            derived: None,
+            respond: None,
+            derive: None,
+        }
+    }
+
+    /// A passphrase held elsewhere, such as by `pw agent`, which never
+    /// hands it out: `key` opens the vault as it is now, and `derive` is
+    /// asked for the keys of any other header, such as the new one of every
+    /// write. Its bytes are empty.
+    pub fn delegated(
+        key: DerivedKey,
+        derive: impl Fn(&Params, &[u8; SALT_LEN], Option<&[u8]>) -> Result<DerivedKey, String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Passphrase {
+        Passphrase {
+            passphrase: Zeroizing::default(),
+            derived: Some(key),
+            respond: None,
+            derive: Some(Arc::new(derive)),
+        }
     }
 
     pub fn as_bytes(&self) -> &[u8] {
-        self.0.as_bytes()
+        self.passphrase.as_bytes()
+    }
+
+    /// The passphrase, with the keys derived from it for the current header
+    /// of a vault, such as `pw agent` holds.
+    pub fn with_derived_key(mut self, key: DerivedKey) -> Passphrase {
+        self.derived = Some(key);
+        self
+    }
+
+    /// The passphrase combined with the contents of a key file, for a vault
+    /// that needs both: the passphrase, a colon and the SHA-256 of `key` in
+    /// lowercase hex. This is an ordinary scrypt passphrase, so `scrypt dec`
+    /// can still open such a vault when given it.
+    pub fn with_key_file(&self, key: &[u8]) -> Passphrase {
+        let mut combined = Zeroizing::new(String::with_capacity(self.passphrase.len() + 65));
+        combined.push_str(&self.passphrase);
+        combined.push(':');
+        for byte in Sha256::digest(key) {
+            combined.push_str(&format!("{byte:02x}"));
+        }
+        Passphrase {
+            passphrase: combined,
+            derived: None,
+            respond: None,
+            derive: None,
+        }
+    }
+
+    /// The passphrase combined, like a key file, with the response to the
+    /// salt of the vault's header, as the challenge: `respond` is asked for
+    /// it on every read that runs scrypt, and on every write for the new
+    /// salt. As every write picks a new salt, the response changes with it,
+    /// and one seen once does not open the vault after its next write. This
+    /// is how a YubiKey takes part, see [`crate::yubikey`].
+    pub fn with_challenge_response(
+        mut self,
+        respond: impl Fn(&[u8]) -> Result<Zeroizing<Vec<u8>>, String> + Send + Sync + 'static,
+    ) -> Passphrase {
+        self.respond = Some(Arc::new(respond));
+        self
+    }
+
+    /// The keys for a header with `params` and `salt`, not yet checked
+    /// against any header.
+    fn key_for(&self, params: &Params, salt: &[u8; SALT_LEN]) -> Result<DerivedKey, Error> {
+        let response = match &self.respond {
+            Some(respond) => Some(respond(salt).map_err(Error::Response)?),
+            None => None,
+        };
+        if let Some(derive) = &self.derive {
+            return derive(params, salt, response.as_ref().map(|r| &r[..]))
+                .map_err(Error::Response);
+        }
+        let key = match response {
+            Some(response) => {
+                DerivedKey::new(self.with_key_file(&response).as_bytes(), params, salt)?
+            }
+            None => DerivedKey::new(self.as_bytes(), params, salt)?,
+        };
+        Ok(key)
     }
 }
 
@@ -58,6 +164,8 @@ pub enum Error {
     },
     #[error(transparent)]
     Format(#[from] scrypt_format::Error),
+    #[error("{0}")]
+    Response(String),
     #[error("invalid vault content")]
     InvalidJson(#[source] serde_json::Error),
     #[error("vault format version {0} is newer than this version of pw understands")]
@@ -95,14 +203,15 @@ pub fn to_json(entries: &[PasswordEntry]) -> Result<Zeroizing<String>, Error> {
 /// Decrypt and parse the vault. Read-only: never creates, locks or touches
 /// the file.
 pub fn load(file: &Path, passphrase: &Passphrase) -> Result<Vec<PasswordEntry>, Error> {
-    let data = fs::read(file).map_err(|source| Error::Read {
-        file: file.to_path_buf(),
-        source,
-    })?;
-    let plaintext = scrypt_format::decrypt(&data, passphrase.as_bytes())?;
-    // On parse failure the decrypted bytes are deliberately not included in
-    // the error (serde_json errors carry positions, not data).
-    let parsed: VaultJson = serde_json::from_slice(&plaintext).map_err(Error::InvalidJson)?;
+    from_json(&load_payload(file, passphrase)?)
+}
+
+/// Parse a JSON envelope, or a bare legacy array — the inverse of
+/// [`to_json`].
+pub fn from_json(json: &[u8]) -> Result<Vec<PasswordEntry>, Error> {
+    // On parse failure the bytes are deliberately not included in the error
+    // (serde_json errors carry positions, not data).
+    let parsed: VaultJson = serde_json::from_slice(json).map_err(Error::InvalidJson)?;
     match parsed {
         VaultJson::Envelope {
             version: ENVELOPE_VERSION,
@@ -113,59 +222,116 @@ pub fn load(file: &Path, passphrase: &Passphrase) -> Result<Vec<PasswordEntry>,
     }
 }
 
-/// Encrypt and write the vault atomically.
+/// Decrypt the vault without parsing it: the raw JSON payload. Read-only,
+/// like [`load`].
+pub fn load_payload(file: &Path, passphrase: &Passphrase) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let data = fs::read(file).map_err(|source| Error::Read {
+        file: file.to_path_buf(),
+        source,
+    })?;
+    match &passphrase.derived {
+        Some(key) if key.matches(&data) => Ok(scrypt_format::decrypt_with_key(&data, key)?),
+        _ => {
+            let key =
+                passphrase.key_for(&scrypt_format::params(&data)?, &scrypt_format::salt(&data)?)?;
+            Ok(scrypt_format::decrypt_with_key(&data, &key)?)
+        }
+    }
+}
+
+/// Derive the keys for the vault as it is now, checking the passphrase; with
+/// them, [`load`] needs no scrypt until the vault is next written.
+pub fn derive_key(file: &Path, passphrase: &Passphrase) -> Result<DerivedKey, Error> {
+    let data = fs::read(file).map_err(|source| Error::Read {
+        file: file.to_path_buf(),
+        source,
+    })?;
+    let key = passphrase.key_for(&scrypt_format::params(&data)?, &scrypt_format::salt(&data)?)?;
+    Ok(DerivedKey::from_parts(&data, key.key())?)
+}
+
+/// The scrypt parameters the vault was written with, read from its header
+/// without the passphrase.
+pub fn params(file: &Path) -> Result<Params, Error> {
+    let data = fs::read(file).map_err(|source| Error::Read {
+        file: file.to_path_buf(),
+        source,
+    })?;
+    Ok(scrypt_format::params(&data)?)
+}
+
+/// Encrypt and write the vault atomically, through a write-ahead journal.
 ///
-/// The ciphertext goes to `<file>.tmp` (created exclusively, `0o600` on
-/// Unix) which is fsynced and then renamed over the target; an existing
-/// vault is first copied to `<file>.bak`. A crash at any point leaves the
-/// target as either the complete old or the complete new vault, never
-/// truncated.
+/// The ciphertext goes to `<file>.new` (created exclusively, `0o600` on
+/// Unix) and is fsynced; an existing vault is copied to `<file>.bak`. Then
+/// the intent to replace the vault, with a checksum of the new ciphertext,
+/// is recorded in `<file>.journal`, `<file>.new` is renamed over the target
+/// and the journal is removed. A crash at any point leaves the target as
+/// either the complete old or the complete new vault, never truncated, and
+/// the next [`recover`] (which this does first) completes or rolls back
+/// the interrupted write. All of it happens under an exclusive lock on
+/// `<file>.lock`, so that one process at a time writes or recovers the
+/// vault.
 ///
-/// The temporary file must live next to the target (the rename may not
-/// cross filesystems), and its name is deterministic so sandbox policies
-/// (e.g. AppArmor) can allow exactly `<file>`, `<file>.tmp` and
-/// `<file>.bak` instead of a wildcard.
+/// Once the new file is renamed over the target, the write has succeeded,
+/// even if the journal cannot be removed: the next [`recover`] does that.
+///
+/// The new file must live next to the target (the rename may not cross
+/// filesystems), and all names are deterministic so sandbox policies (e.g.
+/// AppArmor) can allow exactly `<file>`, `<file>.new`, `<file>.journal`,
+/// `<file>.lock` and `<file>.bak` instead of a wildcard.
 pub fn store(
     file: &Path,
     passphrase: &Passphrase,
     entries: &[PasswordEntry],
     params: &Params,
+) -> Result<(), Error> {
+    write(file, passphrase, entries, params, true)
+}
+
+/// [`store`] for a new passphrase or scrypt parameters: the vault as it was
+/// is not kept, and an existing `<file>.bak` is removed, as it would still
+/// open with the old passphrase, or be cheaper to attack.
+pub fn store_rekeyed(
+    file: &Path,
+    passphrase: &Passphrase,
+    entries: &[PasswordEntry],
+    params: &Params,
+) -> Result<(), Error> {
+    write(file, passphrase, entries, params, false)
+}
+
+/// [`store`], copying the vault as it was to `<file>.bak` if `backup`, or
+/// else removing `<file>.bak`.
+fn write(
+    file: &Path,
+    passphrase: &Passphrase,
+    entries: &[PasswordEntry],
+    params: &Params,
+    backup: bool,
 ) -> Result<(), Error> {
     let plaintext = to_json(entries)?;
-    let ciphertext = scrypt_format::encrypt(plaintext.as_bytes(), passphrase.as_bytes(), params)?;
+    let key = passphrase.key_for(params, &scrypt_format::new_salt())?;
+    let ciphertext = scrypt_format::encrypt_with_key(plaintext.as_bytes(), &key);
 
     let write_err = |source| Error::Write {
         file: file.to_path_buf(),
         source,
     };
-    let dir = match file.parent() {
-        Some(parent) if !parent.as_os_str().is_empty() => parent,
-        _ => Path::new("."),
-    };
-    let tmp_path = temp_path(file);
-
-    // Remove a stale temp file from a crashed previous run, then create
-    // exclusively so an existing file (or symlink) is never followed.
-    match fs::remove_file(&tmp_path) {
-        Ok(()) => {}
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
-        Err(e) => return Err(write_err(e)),
-    }
-    let mut open_options = fs::OpenOptions::new();
-    open_options.write(true).create_new(true);
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::OpenOptionsExt;
-        open_options.mode(0o600);
-    }
-
+    let _lock = lock(file).map_err(write_err)?;
+    recover_locked(file)?;
+    let new_path = new_path(file);
+    let journal_path = journal_path(file);
     let result = (|| {
-        let mut tmp = open_options.open(&tmp_path)?;
-        tmp.write_all(&ciphertext)?;
-        tmp.sync_all()?;
+        create_synced(&new_path, &ciphertext)?;
 
-        if file.exists() {
-            let bak = backup_path(file);
+        let bak = backup_path(file);
+        if !backup {
+            match fs::remove_file(&bak) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        } else if file.exists() {
             fs::copy(file, &bak)?;
             #[cfg(unix)]
             {
@@ -174,21 +340,199 @@ pub fn store(
             }
         }
 
-        fs::rename(&tmp_path, file)?;
-        #[cfg(unix)]
-        fs::File::open(dir).and_then(|d| d.sync_all())?;
-        Ok(())
+        create_synced(&journal_path, journal_record(&ciphertext).as_bytes())?;
+        sync_dir(file)?;
+        fs::rename(&new_path, file)?;
+        sync_dir(file)
     })();
+    // The new vault is in place: a journal that cannot be removed is left
+    // for the next recovery, which finds the write completed.
+    if result.is_ok() {
+        let _ = fs::remove_file(&journal_path);
+    }
     if result.is_err() {
-        let _ = fs::remove_file(&tmp_path);
+        // The journal goes first: a journal without a new file means the
+        // rename happened.
+        let _ = fs::remove_file(&journal_path);
+        let _ = fs::remove_file(&new_path);
     }
     result.map_err(write_err)
 }
 
-/// `<file>.tmp` next to the vault, e.g. `pw.scrypt` -> `pw.scrypt.tmp`.
-fn temp_path(file: &Path) -> PathBuf {
+/// What [`recover`] found next to a vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// No interrupted write
+    Clean,
+    /// A write had recorded its intent, and the new vault is now in place
+    Completed,
+    /// A write was interrupted before recording its intent; its new file is
+    /// removed and the vault is as before
+    RolledBack,
+}
+
+/// Complete or roll back a [`store`] that was interrupted, e.g. by a crash
+/// or power loss, under the lock [`store`] takes. Does nothing, and writes
+/// nothing, if there was none.
+pub fn recover(file: &Path) -> Result<Recovery, Error> {
+    let _lock = lock(file).map_err(|source| Error::Write {
+        file: file.to_path_buf(),
+        source,
+    })?;
+    recover_locked(file)
+}
+
+/// What the next [`store`] will [`recover`], found without changing
+/// anything; [`Recovery::Clean`] while another process is writing.
+pub fn pending_recovery(file: &Path) -> Result<Recovery, Error> {
+    let read_err = |source| Error::Read {
+        file: file.to_path_buf(),
+        source,
+    };
+    let _lock = match fs::File::open(lock_path(file)) {
+        Ok(lock) => match lock.try_lock() {
+            Ok(()) => Some(lock),
+            Err(fs::TryLockError::WouldBlock) => return Ok(Recovery::Clean),
+            Err(fs::TryLockError::Error(e)) => return Err(read_err(e)),
+        },
+        // Written only by versions of pw before the lock.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(read_err(e)),
+    };
+    inspect(file).map_err(read_err)
+}
+
+/// What [`recover`] is to do, from the journal and new file left behind.
+fn inspect(file: &Path) -> std::io::Result<Recovery> {
+    let journal = read_if_exists(&journal_path(file))?;
+    let new = read_if_exists(&new_path(file))?;
+    Ok(match (&journal, &new) {
+        (None, None) => Recovery::Clean,
+        (Some(journal), Some(new)) if *journal == journal_record(new).as_bytes() => {
+            Recovery::Completed
+        }
+        (Some(_), None) => Recovery::Completed,
+        (_, Some(_)) => Recovery::RolledBack,
+    })
+}
+
+/// [`recover`], with the lock held.
+fn recover_locked(file: &Path) -> Result<Recovery, Error> {
+    let write_err = |source| Error::Write {
+        file: file.to_path_buf(),
+        source,
+    };
+    let new_path = new_path(file);
+    let journal_path = journal_path(file);
+    let recovery = inspect(file).map_err(write_err)?;
+    match recovery {
+        Recovery::Clean => return Ok(recovery),
+        Recovery::Completed => {
+            if new_path.exists() {
+                fs::rename(&new_path, file).map_err(write_err)?;
+                sync_dir(file).map_err(write_err)?;
+            }
+            // The new vault is in place, whether or not the journal goes.
+            let _ = remove_if_exists(&journal_path);
+        }
+        Recovery::RolledBack => {
+            remove_if_exists(&journal_path).map_err(write_err)?;
+            remove_if_exists(&new_path).map_err(write_err)?;
+        }
+    }
+    sync_dir(file).map_err(write_err)?;
+    Ok(recovery)
+}
+
+/// The journal's record of the intent to rename `ciphertext` over the vault.
+fn journal_record(ciphertext: &[u8]) -> String {
+    let checksum: String = Sha256::digest(ciphertext)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("pw-journal 1\nrename-new sha256:{checksum}\n")
+}
+
+/// Create `path` exclusively, so an existing file (or symlink) is never
+/// followed, with `0o600` on Unix, and write and fsync `contents`.
+fn create_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut open_options = fs::OpenOptions::new();
+    open_options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(0o600);
+    }
+    let mut out = open_options.open(path)?;
+    out.write_all(contents)?;
+    out.sync_all()
+}
+
+/// Make renames and removals next to `file` durable.
+fn sync_dir(file: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir).and_then(|d| d.sync_all())?;
+    }
+    #[cfg(not(unix))]
+    let _ = file;
+    Ok(())
+}
+
+/// Open `<file>.lock`, creating it `0o600`, and hold an exclusive lock on it
+/// until the returned file is dropped. The lock file stays: removing it
+/// would let two processes lock different files.
+fn lock(file: &Path) -> std::io::Result<fs::File> {
+    let mut open_options = fs::OpenOptions::new();
+    open_options.write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(0o600);
+    }
+    let lock = open_options.open(lock_path(file))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+fn read_if_exists(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// `<file>.new` next to the vault, e.g. `pw.scrypt` -> `pw.scrypt.new`.
+fn new_path(file: &Path) -> PathBuf {
     let mut name = file.as_os_str().to_owned();
-    name.push(".tmp");
+    name.push(".new");
+    PathBuf::from(name)
+}
+
+/// `<file>.journal` next to the vault, e.g. `pw.scrypt` ->
+/// `pw.scrypt.journal`.
+fn journal_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+/// `<file>.lock` next to the vault, e.g. `pw.scrypt` -> `pw.scrypt.lock`.
+fn lock_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".lock");
     PathBuf::from(name)
 }
 
@@ -221,6 +565,18 @@ mod tests {
             username: format!("{name}-user"),
             password: password.into(),
             url: None,
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         }
     }
 
@@ -263,6 +619,62 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn derived_key_opens_the_vault_until_it_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = vault_file(&dir);
+        let entries = vec![entry("a", "pw-a")];
+        store(&file, &passphrase(), &entries, &TEST_PARAMS).unwrap();
+        let key = derive_key(&file, &passphrase()).unwrap();
+        // The keys alone open it; the passphrase is not used.
+        let keyed = Passphrase::new("not the passphrase".to_string()).with_derived_key(key);
+        assert_eq!(load(&file, &keyed).unwrap(), entries);
+
+        store(&file, &passphrase(), &entries, &TEST_PARAMS).unwrap();
+        assert!(matches!(
+            load(&file, &keyed).unwrap_err(),
+            Error::Format(scrypt_format::Error::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn challenge_response_changes_with_every_write() {
+        use std::sync::Mutex;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = vault_file(&dir);
+        let entries = vec![entry("a", "pw-a")];
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let asked_by = Arc::clone(&asked);
+        // A stand-in for a YubiKey: any function of the challenge alone.
+        let keyed = passphrase().with_challenge_response(move |salt| {
+            asked_by.lock().unwrap().push(salt.to_vec());
+            Ok(Zeroizing::new(Sha256::digest(salt)[..20].to_vec()))
+        });
+        store(&file, &keyed, &entries, &TEST_PARAMS).unwrap();
+        let first = fs::read(&file).unwrap();
+        assert_eq!(load(&file, &keyed).unwrap(), entries);
+        assert!(load(&file, &passphrase()).is_err());
+        // The response for this salt opens it like a key file, until the
+        // next write.
+        let response = Sha256::digest(scrypt_format::salt(&first).unwrap());
+        let this_write = passphrase().with_key_file(&response[..20]);
+        assert_eq!(load(&file, &this_write).unwrap(), entries);
+
+        store(&file, &keyed, &entries, &TEST_PARAMS).unwrap();
+        assert_eq!(load(&file, &keyed).unwrap(), entries);
+        assert!(load(&file, &this_write).is_err());
+        let asked = asked.lock().unwrap();
+        assert_eq!(asked[0], scrypt_format::salt(&first).unwrap());
+        assert_ne!(asked[0], asked[2]);
+
+        let absent = passphrase().with_challenge_response(|_| Err("no YubiKey".to_string()));
+        assert!(matches!(
+            load(&file, &absent).unwrap_err(),
+            Error::Response(message) if message == "no YubiKey"
+        ));
+    }
+
     #[test]
     fn missing_file_is_read_error() {
         let dir = tempfile::tempdir().unwrap();
@@ -372,25 +784,95 @@ mod tests {
 
         assert_eq!(load(&file, &passphrase()).unwrap(), entries);
         assert!(!backup_path(&file).exists());
-        // No stray temp files left in the directory.
-        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        // No stray temp files left in the directory, only the lock.
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["pw.scrypt", "pw.scrypt.lock"]);
     }
 
     #[test]
-    fn store_uses_deterministic_temp_name_and_overwrites_stale_one() {
+    fn store_rolls_back_a_stale_new_file() {
         let dir = tempfile::tempdir().unwrap();
         let file = vault_file(&dir);
-        let tmp = temp_path(&file);
-        assert_eq!(tmp, dir.path().join("pw.scrypt.tmp"));
-        // A stale temp file from a crashed previous run must not block the
-        // write, and must be gone afterwards.
-        fs::write(&tmp, b"stale").unwrap();
+        let new = new_path(&file);
+        assert_eq!(new, dir.path().join("pw.scrypt.new"));
+        assert_eq!(journal_path(&file), dir.path().join("pw.scrypt.journal"));
+        // A new file from a crashed previous run, without a journal, must
+        // not block the write, and must be gone afterwards.
+        fs::write(&new, b"stale").unwrap();
         let entries = vec![entry("a", "pw-a")];
         store(&file, &passphrase(), &entries, &TEST_PARAMS).unwrap();
-        assert!(!tmp.exists());
+        assert!(!new.exists());
         assert_eq!(load(&file, &passphrase()).unwrap(), entries);
     }
 
+    #[test]
+    fn recover_completes_or_rolls_back_an_interrupted_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = vault_file(&dir);
+        let old = vec![entry("a", "pw-a")];
+        store(&file, &passphrase(), &old, &TEST_PARAMS).unwrap();
+        assert_eq!(recover(&file).unwrap(), Recovery::Clean);
+
+        // Crashed after recording the intent, before the rename.
+        let new = vec![entry("b", "pw-b")];
+        let plaintext = to_json(&new).unwrap();
+        let ciphertext =
+            scrypt_format::encrypt(plaintext.as_bytes(), passphrase().as_bytes(), &TEST_PARAMS)
+                .unwrap();
+        fs::write(new_path(&file), &ciphertext).unwrap();
+        fs::write(journal_path(&file), journal_record(&ciphertext)).unwrap();
+        assert_eq!(recover(&file).unwrap(), Recovery::Completed);
+        assert_eq!(load(&file, &passphrase()).unwrap(), new);
+
+        // Crashed while writing the journal: the intent does not count.
+        fs::write(new_path(&file), b"partial").unwrap();
+        fs::write(journal_path(&file), "pw-journal 1\nrename-new sha").unwrap();
+        assert_eq!(recover(&file).unwrap(), Recovery::RolledBack);
+        assert_eq!(load(&file, &passphrase()).unwrap(), new);
+
+        // Crashed after the rename, before removing the journal.
+        fs::write(journal_path(&file), journal_record(&ciphertext)).unwrap();
+        assert_eq!(recover(&file).unwrap(), Recovery::Completed);
+        assert_eq!(load(&file, &passphrase()).unwrap(), new);
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["pw.scrypt", "pw.scrypt.lock"]);
+    }
+
+    #[test]
+    fn pending_recovery_changes_nothing_and_waits_for_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = vault_file(&dir);
+        assert_eq!(pending_recovery(&file).unwrap(), Recovery::Clean);
+        store(&file, &passphrase(), &[], &TEST_PARAMS).unwrap();
+        fs::write(new_path(&file), b"partial").unwrap();
+        assert_eq!(pending_recovery(&file).unwrap(), Recovery::RolledBack);
+        assert!(new_path(&file).exists());
+
+        // In the middle of another write, its new file is not left over.
+        let writing = lock(&file).unwrap();
+        assert_eq!(pending_recovery(&file).unwrap(), Recovery::Clean);
+        drop(writing);
+        assert_eq!(recover(&file).unwrap(), Recovery::RolledBack);
+        assert!(!new_path(&file).exists());
+    }
+
+    #[test]
+    fn key_file_is_appended_as_a_hash() {
+        let combined = Passphrase::new("secret".to_string()).with_key_file(b"key");
+        assert_eq!(
+            combined.as_bytes(),
+            b"secret:2c70e12b7a0646f92279f427c7b38e7334d8e5389cff167a1dc30e73f826b683"
+        );
+    }
+
     #[test]
     fn debug_redacts_passphrase() {
         assert_eq!(format!("{:?}", passphrase()), "Passphrase([redacted])");
@@ -0,0 +1,136 @@
+//! The YubiKey of a vault created with `pw init --yubikey`.
+//!
+//! Such a vault needs, besides the passphrase, the HMAC-SHA1 response of a
+//! YubiKey slot programmed for challenge-response. The challenge is the salt
+//! of the vault's header, so it changes with every write, as KeePassXC's
+//! does: a response seen once opens the vault only until its next write, and
+//! `pw.scrypt.bak` still opens with its own salt. The response is combined
+//! with the passphrase like the contents of a key file, see
+//! [`crate::Passphrase::with_challenge_response`]. Only the slot, which is
+//! not secret, lives next to the vault in `pw.scrypt.yubikey`.
+//!
+//! Nothing but a YubiKey programmed with the same secret answers alike, so a
+//! spare one, programmed at the same time, is the way back in when the first
+//! is lost.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{decode_hex, PwError};
+
+/// Bytes in an HMAC-SHA1 response.
+pub const RESPONSE_LEN: usize = 20;
+
+/// `<file>.yubikey` next to the vault, e.g. `pw.scrypt` ->
+/// `pw.scrypt.yubikey`.
+pub fn config_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".yubikey");
+    PathBuf::from(name)
+}
+
+/// The YubiKey slot of a vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YubiKey {
+    /// The slot programmed for HMAC-SHA1 challenge-response: 1 or 2.
+    pub slot: u8,
+}
+
+impl YubiKey {
+    /// The YubiKey of the vault at `file`, or `None` if it has none.
+    pub fn load(file: &Path) -> Result<Option<Self>, PwError> {
+        let path = config_path(file);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(PwError::InvalidInput {
+                    what: "YubiKey configuration",
+                    reason: format!("cannot read {}: {e}", path.display()),
+                })
+            }
+        };
+        let yubikey = Self::from_json(&text).map_err(|reason| PwError::InvalidInput {
+            what: "YubiKey configuration",
+            reason: format!("{} {reason}", path.display()),
+        })?;
+        Ok(Some(yubikey))
+    }
+
+    fn from_json(text: &str) -> Result<Self, String> {
+        let yubikey: YubiKey =
+            serde_json::from_str(text).map_err(|e| format!("is not valid: {e}"))?;
+        if !(1..=2).contains(&yubikey.slot) {
+            return Err(format!("has slot {}, not 1 or 2", yubikey.slot));
+        }
+        Ok(yubikey)
+    }
+
+    /// The contents of `pw.scrypt.yubikey`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a slot serializes") + "\n"
+    }
+}
+
+/// The challenge for a header with `salt`, in the hex `ykchalresp -x` takes.
+pub fn challenge(salt: &[u8]) -> String {
+    salt.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The response `ykchalresp -x` prints, as bytes to use like a key file.
+pub fn parse_response(hex: &str) -> Result<Zeroizing<Vec<u8>>, PwError> {
+    match decode_hex(hex.trim()) {
+        Some(bytes) if bytes.len() == RESPONSE_LEN => Ok(Zeroizing::new(bytes)),
+        _ => Err(PwError::InvalidInput {
+            what: "YubiKey response",
+            reason: format!("is not {RESPONSE_LEN} hex-encoded bytes"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_round_trips_and_is_checked() {
+        let yubikey = YubiKey { slot: 2 };
+        assert_eq!(yubikey.to_json(), "{\"slot\":2}\n");
+        assert_eq!(YubiKey::from_json(&yubikey.to_json()).unwrap(), yubikey);
+
+        assert!(YubiKey::from_json(r#"{"slot":3}"#).is_err());
+        assert!(YubiKey::from_json(r#"{"slot":1,"challenge":"00"}"#).is_err());
+        assert!(YubiKey::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn response_is_twenty_hex_bytes() {
+        let response = parse_response("b1ff59a7f4e8c3d2a1908f7e6d5c4b3a29180716\n").unwrap();
+        assert_eq!(response[..2], [0xb1, 0xff]);
+        assert!(parse_response("b1ff").is_err());
+        assert!(parse_response("not hex at all, but forty characters long").is_err());
+    }
+
+    #[test]
+    fn challenge_is_the_salt_in_hex() {
+        assert_eq!(challenge(&[0x00, 0xab, 0x10]), "00ab10");
+    }
+
+    #[test]
+    fn config_sits_next_to_the_vault() {
+        assert_eq!(
+            config_path(Path::new("/home/alice/pw.scrypt")),
+            Path::new("/home/alice/pw.scrypt.yubikey")
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pw.scrypt");
+        assert_eq!(YubiKey::load(&file).unwrap(), None);
+        fs::write(config_path(&file), "{").unwrap();
+        assert!(YubiKey::load(&file).is_err());
+    }
+}
@@ -46,6 +46,327 @@ fn init_creates_vault() {
     assert!(vault.exists());
 }
 
+#[test]
+fn init_refuses_weak_passphrase_unless_allowed() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    pw(&vault)
+        .arg("init")
+        .write_stdin("P@ssw0rd1\n")
+        .assert()
+        .failure()
+        .stderr(
+            contains("the passphrase is weak")
+                .and(contains("it is a common password"))
+                .and(contains("--allow-weak")),
+        );
+    assert!(!vault.exists());
+
+    pw(&vault)
+        .args(["init", "--allow-weak"])
+        .write_stdin("P@ssw0rd1\n")
+        .assert()
+        .success()
+        .stderr(contains("Warning: the passphrase is weak"));
+}
+
+#[test]
+fn rekey_changes_the_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "mail", "alice");
+
+    pw(&vault)
+        .arg("rekey")
+        .write_stdin(format!("{PASSPHRASE}password\n"))
+        .assert()
+        .failure()
+        .stderr(contains("weak"));
+    pw(&vault)
+        .arg("rekey")
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
+        .success()
+        .stdout(contains("(1 entries)").and(contains("removed")));
+
+    pw(&vault)
+        .args(["get", "mail", "--show"])
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .success()
+        .stdout(format!("alice\n{password}\n"));
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure();
+    // The backup would still open with the old passphrase.
+    assert!(!dir.path().join("pw.scrypt.bak").exists());
+}
+
+#[test]
+fn rekey_harden_raises_the_scrypt_parameters() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "mail", "alice");
+    let pw_with = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("pw").unwrap();
+        cmd.arg("--file")
+            .arg(&vault)
+            .arg("--passphrase-stdin")
+            .args(args)
+            .args(["rekey", "--harden"]);
+        cmd
+    };
+
+    pw(&vault)
+        .args(["rekey", "--harden"])
+        .assert()
+        .failure()
+        .stderr(contains("already uses N=2^12, r=8, p=1 (4 MiB)"));
+    pw_with(&["--scrypt-max-memory", "8X"])
+        .assert()
+        .failure()
+        .stderr(contains("not a size"));
+    // 8 MiB fits N=2^13 with r=8.
+    pw_with(&["--scrypt-max-memory", "8M", "--scrypt-max-time", "600"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("with N=2^13, r=8, p=1 (8 MiB) instead of N=2^12"));
+    pw_with(&["--scrypt-log-n", "13"])
+        .assert()
+        .failure()
+        .stderr(contains("already uses N=2^13"));
+
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("mail: alice"));
+}
+
+#[test]
+fn key_file_is_required_with_the_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    let key = dir.path().join("pw.key");
+    let pw_key = |key: &Path| {
+        let mut cmd = pw(&vault);
+        cmd.arg("--key-file").arg(key);
+        cmd
+    };
+
+    // No key file is left behind by an init that fails.
+    let mut cmd = pw(&dir.path().join("missing/pw.scrypt"));
+    cmd.arg("--key-file")
+        .arg(&key)
+        .arg("init")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("Created key file").not());
+    assert!(!key.exists());
+    pw_key(&key)
+        .arg("init")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("Created key file"));
+    assert_eq!(std::fs::read(&key).unwrap().len(), 64);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    pw_key(&key)
+        .args(["add", "mail", "alice", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw_key(&key)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("mail: alice"));
+
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("incorrect passphrase"));
+    let other = dir.path().join("other.key");
+    std::fs::write(&other, b"not the key").unwrap();
+    pw_key(&other)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("incorrect passphrase or key file"));
+    pw_key(&dir.path().join("missing.key"))
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("missing.key"));
+
+    // Changing the passphrase keeps the key file.
+    pw_key(&key)
+        .arg("rekey")
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
+        .success();
+    pw_key(&key)
+        .arg("list")
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .success()
+        .stdout(contains("mail: alice"));
+    pw(&vault)
+        .arg("list")
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .failure();
+}
+
+/// A stand-in for ykchalresp in `dir/bin`, answering slot 2 with a function
+/// of the challenge, which it logs to `dir/challenges`; returns a `$PATH`
+/// with it first.
+#[cfg(unix)]
+fn fake_ykchalresp(dir: &TempDir) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let ykchalresp = bin.join("ykchalresp");
+    let answer = format!(
+        "#!/bin/sh\n[ \"$1\" = -2 ] && [ \"$2\" = -x ] || exit 1\n\
+         echo \"$3\" >> {}\nprintf %s \"$3\" | sha1sum | cut -c1-40\n",
+        dir.path().join("challenges").display()
+    );
+    std::fs::write(&ykchalresp, answer).unwrap();
+    std::fs::set_permissions(&ykchalresp, std::fs::Permissions::from_mode(0o755)).unwrap();
+    format!("{}:{}", bin.display(), std::env::var("PATH").unwrap())
+}
+
+#[cfg(unix)]
+#[test]
+fn yubikey_response_is_required_with_the_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    let path = fake_ykchalresp(&dir);
+    let challenges = || {
+        std::fs::read_to_string(dir.path().join("challenges"))
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    pw(&vault)
+        .args(["init", "--yubikey"])
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("recovery").not());
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("pw.scrypt.yubikey")).unwrap(),
+        "{\"slot\":2}\n"
+    );
+    let at_init = challenges();
+    pw(&vault)
+        .args(["add", "mail", "alice", "--show"])
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .arg("list")
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("mail: alice"));
+    // Every write asks a new challenge: the add's read answers the one of
+    // `init`, its write a new one.
+    let asked = challenges();
+    assert_eq!(asked[..at_init.len()], at_init[..]);
+    assert_eq!(asked[at_init.len()], at_init[0]);
+    assert!(!at_init.contains(asked.last().unwrap()));
+
+    pw(&vault)
+        .arg("list")
+        .env("PATH", dir.path().join("nothing"))
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("cannot find ykchalresp"));
+    let key = dir.path().join("pw.key");
+    std::fs::write(&key, b"a key file").unwrap();
+    pw(&vault)
+        .arg("--key-file")
+        .arg(&key)
+        .arg("list")
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("needs its YubiKey"));
+    std::fs::write(dir.path().join("bin/ykchalresp"), "#!/bin/sh\nexit 1\n").unwrap();
+    pw(&vault)
+        .arg("list")
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("no response from the YubiKey in slot 2"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn agent_keeps_a_yubikey_vault_unlocked_across_writes() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    let path = fake_ykchalresp(&dir);
+    let cmd = || {
+        let mut cmd = pw(&vault);
+        cmd.env("XDG_RUNTIME_DIR", dir.path().join("run"))
+            .env("PATH", &path);
+        cmd
+    };
+
+    cmd()
+        .args(["init", "--yubikey"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    cmd()
+        .args(["unlock", "--idle-minutes", "1"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    // The write asks the YubiKey for the new salt, but not the passphrase.
+    cmd()
+        .args(["add", "a", "alice", "--show"])
+        .assert()
+        .success();
+    // Reads then need neither, until `pw lock`.
+    std::fs::write(dir.path().join("bin/ykchalresp"), "#!/bin/sh\nexit 1\n").unwrap();
+    cmd()
+        .args(["show", "a"])
+        .assert()
+        .success()
+        .stdout(contains("username: alice"));
+    cmd()
+        .arg("lock")
+        .assert()
+        .stdout(contains(format!("The agent forgot {}.", vault.display())));
+}
+
 #[test]
 fn init_fails_if_vault_already_exists() {
     let dir = TempDir::new().unwrap();
@@ -85,6 +406,155 @@ fn add_then_get_round_trip() {
         .stdout(format!("user1\n{password}\n"));
 }
 
+#[test]
+fn get_hint_reveals_only_ends_and_length() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+    let chars: Vec<char> = password.chars().collect();
+    let head: String = chars[..2].iter().collect();
+    let tail: String = chars[chars.len() - 2..].iter().collect();
+
+    pw(&vault)
+        .args(["get", "foo", "--hint"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user1\n{head}…{tail} (16 chars)\n"));
+}
+
+/// The fake clipboard file of `dir`, which `PW_CLIPBOARD_FILE` makes pw copy
+/// to instead of the system clipboard.
+fn clipboard(dir: &TempDir) -> PathBuf {
+    dir.path().join("clipboard")
+}
+
+fn read_clipboard(dir: &TempDir) -> String {
+    std::fs::read_to_string(clipboard(dir)).unwrap()
+}
+
+#[test]
+fn get_copies_the_password_and_clears_it_after_the_timeout() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+
+    pw(&vault)
+        .args(["get", "foo", "--clear-timeout", "0"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("user1\n")
+        .stderr(
+            contains("Password for 'foo' copied to clipboard.\n")
+                .and(contains("Warning: $PW_CLIPBOARD_FILE is set")),
+        );
+    assert_eq!(read_clipboard(&dir), password);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(clipboard(&dir))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    pw(&vault)
+        .args(["get", "foo", "--clear-timeout", "1"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("clearing in 1s").and(contains("Clipboard cleared.")));
+    // Cleared with a space, which desktop clipboard managers pick up.
+    assert_eq!(read_clipboard(&dir), " ");
+}
+
+#[test]
+fn add_and_generate_copy_to_the_clipboard() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+
+    pw(&vault)
+        .args(["generate", "--clear-timeout", "0"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(contains("Generated password copied to clipboard."));
+    assert_eq!(read_clipboard(&dir).chars().count(), 16);
+
+    pw(&vault)
+        .args(["add", "foo", "user1", "--clear-timeout", "0"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("Password for 'foo' copied to clipboard."));
+    pw(&vault)
+        .args(["get", "foo", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user1\n{}\n", read_clipboard(&dir)));
+
+    // A password that was copied but never saved is cleared at once.
+    pw(&vault)
+        .args(["add", "foo", "user2", "--clear-timeout", "0"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("Clipboard cleared."));
+    assert_eq!(read_clipboard(&dir), " ");
+}
+
+#[test]
+fn clipboard_changed_during_the_wait_is_left_alone() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_pw"))
+        .arg("--file")
+        .arg(&vault)
+        .args(["--passphrase-stdin", "--scrypt-log-n", "12"])
+        .args(["get", "foo", "--clear-timeout", "3"])
+        .env("PW_CLIPBOARD_FILE", clipboard(&dir))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(PASSPHRASE.as_bytes())
+        .unwrap();
+    // Copy something else while pw waits to clear its password.
+    let copied = (0..100).any(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::read_to_string(clipboard(&dir)).is_ok_and(|text| text == password)
+    });
+    assert!(copied, "password was never copied");
+    std::fs::write(clipboard(&dir), "mine").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Clipboard changed since copy; left as-is."),
+        "{stderr}"
+    );
+    assert_eq!(read_clipboard(&dir), "mine");
+}
+
 #[test]
 fn add_without_username() {
     let dir = TempDir::new().unwrap();
@@ -246,233 +716,2601 @@ fn list_shows_vault_path_and_filters() {
 }
 
 #[test]
-fn wrong_passphrase() {
+fn list_pages_with_limit_and_offset() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
+    for name in ["a", "b", "c", "d", "e"] {
+        add_entry(&vault, name, "user");
+    }
+
+    pw(&vault)
+        .args(["list", "--offset", "1", "--limit", "2"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains(
+            "(5 entries)\nb: user\nc: user\nShowing 2-3 of 5 entries.\n",
+        ));
+    pw(&vault)
+        .args(["list", "--offset", "5"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            contains(": user")
+                .not()
+                .and(contains("Showing none of 5 entries.")),
+        );
+    pw(&vault)
+        .args(["search", "--limit", "1", "user"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("a: user\nShowing 1-1 of 5 entries."));
     pw(&vault)
         .arg("list")
-        .write_stdin("not the passphrase\n")
+        .write_stdin(PASSPHRASE)
         .assert()
-        .failure()
-        .stderr(contains("incorrect passphrase"));
+        .success()
+        .stdout(contains("Showing").not());
 }
 
 #[test]
-fn export_prints_the_vault_as_json() {
+fn timestamps_are_shown_by_list_long_and_get_verbose() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
     let password = add_entry(&vault, "foo", "user1");
 
+    let date = r"20[0-9]{2}-[01][0-9]-[0-3][0-9]";
     pw(&vault)
-        .arg("export")
+        .args(["list", "--long"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success()
-        .stdout(contains(r#""version":1"#).and(contains(format!(
-            r#"{{"name":"foo","username":"user1","password":"{password}"}}"#
-        ))))
-        .stderr(contains("Warning"));
-}
-
-#[test]
-fn url_is_stored_and_shown() {
+        .stdout(
+            predicate::str::is_match(format!(
+                "\nfoo: user1 \\(created {date}, updated {date}\\)\n"
+            ))
+            .unwrap(),
+        );
+    pw(&vault)
+        .args(["get", "foo", "--show", "--verbose"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user1\n{password}\n"))
+        .stderr(
+            predicate::str::is_match(format!("created: {date} [0-9:]{{8}} UTC\nupdated: {date} "))
+                .unwrap(),
+        );
+}
+
+#[test]
+fn pinned_entries_are_listed_first() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "foo", "user1");
+    add_entry(&vault, "bar", "user2");
+
+    pw(&vault)
+        .args(["pin", "foo"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Pinned 'foo'.\n");
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("foo: user1\nbar: user2\n"));
+    pw(&vault)
+        .args(["show", "foo"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("pinned"));
+
+    pw(&vault)
+        .args(["unpin", "foo"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Unpinned 'foo'.\n");
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("bar: user2\nfoo: user1\n"));
+}
+
+#[test]
+fn wrong_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .arg("list")
+        .write_stdin("not the passphrase\n")
+        .assert()
+        .failure()
+        .stderr(contains("incorrect passphrase"));
+}
+
+#[test]
+fn export_prints_the_vault_as_json() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+
     pw(&vault)
+        .arg("export")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains(r#""version":1"#).and(contains(format!(
+            r#"{{"name":"foo","username":"user1","password":"{password}","created_at":"#
+        ))))
+        .stderr(contains("Warning"));
+}
+
+#[test]
+fn export_csv_asks_first_and_writes_browser_columns() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let assert = pw(&vault)
         .args([
             "add",
-            "work-github",
-            "alice",
+            "mail",
+            "al, ice",
             "--url",
-            "github.com",
-            "--show",
+            "https://mail.example.com",
         ])
+        .args(["--notes", "say \"hi\"\nthen go", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    let password = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let password = password.trim_end();
+    let expected = format!(
+        "name,url,username,password,note\r\n\
+         mail,https://mail.example.com,\"al, ice\",{password},\"say \"\"hi\"\"\nthen go\"\r\n"
+    );
+
+    pw(&vault)
+        .args(["export", "--format", "csv"])
+        .write_stdin("n\n")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(contains("Aborted"));
+    pw(&vault)
+        .args(["export", "--format", "csv"])
+        .write_stdin(format!("y\n{PASSPHRASE}"))
+        .assert()
+        .success()
+        .stdout(expected.clone());
+
+    let out = dir.path().join("export.csv");
+    pw(&vault)
+        .args(["export", "--format", "csv", "--yes", "--output"])
+        .arg(&out)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(contains("unencrypted"));
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&out).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // An existing file is never overwritten.
+    pw(&vault)
+        .args(["export", "--format", "csv", "--yes", "--output"])
+        .arg(&out)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("cannot write"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn safety_json_allows_or_blocks_risky_uses() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let config = dir.path().join("config");
+    std::fs::create_dir_all(config.join("pw")).unwrap();
+    std::fs::write(
+        config.join("pw/safety.json"),
+        r#"{"plaintext-export": "block", "weak-scrypt": "allow"}"#,
+    )
+    .unwrap();
+    let cmd = || {
+        let mut cmd = pw(&vault);
+        cmd.env("XDG_CONFIG_HOME", &config);
+        cmd
+    };
+
+    cmd()
+        .args(["export", "--format", "json"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(contains("blocks plaintext-export"));
+    cmd()
+        .args(["list"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("Warning").not());
+    cmd()
+        .args(["generate", "--password-length", "8", "--show"])
+        .assert()
+        .success()
+        .stderr(contains("Warning: 8 characters is a short password"));
+
+    std::fs::write(
+        config.join("pw/safety.json"),
+        r#"{"short-password": "never"}"#,
+    )
+    .unwrap();
+    cmd()
+        .args(["generate", "--show"])
+        .assert()
+        .failure()
+        .stderr(contains("safety configuration"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn safety_json_blocks_printing_secrets_from_every_command() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "db", "admin");
+    let config = dir.path().join("config");
+    std::fs::create_dir_all(config.join("pw")).unwrap();
+    std::fs::write(
+        config.join("pw/safety.json"),
+        r#"{"show-to-pipe": "block"}"#,
+    )
+    .unwrap();
+
+    for args in [
+        &["get", "db", "--show"][..],
+        &["show", "db", "--secrets"],
+        &["show", "db", "--first-line-only"],
+        &["history", "db", "--show"],
+        &["otp", "db", "--show"],
+        &["add", "new", "--show"],
+        &["update", "db", "admin", "--show"],
+        &["generate", "--show"],
+        &["lookup", "db", "--format", "tf-var"],
+        &["dsn", "db", "--format", "postgres"],
+    ] {
+        pw(&vault)
+            .env("XDG_CONFIG_HOME", &config)
+            .args(args)
+            .write_stdin(PASSPHRASE)
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(contains("blocks show-to-pipe"));
+    }
+    // Neither the blocked add nor the blocked update wrote anything.
+    pw(&vault)
+        .args(["list"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("new").not());
+}
+
+#[test]
+fn import_restores_a_json_export() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+    let backup = dir.path().join("backup.json");
+    pw(&vault)
+        .args(["export", "--format", "json", "--output"])
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    let other = dir.path().join("other.scrypt");
+    pw(&other)
+        .arg("init")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&other)
+        .arg("import")
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Imported 1 entries.\n");
+    pw(&other)
+        .args(["get", "foo", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user1\n{password}\n"));
+
+    // Importing the same backup again finds only duplicates.
+    pw(&other)
+        .arg("import")
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Imported 0 entries, skipped 1 already in the vault.\n");
+    pw(&other)
+        .args(["import", "--keep-duplicates"])
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("entry 'foo' already exists - pass --replace"));
+    pw(&other)
+        .args(["import", "--keep-duplicates", "--rename", "{name} (copy)"])
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Renamed 'foo' to 'foo (copy)'.\nImported 1 entries.\n");
+    pw(&other)
+        .args(["import", "--keep-duplicates", "--replace"])
+        .arg(&backup)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Imported 1 entries.\n");
+    pw(&other)
+        .args(["import", "-"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains(
+            "cannot read both the passphrase and the entries from stdin",
+        ));
+}
+
+#[test]
+fn import_reads_a_bitwarden_export() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let export = dir.path().join("bitwarden.json");
+    std::fs::write(
+        &export,
+        r#"{"encrypted": false,
+            "folders": [{"id": "f1", "name": "Work"}],
+            "items": [
+              {"type": 1, "name": "github", "folderId": "f1", "favorite": false,
+               "login": {"username": "alice", "password": "s3cret",
+                         "uris": [{"uri": "https://github.com"}]}},
+              {"type": 2, "name": "wifi", "notes": "network: home",
+               "secureNote": {"type": 0}},
+              {"type": 4, "name": "me", "identity": {}}]}"#,
+    )
+    .unwrap();
+
+    pw(&vault)
+        .args(["import", "--format", "bitwarden"])
+        .arg(&export)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            "Imported 2 entries.\n\
+             Skipped 1 items pw has no place for, such as cards and identities.\n",
+        );
+    pw(&vault)
+        .args(["get", "github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("alice\ns3cret\n");
+    pw(&vault)
+        .args(["list", "--tag", "Work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("github"));
+
+    std::fs::write(&export, r#"{"encrypted": true, "items": []}"#).unwrap();
+    pw(&vault)
+        .args(["import", "--format", "bitwarden"])
+        .arg(&export)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("export from Bitwarden as unencrypted JSON"));
+}
+
+#[test]
+fn import_reads_csv_exports_with_a_dry_run() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let export = dir.path().join("lastpass.csv");
+    std::fs::write(
+        &export,
+        "url,username,password,totp,extra,name,grouping,fav\n\
+         https://github.com,alice,s3cret,,,github,Work,0\n\
+         http://sn,,,,\"network: home\",wifi,,0\n",
+    )
+    .unwrap();
+
+    pw(&vault)
+        .args(["import", "--format", "lastpass-csv", "--dry-run"])
+        .arg(&export)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Would import 'github'.\nWould import 'wifi'.\nWould import 2 entries.\n");
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("github").not());
+    pw(&vault)
+        .args(["import", "--format", "lastpass-csv"])
+        .arg(&export)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Imported 2 entries.\n");
+    pw(&vault)
+        .args(["get", "github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("alice\ns3cret\n");
+
+    let onepassword = dir.path().join("1password.csv");
+    std::fs::write(
+        &onepassword,
+        "Title,Url,Username,Password\nmail,,bob,pw1\n,,carol,pw2\nbank,x\n",
+    )
+    .unwrap();
+    pw(&vault)
+        .args(["import", "--format", "1password-csv"])
+        .arg(&onepassword)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains(
+            "line 3: invalid entry name: must not be empty; \
+             line 4: 2 fields where the header has 4",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn import_decrypts_a_pass_store_with_gpg() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+
+    // A stand-in for gpg that "decrypts" by printing its last argument.
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let gpg = bin.join("gpg");
+    std::fs::write(&gpg, "#!/bin/sh\nfor f; do :; done\ncat \"$f\"\n").unwrap();
+    std::fs::set_permissions(&gpg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let store = dir.path().join("password-store");
+    std::fs::create_dir_all(store.join("email")).unwrap();
+    std::fs::write(store.join(".gpg-id"), "alice@example.com\n").unwrap();
+    std::fs::write(
+        store.join("email/github.com.gpg"),
+        "s3cret\nusername: alice\nrecovery codes in the safe\n",
+    )
+    .unwrap();
+    std::fs::write(store.join("wifi.gpg"), "hunter2\n").unwrap();
+
+    pw(&vault)
+        .args(["import", "--format", "pass"])
+        .env("PASSWORD_STORE_DIR", &store)
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Imported 2 entries.\n");
+    pw(&vault)
+        .args(["get", "email/github.com", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("alice\ns3cret\n");
+    pw(&vault)
+        .args(["show", "email/github.com"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("recovery codes in the safe"));
+
+    std::fs::write(&gpg, "#!/bin/sh\nexit 2\n").unwrap();
+    pw(&vault)
+        .args(["import", "--format", "pass"])
+        .arg(&store)
+        .env("PATH", &path)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("gpg cannot decrypt"));
+}
+
+#[test]
+fn apply_creates_declared_entries_and_reports_drift() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let manifest = dir.path().join("team.json");
+    std::fs::write(
+        &manifest,
+        r#"{"entries": [{"name": "ci/deploy", "username": "deploy", "tags": ["ci"], "length": 32}]}"#,
+    )
+    .unwrap();
+
+    pw(&vault)
+        .arg("apply")
+        .arg(&manifest)
+        .arg("--dry-run")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("Would create 'ci/deploy'."));
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("ci/deploy").not());
+
+    pw(&vault)
+        .arg("apply")
+        .arg(&manifest)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Created 'ci/deploy'.\nCreated 1 entries; 0 already match the manifest.\n");
+    let assert = pw(&vault)
+        .args(["get", "ci/deploy", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let (username, password) = stdout.trim_end().split_once('\n').unwrap();
+    assert_eq!((username, password.len()), ("deploy", 32));
+
+    std::fs::write(
+        &manifest,
+        r#"{"entries": [{"name": "ci/deploy", "username": "ci"}]}"#,
+    )
+    .unwrap();
+    pw(&vault)
+        .arg("apply")
+        .arg(&manifest)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout(contains(
+            "ci/deploy: username is 'deploy', the manifest has 'ci'",
+        ))
+        .stderr(contains("1 of 1 declared entries differ from the manifest"));
+}
+
+#[test]
+fn rewrite_moves_usernames_and_domains() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args([
+            "add",
+            "mail",
+            "alice@corp.com",
+            "--url",
+            "https://mail.corp.com/",
+            "--show",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    add_entry(&vault, "home", "alice");
+
+    pw(&vault)
+        .args(["rewrite", "--match-username", "alice@corp.com"])
+        .assert()
+        .failure()
+        .stderr(contains("--set-username"));
+    pw(&vault)
+        .args(["rewrite", "--dry-run"])
+        .args([
+            "--match-username",
+            "alice@corp.com",
+            "--set-username",
+            "alice@new.org",
+        ])
+        .args(["--match-domain", "corp.com", "--set-domain", "new.org"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            "mail: username 'alice@corp.com' -> 'alice@new.org'\n\
+             mail: url https://mail.corp.com/ -> https://mail.new.org/\n\
+             Would change 1 entries.\n",
+        );
+    pw(&vault)
+        .args([
+            "rewrite",
+            "--match-domain",
+            "corp.com",
+            "--set-domain",
+            "new.org",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("Changed 1 entries."));
+    pw(&vault)
+        .args(["get", "mail", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("url: https://mail.new.org/"));
+}
+
+#[test]
+fn export_travel_writes_tagged_entries_to_a_new_vault() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "mail", "alice");
+    add_entry(&vault, "bank", "alice");
+    pw(&vault)
+        .args(["tag", "add", "mail", "travel"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    let travel = dir.path().join("travel.scrypt");
+    let out = travel.to_str().unwrap();
+    let args = ["export", "travel", "--tag", "travel", "--out", out];
+    pw(&vault)
+        .args(args)
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
+        .failure()
+        .stderr(contains("--new-passphrase"));
+    pw(&vault)
+        .args(args)
+        .arg("--new-passphrase")
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
+        .success()
+        .stdout(contains("Wrote 1 entries to "));
+
+    pw(&travel)
+        .args(["get", "mail", "--show"])
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .success()
+        .stdout(format!("alice\n{password}\n"));
+    pw(&travel)
+        .arg("list")
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .success()
+        .stdout(contains("bank").not());
+    pw(&vault)
+        .args(args)
+        .arg("--new-passphrase")
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
+        .failure()
+        .stderr(contains("already exists"));
+}
+
+#[test]
+fn export_kdbx_writes_a_keepass_database() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "mail", "alice");
+
+    pw(&vault)
+        .args(["export", "--format", "kdbx"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("--output"));
+
+    let kdbx = dir.path().join("vault.kdbx");
+    let out = kdbx.to_str().unwrap();
+    pw(&vault)
+        .args(["export", "--format", "kdbx", "--output", out])
+        .write_stdin(format!(
+            "{PASSPHRASE}another passphrase 42\nanother passphrase 42\n"
+        ))
+        .assert()
+        .success()
+        .stdout(contains("Wrote 1 entries to "));
+    let bytes = std::fs::read(&kdbx).unwrap();
+    assert_eq!(bytes[..8], [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&kdbx).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    pw(&vault)
+        .args(["export", "--format", "kdbx", "--output", out])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("already exists"));
+}
+
+#[test]
+fn export_inventory_has_no_passwords() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "db|prod", "admin, \"root\"");
+    pw(&vault)
+        .args(["tag", "add", "db|prod", "ops"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["export", "inventory", "--no-secrets"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            "| Name | Username | URL | Tags |\n\
+             |---|---|---|---|\n\
+             | db\\|prod | admin, \"root\" |  | ops |\n",
+        );
+    pw(&vault)
+        .args(["export", "inventory", "--no-secrets", "--format", "csv"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Name,Username,URL,Tags\r\ndb|prod,\"admin, \"\"root\"\"\",,ops\r\n")
+        .stdout(contains(password).not());
+    pw(&vault)
+        .args(["export", "inventory"])
+        .assert()
+        .failure()
+        .stderr(contains("--no-secrets"));
+}
+
+#[test]
+fn url_is_stored_and_shown() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args([
+            "add",
+            "work-github",
+            "alice",
+            "--url",
+            "github.com",
+            "--show",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    // The url is persisted in the vault...
+    pw(&vault)
+        .arg("export")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains(r#""url":"github.com""#));
+    // ...and surfaced (on stderr) by `get`.
+    pw(&vault)
+        .args(["get", "work-github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("url: github.com"));
+}
+
+#[test]
+fn match_lists_entries_by_url_best_first() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["add", "shop", "alice", "--url", "example.com", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["add", "admin", "root", "--url", "shop.example.com/admin"])
+        .args(["--match", "prefix", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["match", "https://shop.example.com/admin/login"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("admin: root\nshop: alice\n");
+    pw(&vault)
+        .args(["match", "https://shop.example.com/"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("shop: alice\n");
+    pw(&vault)
+        .args(["match", "example.org"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("no entry matches example.org"));
+    pw(&vault)
+        .args(["show", "admin"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("match: prefix"));
+    pw(&vault)
+        .args(["add", "x", "--match", "host", "--show"])
+        .assert()
+        .failure()
+        .stderr(contains("--url"));
+}
+
+#[test]
+fn update_without_url_clears_it() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["add", "site", "u", "--url", "example.com", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    // Omitting --url on update clears it, the same way omitting the username
+    // clears the username.
+    pw(&vault)
+        .args(["update", "site", "u", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .arg("export")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains(r#""url":"#).not());
+}
+
+#[test]
+fn update_keep_password_changes_metadata_only() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "foo", "user1");
+
+    // Re-point the entry at a site and relabel it, keeping the password.
+    pw(&vault)
+        .args([
+            "update",
+            "foo",
+            "user2",
+            "--url",
+            "github.com",
+            "--keep-password",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("password unchanged"));
+
+    // The username and url changed; the original password is intact.
+    pw(&vault)
+        .args(["get", "foo", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user2\n{password}\n"))
+        .stderr(contains("url: github.com"));
+}
+
+#[test]
+fn update_keep_password_rejects_input_password() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "foo", "user1");
+    // --keep-password and --input-password contradict each other.
+    pw(&vault)
+        .args(["update", "foo", "--keep-password", "--input-password"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn low_memory_writes_reduced_parameters() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    Command::cargo_bin("pw")
+        .unwrap()
+        .arg("--file")
+        .arg(&vault)
+        .args(["--passphrase-stdin", "--low-memory", "init"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("Warning: --low-memory"));
+    // log2(N) is the byte after the "scrypt" magic and the version.
+    assert_eq!(std::fs::read(&vault).unwrap()[7], 14);
+    pw(&vault)
+        .args(["--low-memory", "list"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+}
+
+#[test]
+fn interrupted_writes_are_recovered_by_the_next_command() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "foo", "user1");
+    let leftover = |suffix: &str| dir.path().join(format!("pw.scrypt.{suffix}"));
+
+    // Crashed before recording the intent: even a read discards the new
+    // file.
+    std::fs::write(leftover("new"), "partial").unwrap();
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("foo"))
+        .stderr(contains("and discarded it"));
+    assert!(!leftover("new").exists());
+    add_entry(&vault, "bar", "user2");
+
+    // Crashed after the rename: only the journal is left to clear.
+    std::fs::write(leftover("journal"), "pw-journal 1\n").unwrap();
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stderr(contains("and completed it"));
+    assert!(!leftover("journal").exists());
+    add_entry(&vault, "baz", "user3");
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("bar").and(contains("baz")))
+        .stderr(contains("interrupted").not());
+}
+
+#[test]
+fn backup_is_kept_after_rewrite() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "foo", "user1");
+
+    let backup = dir.path().join("pw.scrypt.bak");
+    assert!(backup.exists());
+
+    // The backup holds the previous generation: an empty vault.
+    pw(&backup)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("(0 entries)"));
+}
+
+#[test]
+fn rejects_control_characters_in_name() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["add", "bad\x1bname", "user", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("invalid entry name"));
+}
+
+#[test]
+fn generate_prints_password_of_requested_length() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt"); // not created; generate is stateless
+    let assert = pw(&vault)
+        .args(["generate", "--password-length", "32", "--show"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert_eq!(stdout.trim_end().chars().count(), 32);
+}
+
+#[test]
+fn generate_prints_usernames() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    pw(&vault)
+        .args([
+            "generate",
+            "--username-style",
+            "email",
+            "--domain",
+            "example.com",
+        ])
+        .args(["--email-base", "shop"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^shop\+[a-z0-9]{4}@example\.com\n$").unwrap());
+    pw(&vault)
+        .args(["generate", "--username-style", "handle"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^[a-z]{6}[0-9]{2}\n$").unwrap());
+    pw(&vault)
+        .args(["generate", "--username-style", "email"])
+        .assert()
+        .failure()
+        .stderr(contains("--domain"));
+}
+
+#[test]
+fn generate_rejects_bad_charset() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    pw(&vault)
+        .args(["generate", "--password-charset", "aaa", "--show"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid password charset"));
+}
+
+#[test]
+fn show_prints_attributes_without_password() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["add", "work-github", "alice", "--url", "github.com", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["show", "work-github"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            contains("name: work-github")
+                .and(contains("username: alice"))
+                .and(contains("url: github.com")),
+        );
+}
+
+#[test]
+fn notes_are_shown_kept_and_removed() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args([
+            "add",
+            "bank",
+            "alice",
+            "--notes",
+            "PIN hint: birthday\nCard ends 1234",
+        ])
+        .arg("--show")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["update", "bank", "alice", "--keep-password"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["show", "bank"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("notes:\n  PIN hint: birthday\n  Card ends 1234\n"));
+
+    pw(&vault)
+        .args(["update", "bank", "alice", "--notes", "", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["show", "bank"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("notes").not());
+}
+
+#[test]
+fn show_prints_secrets_only_when_asked() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "bank", "alice");
+    pw(&vault)
+        .args(["set-field", "bank", "pin"])
+        .write_stdin(format!("{PASSPHRASE}1234\n"))
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["show", "bank"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            contains(password.as_str())
+                .not()
+                .and(contains("fields: pin\n")),
+        );
+    pw(&vault)
+        .args(["show", "bank", "--secrets"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with(format!("{password}\nname: bank\nusername: alice\n"))
+                .and(contains("fields:\n  pin: 1234\n")),
+        )
+        .stderr(contains("Warning").not());
+    pw(&vault)
+        .args(["show", "bank", "--first-line-only"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("{password}\n"));
+}
+
+#[test]
+fn show_omits_absent_username_and_url() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "bare", "");
+
+    pw(&vault)
+        .args(["show", "bare"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("name: bare").and(contains("username").not()).and(contains("url").not()));
+}
+
+#[test]
+fn show_fails_for_unknown_entry() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["show", "bogus"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("no entry 'bogus'"));
+}
+
+#[test]
+fn tag_commands_add_list_rename_and_remove() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+    add_entry(&vault, "b", "bob");
+    for (name, tag) in [("a", "job"), ("b", "job"), ("b", "shared")] {
+        pw(&vault)
+            .args(["tag", "add", name, tag])
+            .write_stdin(PASSPHRASE)
+            .assert()
+            .success();
+    }
+
+    pw(&vault)
+        .args(["tag", "list"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("job: 2\nshared: 1\n");
+
+    pw(&vault)
+        .args(["tag", "rename", "job", "work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("on 2 entries"));
+
+    pw(&vault)
+        .args(["tag", "rm", "b", "shared"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["tag", "rm", "b", "shared"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("not tagged 'shared'"));
+
+    pw(&vault)
+        .args(["show", "b"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("tags: work\n"));
+}
+
+#[test]
+fn tags_can_be_given_on_add_and_update_and_filter_list() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args([
+            "add", "a", "alice", "--tag", "work", "--tag", "email", "--show",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    add_entry(&vault, "b", "bob");
+    pw(&vault)
+        .args(["update", "b", "robert", "--keep-password", "--tag", "work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["add", "c", "carol", "--tag", "two words", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("invalid tag"));
+
+    pw(&vault)
+        .args(["list", "--tag", "work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("a: alice\nb: robert\n"));
+    pw(&vault)
+        .args(["tag", "remove", "a", "work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["list", "--tag", "work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("a: alice").not());
+    pw(&vault)
+        .args(["show", "a"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("tags: email\n"));
+}
+
+#[test]
+fn search_combines_query_terms() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    for (name, username, tag) in [
+        ("work-github", "alice", "work"),
+        ("home-github", "alice", "home"),
+        ("work-mail", "alice", "work"),
+    ] {
+        pw(&vault)
+            .args(["add", name, username, "--tag", tag, "--show"])
+            .write_stdin(PASSPHRASE)
+            .assert()
+            .success();
+    }
+
+    pw(&vault)
+        .args(["search", "tag:work user:alice github"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("Vault: ").and(contains("\nwork-github: alice\n")))
+        .stdout(
+            contains("home-github")
+                .not()
+                .and(contains("work-mail").not()),
+        );
+    pw(&vault)
+        .args(["search", "alice", "-tag:work"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("home-github: alice\n").and(contains("work-").not()));
+    add_entry(&vault, "email", "carol");
+    add_entry(&vault, "mail", "dave");
+    pw(&vault)
+        .args(["search", "mail"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("\nmail: dave\nemail: carol\nwork-mail: alice\n"));
+    pw(&vault)
+        .args(["search", "wrok-mail"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("\nwork-mail: alice\n"));
+    pw(&vault)
+        .args(["search", "usr:alice"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("invalid search query").and(contains("unknown key 'usr:'")));
+}
+
+#[test]
+fn search_in_notes_and_fields_shows_highlighted_snippets() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    pw(&vault)
+        .args(["add", "bank", "alice", "--show"])
+        .args(["--notes", "Branch: Main St\nRecovery codes are in the safe"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    add_entry(&vault, "phone", "bob");
+    pw(&vault)
+        .args(["set-field", "phone", "sim-pin"])
+        .write_stdin(format!("{PASSPHRASE}safe\n"))
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["search", "safe"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("bank").not());
+    pw(&vault)
+        .args(["search", "--in", "notes,fields", "SAFE"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            contains("\nbank: alice\n  notes: Recovery codes are in the [safe]\n")
+                .and(contains("phone").not()),
+        );
+    pw(&vault)
+        .args(["search", "--in", "fields", "pin"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("\nphone: bob\n  field: sim-[pin]\n").and(contains("bank").not()));
+}
+
+#[test]
+fn history_lists_and_restores_previous_passwords() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let first = add_entry(&vault, "foo", "user1");
+    pw(&vault)
+        .args(["history", "foo"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("'foo' has no previous passwords.\n");
+    pw(&vault)
+        .args(["update", "foo", "user1", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["history", "foo", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with("1: replaced 20")
+                .and(predicate::str::ends_with(format!(" UTC  {first}\n"))),
+        );
+    pw(&vault)
+        .args(["history", "foo"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("(16 chars)\n").and(contains(first.as_str()).not()));
+    pw(&vault)
+        .args(["history", "foo", "--restore", "1"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("Restored the previous password 1 of 'foo'."));
+    pw(&vault)
+        .args(["get", "foo", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("user1\n{first}\n"));
+    pw(&vault)
+        .args(["history", "foo", "--restore", "2"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("invalid history position"));
+}
+
+#[test]
+fn expiring_passwords_are_listed_and_audited() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    for (name, expires) in [("due", "0d"), ("soon", "10d"), ("later", "2w")] {
+        pw(&vault)
+            .args(["add", name, "alice", "--expires", expires, "--show"])
+            .write_stdin(PASSPHRASE)
+            .assert()
+            .success();
+    }
+    add_entry(&vault, "forever", "alice");
+
+    pw(&vault)
+        .args(["list", "--expired"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("\ndue: alice\n").and(contains("soon").not()));
+    pw(&vault)
+        .args(["audit", "--expiring", "10d"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout(
+            contains("due: password expired on ")
+                .and(contains("soon: password expires on "))
+                .and(contains("later").not())
+                .and(contains("forever").not()),
+        )
+        .stderr(contains("2 of 4 passwords failed the audit"));
+
+    pw(&vault)
+        .args([
+            "update",
+            "due",
+            "alice",
+            "--keep-password",
+            "--expires",
+            "never",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["update", "soon", "alice", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["show", "soon"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("expires: "));
+    pw(&vault)
+        .args(["list", "--expired"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("due").not());
+    pw(&vault)
+        .args(["add", "x", "--expires", "3m"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("invalid duration"));
+}
+
+#[test]
+fn get_suggests_close_names() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "work-github", "alice");
+    add_entry(&vault, "github", "bob");
+    add_entry(&vault, "bank", "carol");
+
+    pw(&vault)
+        .args(["get", "githb", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(
+            contains("no entry 'githb' in ").and(contains("did you mean 'github', 'work-github'?")),
+        );
+    pw(&vault)
+        .args(["search", "--exact", "githb"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("github").not());
+    pw(&vault)
+        .args(["search", "carol"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("\nbank: carol\n"));
+}
+
+#[test]
+fn rename_moves_an_entry_to_a_free_name() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "old", "alice");
+    add_entry(&vault, "taken", "bob");
+
+    pw(&vault)
+        .args(["rename", "old", "new"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Renamed entry 'old' to 'new'.\n");
+    pw(&vault)
+        .args(["get", "new", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("alice\n{password}\n"));
+    pw(&vault)
+        .args(["rename", "new", "taken"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("'taken' already exists"));
+}
+
+#[test]
+fn custom_fields_are_set_read_and_removed() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "bank", "alice");
+    pw(&vault)
+        .args(["set-field", "bank", "pin"])
+        .write_stdin(format!("{PASSPHRASE}1234\n"))
+        .assert()
+        .success()
+        .stdout("Set field 'pin' of 'bank'.\n");
+
+    pw(&vault)
+        .args(["get", "bank", "--field", "pin", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("1234\n");
+    pw(&vault)
+        .args(["show", "bank"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("fields: pin\n").and(contains("1234").not()));
+    pw(&vault)
+        .args(["get", "bank", "--field", "puk", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("has no field 'puk'"));
+
+    pw(&vault)
+        .args(["set-field", "bank", "pin", "--unset"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["set-field", "bank", "pin", "--unset"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("has no field 'pin'"));
+}
+
+#[test]
+fn otp_prints_the_current_code_of_a_stored_secret() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "github", "alice");
+    pw(&vault)
+        .args(["otp", "github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("has no TOTP secret"));
+    pw(&vault)
+        .args(["otp", "github", "--set"])
+        .write_stdin(format!("{PASSPHRASE}not a key!\n"))
+        .assert()
+        .failure()
+        .stderr(contains("invalid TOTP secret"));
+    pw(&vault)
+        .args(["otp", "github", "--set"])
+        .write_stdin(format!(
+            "{PASSPHRASE}otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP\n"
+        ))
+        .assert()
+        .success();
+
+    pw(&vault)
+        .args(["otp", "github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("^[0-9]{6}\n$").unwrap());
+    pw(&vault)
+        .args(["show", "github"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("totp: set\n").and(contains("JBSWY3DP").not()));
+
+    pw(&vault)
+        .args(["otp", "github", "--unset"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["otp", "github", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn hash_prints_and_verifies_a_sha_crypt_hash() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "root", "root");
+    let hash = |args: &[&str]| {
+        let assert = pw(&vault)
+            .arg("hash")
+            .args(args)
+            .arg("root")
+            .write_stdin(PASSPHRASE)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+    };
+
+    let random = hash(&[]);
+    assert!(random.starts_with("$6$") && !random.contains(&password));
+    assert_ne!(hash(&[]), random);
+    let salted = hash(&["--algorithm", "sha256", "--salt", "abc", "--rounds", "1000"]);
+    assert!(salted.starts_with("$5$rounds=1000$abc$"));
+    assert_eq!(
+        hash(&["--algorithm", "sha256", "--salt", "abc", "--rounds", "1000"]),
+        salted
+    );
+
+    pw(&vault)
+        .args(["hash", "--verify", salted.trim_end(), "root"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("The password of 'root' matches the hash.\n");
+    pw(&vault)
+        .args(["hash", "--verify", "$6$abc$wrong", "root"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("does not match the hash"));
+    pw(&vault)
+        .args(["hash", "--salt", "a:b", "root"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("salt"));
+}
+
+#[test]
+fn bulk_edit_applies_changes_from_the_editor() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "a", "alice");
+    add_entry(&vault, "b", "bob");
+
+    // A non-interactive "editor" that renames entry a and changes a username.
+    pw(&vault)
+        .args(["bulk-edit"])
+        .env_remove("VISUAL")
+        .env(
+            "EDITOR",
+            r#"sed -i -e s/"a",$/"renamed",/ -e s/bob/robert/"#,
+        )
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Updated 2 entries.\n");
+
+    pw(&vault)
+        .args(["get", "renamed", "--show"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(format!("alice\n{password}\n"));
+    pw(&vault)
+        .args(["show", "b"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("username: robert"));
+
+    // An editor that saves nothing new changes nothing.
+    pw(&vault)
+        .args(["bulk-edit"])
+        .env_remove("VISUAL")
+        .env("EDITOR", "true")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("No changes.\n");
+}
+
+#[test]
+fn edit_changes_notes_and_fields_from_the_editor() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "a", "alice");
+
+    pw(&vault)
+        .args(["edit", "a"])
+        .env_remove("VISUAL")
+        .env(
+            "EDITOR",
+            r#"sed -i -e s/"notes":."",/"notes":"line1\\nline2",/ -e s/"fields":.{}/"fields":{"pin":"1234"}/"#,
+        )
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("Updated 'a'.\n");
+    pw(&vault)
+        .args(["show", "a", "--secrets"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with(format!("{password}\n"))
+                .and(contains("notes:\n  line1\n  line2\n"))
+                .and(contains("fields:\n  pin: 1234\n")),
+        );
+
+    pw(&vault)
+        .args(["edit", "a"])
+        .env_remove("VISUAL")
+        .env("EDITOR", "true")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("No changes.\n");
+    pw(&vault)
+        .args(["edit", "a"])
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i s/notes/nodes/")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("cannot parse the edited entry"));
+}
+
+#[test]
+fn fsck_checks_the_vault_and_its_schema() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+
+    pw(&vault)
+        .arg("fsck")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("OK, 1 entries"));
+    pw(&vault)
+        .args(["fsck", "--schema"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("OK, matches the vault schema"));
+}
+
+#[test]
+fn audit_finds_breached_passwords_in_an_offline_corpus() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+    let corpus = dir.path().join("pwned-passwords-sha1-ordered-by-hash.txt");
+    // SHA-1 of "password", between two neighbours.
+    std::fs::write(
+        &corpus,
+        "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD7:1\r\n\
+         5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:9659365\r\n\
+         5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD9:2\r\n",
+    )
+    .unwrap();
+
+    pw(&vault)
+        .args(["audit", "--hibp-offline"])
+        .arg(&corpus)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("1 passwords checked, no problems found\n");
+
+    pw(&vault)
+        .arg("bulk-edit")
+        .arg("--with-passwords")
+        .env_remove("VISUAL")
+        .env(
+            "EDITOR",
+            r#"sed -i -e s/"password":."[^"]*"/"password":"password"/"#,
+        )
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["audit", "--hibp-offline"])
+        .arg(&corpus)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout("a: password seen 9659365 times in breaches\n")
+        .stderr(contains("1 of 1 passwords failed the audit"));
+}
+
+#[test]
+fn audit_checks_passwords_against_a_policy() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+    let policy = dir.path().join("policy.json");
+
+    std::fs::write(&policy, r#"{"min_length": 16, "forbid_username": true}"#).unwrap();
+    pw(&vault)
+        .args(["audit", "--policy"])
+        .arg(&policy)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("1 passwords checked, no problems found\n");
+
+    std::fs::write(&policy, r#"{"min_length": 20}"#).unwrap();
+    pw(&vault)
+        .args(["audit", "--policy"])
+        .arg(&policy)
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout("a: shorter than 20 characters (16)\n");
+
+    std::fs::write(&policy, r#"{"max_age_days": 90}"#).unwrap();
+    pw(&vault)
+        .args(["audit", "--policy"])
+        .arg(&policy)
+        .assert()
+        .failure()
+        .stderr(contains("invalid policy"));
+    let toml = dir.path().join("policy.toml");
+    std::fs::write(&toml, "min_length = 20\n").unwrap();
+    pw(&vault)
+        .args(["audit", "--policy"])
+        .arg(&toml)
+        .assert()
+        .failure()
+        .stderr(contains("policies are JSON"));
+    pw(&vault)
+        .arg("audit")
+        .assert()
+        .failure()
+        .stderr(contains("required"));
+}
+
+#[test]
+fn scan_finds_plaintext_copies_of_stored_passwords() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "db", "alice");
+    pw(&vault)
+        .arg("scan")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("No plaintext secrets found"));
+
+    std::fs::write(dir.path().join(".env"), format!("DB_PASSWORD={password}\n")).unwrap();
+    pw(&vault)
+        .arg("scan")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout(format!(
+            "{}: contains the password of 'db'\n",
+            dir.path().join(".env").display()
+        ))
+        .stderr(contains("1 files may hold plaintext secrets"));
+}
+
+#[test]
+fn watch_reports_a_change_to_the_vault() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let mut watch = std::process::Command::new(assert_cmd::cargo::cargo_bin("pw"))
+        .arg("--file")
+        .arg(&vault)
+        .args(["watch", "--interval", "1", "--once"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    // Let it take its first look before the vault changes.
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    add_entry(&vault, "a", "alice");
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    while watch.try_wait().unwrap().is_none() {
+        if std::time::Instant::now() > deadline {
+            watch.kill().unwrap();
+            panic!("pw watch did not notice the change");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let output = watch.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}: changed\n", vault.display())
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn status_prints_the_vault_and_directories() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    let status = |vault: &Path| {
+        let mut cmd = pw(vault);
+        cmd.arg("status")
+            .env("HOME", dir.path())
+            .env("XDG_CONFIG_HOME", dir.path().join("config"))
+            .env("XDG_STATE_HOME", dir.path().join("state"))
+            .env_remove("XDG_RUNTIME_DIR");
+        cmd
+    };
+    status(&vault).assert().success().stdout(
+        contains(format!("Vault:   {} (not created yet)\n", vault.display()))
+            .and(contains(format!(
+                "Config:  {}\n",
+                dir.path().join("config/pw").display()
+            )))
+            .and(contains(format!(
+                "State:   {}\n",
+                dir.path().join("state/pw").display()
+            )))
+            .and(contains("Runtime: none on this system")),
+    );
+    init_vault(&dir);
+    status(&vault)
+        .assert()
+        .success()
+        .stdout(contains("not created yet").not());
+}
+
+#[test]
+fn cache_minutes_keeps_the_passphrase_until_lock() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+    // Containers and sandboxes often deny the keyring system calls.
+    if !pw(&vault)
+        .arg("lock")
+        .assert()
+        .get_output()
+        .status
+        .success()
+    {
+        eprintln!("skipping: kernel keyring unavailable");
+        return;
+    }
+
+    pw(&vault)
+        .args(["--cache-minutes", "1", "show", "a"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    // No passphrase on stdin: it comes from the keyring.
+    pw(&vault)
+        .args(["--cache-minutes", "1", "show", "a"])
+        .assert()
+        .success()
+        .stdout(contains("username: alice"));
+
+    pw(&vault)
+        .arg("lock")
+        .assert()
+        .success()
+        .stdout(contains("Forgot the cached passphrase"));
+    pw(&vault)
+        .args(["--cache-minutes", "1", "show", "a"])
+        .assert()
+        .failure()
+        .stderr(contains("no passphrase on stdin"));
+}
+
+#[cfg(unix)]
+#[test]
+fn cache_backend_secret_service_expires_the_passphrase() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "a", "alice");
+
+    // A stand-in for secret-tool that keeps a single secret in a file.
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let store = bin.join("store");
+    let secret_tool = bin.join("secret-tool");
+    std::fs::write(
+        &secret_tool,
+        format!(
+            "#!/bin/sh\ncase \"$1\" in\n\
+             store) cat > '{0}' ;;\n\
+             lookup) [ -f '{0}' ] && cat '{0}' || exit 1 ;;\n\
+             clear) rm -f '{0}' ;;\n\
+             esac\n",
+            store.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&secret_tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let cached = |args: &[&str]| {
+        let mut cmd = pw(&vault);
+        cmd.args(["--cache-backend", "secret-service", "--cache-minutes", "1"])
+            .args(args)
+            .env("PATH", &path);
+        cmd
+    };
+
+    cached(&["show", "a"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    let payload = std::fs::read_to_string(&store).unwrap();
+    assert!(!payload.contains("test passphrase"));
+    cached(&["show", "a"])
+        .assert()
+        .success()
+        .stdout(contains("username: alice"));
+
+    // An expired passphrase is removed and asked for instead.
+    let (_, hex) = payload.split_once(':').unwrap();
+    std::fs::write(&store, format!("1:{hex}")).unwrap();
+    cached(&["show", "a"])
+        .assert()
+        .failure()
+        .stderr(contains("no passphrase on stdin"));
+    assert!(!store.exists());
+
+    cached(&["list"]).write_stdin(PASSPHRASE).assert().success();
+    cached(&["lock"])
+        .assert()
+        .success()
+        .stdout(contains("Forgot the cached passphrase"));
+    assert!(!store.exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn unlock_hands_the_vault_to_the_agent_until_lock() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "a", "alice");
+    let socket = dir.path().join("run/pw/agent.sock");
+    let cmd = || {
+        let mut cmd = pw(&vault);
+        cmd.env("XDG_RUNTIME_DIR", dir.path().join("run"));
+        cmd
+    };
+
+    cmd()
+        .args(["unlock", "--idle-minutes", "1"])
+        .write_stdin("wrong passphrase")
+        .assert()
+        .failure();
+    assert!(!socket.exists());
+    cmd()
+        .args(["unlock", "--idle-minutes", "1"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("Unlocked"));
+
+    // No passphrase on stdin: the agent holds it, also after a write.
+    cmd()
+        .args(["get", "a", "--show"])
+        .assert()
+        .success()
+        .stdout(contains(password.as_str()));
+    cmd().args(["add", "b", "bob", "--show"]).assert().success();
+    cmd()
+        .args(["show", "b"])
+        .assert()
+        .success()
+        .stdout(contains("username: bob"));
+    cmd()
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(contains("Agent:   running"));
+
+    // Containers often deny the kernel keyring, which `lock` also clears.
+    cmd()
+        .arg("lock")
+        .assert()
+        .stdout(contains(format!("The agent forgot {}.", vault.display())));
+    cmd()
+        .args(["show", "a"])
+        .assert()
+        .failure()
+        .stderr(contains("no passphrase on stdin"));
+    // Holding no vault, the agent exits and removes its socket.
+    let start = std::time::Instant::now();
+    while socket.exists() {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn rekey_keeps_the_key_file_of_a_vault_the_agent_holds() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt");
+    let key = dir.path().join("pw.key");
+    let cmd = |key: Option<&Path>| {
+        let mut cmd = pw(&vault);
+        cmd.env("XDG_RUNTIME_DIR", dir.path().join("run"));
+        if let Some(key) = key {
+            cmd.arg("--key-file").arg(key);
+        }
+        cmd
+    };
+
+    cmd(Some(&key))
+        .arg("init")
         .write_stdin(PASSPHRASE)
         .assert()
         .success();
-    // The url is persisted in the vault...
-    pw(&vault)
-        .arg("export")
+    cmd(Some(&key))
+        .args(["unlock", "--idle-minutes", "1"])
         .write_stdin(PASSPHRASE)
         .assert()
+        .success();
+    cmd(Some(&key))
+        .arg("rekey")
+        .write_stdin(format!("{PASSPHRASE}another passphrase 42\n"))
+        .assert()
         .success()
-        .stdout(contains(r#""url":"github.com""#));
-    // ...and surfaced (on stderr) by `get`.
+        .stdout(contains(format!("The agent forgot {}", vault.display())));
+
+    cmd(Some(&key))
+        .arg("list")
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .success();
+    cmd(None)
+        .arg("list")
+        .write_stdin("another passphrase 42\n")
+        .assert()
+        .failure()
+        .stderr(contains("incorrect passphrase"));
+}
+
+#[test]
+fn wrong_cached_passphrase_is_dropped_and_asked_for() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    if !pw(&vault)
+        .arg("lock")
+        .assert()
+        .get_output()
+        .status
+        .success()
+    {
+        eprintln!("skipping: kernel keyring unavailable");
+        return;
+    }
     pw(&vault)
-        .args(["get", "work-github", "--show"])
+        .args(["--cache-minutes", "1", "list"])
         .write_stdin(PASSPHRASE)
         .assert()
+        .success();
+
+    // Replace the vault with one under another passphrase: the cached one is
+    // now wrong, and the command goes on to read the right one.
+    let other = dir.path().join("other.scrypt");
+    for args in [vec!["init"], vec!["add", "a", "alice", "--show"]] {
+        pw(&other)
+            .args(args)
+            .write_stdin("another test passphrase\n")
+            .assert()
+            .success();
+    }
+    std::fs::copy(&other, &vault).unwrap();
+    pw(&vault)
+        .args(["--cache-minutes", "1", "show", "a"])
+        .write_stdin("another test passphrase\n")
+        .assert()
         .success()
-        .stderr(contains("url: github.com"));
+        .stdout(contains("username: alice"));
 }
 
 #[test]
-fn update_without_url_clears_it() {
+fn lookup_prints_ansible_extra_vars() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
-    pw(&vault)
-        .args(["add", "site", "u", "--url", "example.com", "--show"])
+    let password = add_entry(&vault, "db.example.com", "admin");
+
+    let assert = pw(&vault)
+        .args(["lookup", "db.example.com", "--format", "ansible-vars"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success();
-    // Omitting --url on update clears it, the same way omitting the username
-    // clears the username.
-    pw(&vault)
-        .args(["update", "site", "u", "--show"])
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let vars: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        vars,
+        serde_json::json!({
+            "db_example_com_username": "admin",
+            "db_example_com_password": password,
+        })
+    );
+}
+
+#[test]
+fn lookup_escapes_ansible_extra_vars() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "db", r#"o"brien\ops"#);
+
+    let assert = pw(&vault)
+        .args(["lookup", "db", "--format", "ansible-vars"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let vars: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        vars,
+        serde_json::json!({
+            "db_username": r#"o"brien\ops"#,
+            "db_password": password,
+        })
+    );
+}
+
+#[test]
+fn lookup_prints_quoted_tf_var_lines() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    let password = add_entry(&vault, "db", "o'brien");
+
     pw(&vault)
-        .arg("export")
+        .args(["lookup", "db", "--format", "tf-var", "--var", "prod_db"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success()
-        .stdout(contains(r#""url":"#).not());
+        .stdout(format!(
+            "export TF_VAR_prod_db_username='o'\\''brien'\n\
+             export TF_VAR_prod_db_password='{password}'\n"
+        ));
 }
 
 #[test]
-fn update_keep_password_changes_metadata_only() {
+fn lookup_rejects_invalid_variable_names() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
-    let password = add_entry(&vault, "foo", "user1");
+    add_entry(&vault, "db", "admin");
 
-    // Re-point the entry at a site and relabel it, keeping the password.
+    pw(&vault)
+        .args(["lookup", "db", "--format", "tf-var", "--var", "prod-db"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("not a valid variable name"));
+}
+
+#[test]
+fn open_launches_the_entry_url() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
     pw(&vault)
         .args([
-            "update",
-            "foo",
-            "user2",
+            "add",
+            "work",
+            "alice",
             "--url",
-            "github.com",
-            "--keep-password",
+            "github.com/login",
+            "--show",
         ])
         .write_stdin(PASSPHRASE)
         .assert()
-        .success()
-        .stdout(contains("password unchanged"));
+        .success();
 
-    // The username and url changed; the original password is intact.
+    // `echo` stands in for the system opener and prints what it was given.
     pw(&vault)
-        .args(["get", "foo", "--show"])
+        .args(["open", "work"])
+        .env("PW_OPENER", "echo")
         .write_stdin(PASSPHRASE)
         .assert()
         .success()
-        .stdout(format!("user2\n{password}\n"))
-        .stderr(contains("url: github.com"));
-}
-
-#[test]
-fn update_keep_password_rejects_input_password() {
-    let dir = TempDir::new().unwrap();
-    let vault = init_vault(&dir);
-    add_entry(&vault, "foo", "user1");
-    // --keep-password and --input-password contradict each other.
+        .stdout("https://github.com/login\n")
+        .stderr(contains("Warning: $PW_OPENER is set"));
     pw(&vault)
-        .args(["update", "foo", "--keep-password", "--input-password"])
+        .args(["open", "work", "--copy-password", "--copy-username"])
+        .env("PW_OPENER", "echo")
         .write_stdin(PASSPHRASE)
         .assert()
-        .failure();
+        .failure()
+        .stderr(contains("cannot be used with"));
 }
 
 #[test]
-fn backup_is_kept_after_rewrite() {
+fn launch_runs_the_entry_command_with_the_password() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
-    add_entry(&vault, "foo", "user1");
+    let assert = pw(&vault)
+        .args([
+            "add",
+            "db",
+            "alice",
+            "--url",
+            "https://db.example.com:8443/",
+            "--launch",
+            "echo {username}@{host}",
+            "--show",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    let password = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
 
-    let backup = dir.path().join("pw.scrypt.bak");
-    assert!(backup.exists());
+    pw(&vault)
+        .args(["launch", "db"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout("alice@db.example.com\n");
 
-    // The backup holds the previous generation: an empty vault.
-    pw(&backup)
-        .arg("list")
+    // Without {env}, the command gets no password.
+    pw(&vault)
+        .args(["launch", "db", "--set", "printenv PW_PASSWORD"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success()
-        .stdout(contains("(0 entries)"));
-}
+        .stdout("Set the launch command of 'db'.\n");
+    pw(&vault)
+        .args(["launch", "db"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stdout("");
 
-#[test]
-fn rejects_control_characters_in_name() {
-    let dir = TempDir::new().unwrap();
-    let vault = init_vault(&dir);
     pw(&vault)
-        .args(["add", "bad\x1bname", "user", "--show"])
+        .args(["launch", "db", "--set", "{env} printenv PW_PASSWORD"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["launch", "db"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(password);
+    // SSHPASS is only for sshpass.
+    pw(&vault)
+        .args(["launch", "db", "--set", "{env} printenv SSHPASS"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["launch", "db"])
         .write_stdin(PASSPHRASE)
         .assert()
         .failure()
-        .stderr(contains("invalid entry name"));
+        .stdout("");
+
+    pw(&vault)
+        .args(["launch", "db", "--unset"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
+    pw(&vault)
+        .args(["launch", "db"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("has no launch command"));
+    pw(&vault)
+        .args(["launch", "db", "--set", "ssh {password}@{host}"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("unknown placeholder {password}"));
 }
 
+#[cfg(unix)]
 #[test]
-fn generate_prints_password_of_requested_length() {
+fn ssh_connect_answers_the_password_prompt() {
+    use std::os::unix::fs::PermissionsExt;
     let dir = TempDir::new().unwrap();
-    let vault = dir.path().join("pw.scrypt"); // not created; generate is stateless
+    let vault = init_vault(&dir);
     let assert = pw(&vault)
-        .args(["generate", "--password-length", "32", "--show"])
+        .args(["add", "router", "admin", "--url", "ssh://router.lan:2222"])
+        .arg("--show")
+        .write_stdin(PASSPHRASE)
         .assert()
         .success();
-    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
-    assert_eq!(stdout.trim_end().chars().count(), 32);
-}
+    let password = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+
+    // A stand-in for ssh that prints its arguments and what $SSH_ASKPASS
+    // answers to a password prompt and to a host key question.
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let ssh = bin.join("ssh");
+    std::fs::write(
+        &ssh,
+        "#!/bin/sh\necho \"$@\"\n\"$SSH_ASKPASS\" \"admin@router.lan's password: \"\n\
+         \"$SSH_ASKPASS\" \"Are you sure you want to continue connecting (yes/no)? \" || echo refused\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&ssh, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
 
-#[test]
-fn generate_rejects_bad_charset() {
-    let dir = TempDir::new().unwrap();
-    let vault = dir.path().join("pw.scrypt");
     pw(&vault)
-        .args(["generate", "--password-charset", "aaa", "--show"])
+        .args(["ssh", "connect", "router", "--", "show", "version"])
+        .env("PATH", path)
+        .write_stdin(PASSPHRASE)
         .assert()
-        .failure()
-        .stderr(contains("invalid password charset"));
+        .success()
+        .stdout(format!(
+            "-p 2222 -l admin -- router.lan show version\n{password}refused\n"
+        ))
+        .stderr(contains("not answering ssh prompt"));
 }
 
 #[test]
-fn show_prints_attributes_without_password() {
+fn dsn_builds_connection_strings_from_the_url() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
     pw(&vault)
-        .args(["add", "work-github", "alice", "--url", "github.com", "--show"])
+        .args([
+            "add",
+            "db",
+            "app",
+            "--url",
+            "postgres://db.example.com:5433/app",
+            "--show",
+        ])
         .write_stdin(PASSPHRASE)
         .assert()
         .success();
 
     pw(&vault)
-        .args(["show", "work-github"])
+        .args(["dsn", "db", "--format", "postgres"])
         .write_stdin(PASSPHRASE)
         .assert()
         .success()
         .stdout(
-            contains("name: work-github")
-                .and(contains("username: alice"))
-                .and(contains("url: github.com")),
+            predicate::str::starts_with("postgresql://app:")
+                .and(predicate::str::ends_with("@db.example.com:5433/app\n")),
         );
+    pw(&vault)
+        .args(["dsn", "db", "--format", "jdbc", "--export", "DATABASE_URL"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "export DATABASE_URL='jdbc:postgresql://db.example.com:5433/app?user=app&password=",
+        ));
+    pw(&vault)
+        .args(["dsn", "db", "--format", "mysql", "--export", "1DB"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("not a valid variable name"));
+
+    add_entry(&vault, "bare", "alice");
+    pw(&vault)
+        .args(["dsn", "bare", "--format", "postgres"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("the entry has no url"));
 }
 
 #[test]
-fn show_omits_absent_username_and_url() {
+fn open_refuses_entries_without_a_web_url() {
     let dir = TempDir::new().unwrap();
     let vault = init_vault(&dir);
-    add_entry(&vault, "bare", "");
+    add_entry(&vault, "bare", "alice");
+    pw(&vault)
+        .args([
+            "add",
+            "local",
+            "alice",
+            "--url",
+            "file:///etc/passwd",
+            "--show",
+        ])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success();
 
     pw(&vault)
-        .args(["show", "bare"])
+        .args(["open", "bare"])
+        .env("PW_OPENER", "echo")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .failure()
+        .stderr(contains("has no url"));
+    pw(&vault)
+        .args(["open", "local"])
+        .env("PW_OPENER", "echo")
         .write_stdin(PASSPHRASE)
         .assert()
+        .failure()
+        .stdout("")
+        .stderr(contains("not a web address"));
+}
+
+#[test]
+fn examples_lists_topics_and_prints_one() {
+    let dir = TempDir::new().unwrap();
+    let vault = dir.path().join("pw.scrypt"); // not created; examples need no vault
+    pw(&vault)
+        .arg("examples")
+        .assert()
         .success()
-        .stdout(contains("name: bare").and(contains("username").not()).and(contains("url").not()));
+        .stdout(contains("scripting").and(contains("backup")));
+    pw(&vault)
+        .args(["examples", "scripting"])
+        .assert()
+        .success()
+        .stdout(contains("--passphrase-stdin"));
+    pw(&vault)
+        .args(["examples", "bogus"])
+        .assert()
+        .failure()
+        .stderr(contains("topics are: scripting"));
 }
 
 #[test]
-fn show_fails_for_unknown_entry() {
+fn system_conflicts_with_file() {
     let dir = TempDir::new().unwrap();
-    let vault = init_vault(&dir);
+    let vault = dir.path().join("pw.scrypt");
     pw(&vault)
-        .args(["show", "bogus"])
+        .args(["--system", "list"])
         .write_stdin(PASSPHRASE)
         .assert()
         .failure()
-        .stderr(contains("no entry 'bogus'"));
+        .stderr(contains("cannot be used with"));
 }
 
 #[cfg(unix)]
@@ -484,3 +3322,26 @@ fn vault_created_with_restrictive_permissions() {
     let mode = std::fs::metadata(&vault).unwrap().permissions().mode() & 0o777;
     assert_eq!(mode, 0o600);
 }
+
+#[test]
+fn names_are_normalized_and_listed_ignoring_case_and_accents() {
+    let dir = TempDir::new().unwrap();
+    let vault = init_vault(&dir);
+    add_entry(&vault, "zoo", "user1");
+    // "Ärzte" with a combining diaeresis (NFD)
+    add_entry(&vault, "A\u{308}rzte", "user2");
+    add_entry(&vault, "arzt", "user3");
+
+    pw(&vault)
+        .arg("list")
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("arzt: user3\n\u{c4}rzte: user2\nzoo: user1\n"));
+    pw(&vault)
+        .args(["show", "\u{c4}rzte"])
+        .write_stdin(PASSPHRASE)
+        .assert()
+        .success()
+        .stdout(contains("user2"));
+}
@@ -170,6 +170,18 @@ fn get_logins_forwards_environment_to_pinentry() {
             username: "alice".to_string(),
             password: "s3cret".into(),
             url: Some("example.com".to_string()),
+            url_match: Default::default(),
+            launch: None,
+            notes: None,
+            totp: None,
+            pinned: false,
+            tags: Vec::new(),
+            fields: Default::default(),
+            history: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            expires_at: None,
+            extra: Default::default(),
         },
         &PARAMS,
     )
@@ -1,15 +1,18 @@
 //! Interoperability between the native codec and the `scrypt` command-line
-//! tool
+//! tool, and between `pw export --format kdbx` and KeePassXC
 //!
 //! The known-answer tests run everywhere using a fixture generated once by
 //! scrypt 1.3.2 (see tests/data/). The live round-trip tests additionally
-//! require a real `scrypt` binary on PATH and are skipped with a notice if
-//! it is absent.
+//! require a real `scrypt` binary on PATH, and the KDBX test a
+//! `keepassxc-cli` binary; each is skipped with a notice if its tool is
+//! absent.
 
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use pw::scrypt_format::{self, Error, Params};
+use pw::{Passphrase, PasswordEntry};
 
 /// Must match how tests/data/known_answer.scrypt was generated:
 /// `scrypt enc --logN 12 -r 8 -p 1 --passphrase file:<passphrase-file>`
@@ -113,3 +116,64 @@ fn we_decrypt_scrypt_tool_output() {
     let plain = scrypt_format::decrypt(&data, PASSPHRASE).unwrap();
     assert_eq!(plain.as_slice(), PLAINTEXT);
 }
+
+fn keepassxc_cli_available() -> bool {
+    match Command::new("keepassxc-cli").arg("--version").output() {
+        Ok(output) => output.status.success(),
+        Err(_) => {
+            eprintln!("skipping live interop test: no `keepassxc-cli` binary on PATH");
+            false
+        }
+    }
+}
+
+#[test]
+fn keepassxc_reads_our_kdbx() {
+    if !keepassxc_cli_available() {
+        return;
+    }
+    let entries: Vec<PasswordEntry> = serde_json::from_value(serde_json::json!([
+        {
+            "name": "example.com",
+            "username": "alice",
+            "password": "p4ss,\"word\"",
+            "url": "https://example.com",
+            "notes": "line one\nline two",
+        },
+        {"name": "bank", "username": "bob", "password": "s3cret"},
+    ]))
+    .unwrap();
+    let kdbx_passphrase = "kdbx passphrase";
+    let data = pw::kdbx::write(
+        &entries,
+        &Passphrase::new(kdbx_passphrase.to_string()),
+        1000,
+    );
+    let tmp = assert_fs::TempDir::new().unwrap();
+    let kdbx_file = tmp.path().join("export.kdbx");
+    std::fs::write(&kdbx_file, data).unwrap();
+
+    let mut child = Command::new("keepassxc-cli")
+        .args(["export", "--format", "csv"])
+        .arg(&kdbx_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{kdbx_passphrase}").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "keepassxc-cli export failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let csv = String::from_utf8(output.stdout).unwrap();
+    // KeePassXC quotes every field and doubles embedded quotes.
+    for field in [
+        r#""example.com","alice","p4ss,""word""","https://example.com","line one"#,
+        r#""bank","bob","s3cret","""#,
+    ] {
+        assert!(csv.contains(field), "{field} not in {csv}");
+    }
+}